@@ -16,8 +16,117 @@ pub enum BallotPaperSize {
     Letter,
     #[serde(rename = "legal")]
     Legal,
+    #[serde(rename = "custom-8.5x17")]
+    Custom8Point5X17,
+    #[serde(rename = "custom-8.5x18")]
+    Custom8Point5X18,
+    #[serde(rename = "custom")]
+    Custom,
 }
 
+impl BallotPaperSize {
+    /// Finds the paper size closest to `size`, given in inches, or `Custom` if
+    /// it isn't a standard size.
+    pub fn from_inches(size: Size<f32>) -> Self {
+        [
+            (Self::Letter, 11.0),
+            (Self::Legal, 14.0),
+            (Self::Custom8Point5X17, 17.0),
+            (Self::Custom8Point5X18, 18.0),
+        ]
+        .into_iter()
+        .find(|(_, height)| {
+            (size.width - 8.5).abs() < PAPER_SIZE_TOLERANCE_INCHES
+                && (size.height - height).abs() < PAPER_SIZE_TOLERANCE_INCHES
+        })
+        .map_or(Self::Custom, |(paper_size, _)| paper_size)
+    }
+}
+
+/// How far the dimensions of a page may be from those of a standard paper
+/// size to be considered that size, in inches.
+const PAPER_SIZE_TOLERANCE_INCHES: f32 = 0.05;
+
+/// An edge of a ballot page.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BorderEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl BorderEdge {
+    /// The edge on the other side of the page.
+    pub const fn opposite(self) -> Self {
+        match self {
+            Self::Top => Self::Bottom,
+            Self::Bottom => Self::Top,
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+}
+
+/// Describes which edges of a ballot page have timing marks printed on them
+/// and which edge, if any, encodes the page metadata. Edges without timing
+/// marks are assumed to have evenly spaced grid lines between the corners.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TimingMarkLayout {
+    pub top: bool,
+    pub bottom: bool,
+    pub left: bool,
+    pub right: bool,
+
+    /// The edge whose timing marks encode the page metadata. The metadata bits
+    /// are read from the right end of a horizontal edge or the bottom end of a
+    /// vertical edge. If there is no metadata edge then the ballot card sides
+    /// cannot be told apart and are interpreted in the order given.
+    pub metadata_edge: Option<BorderEdge>,
+}
+
+impl Default for TimingMarkLayout {
+    fn default() -> Self {
+        Self::all_edges()
+    }
+}
+
+impl TimingMarkLayout {
+    /// Timing marks on all four edges with metadata along the bottom.
+    pub const fn all_edges() -> Self {
+        Self {
+            top: true,
+            bottom: true,
+            left: true,
+            right: true,
+            metadata_edge: Some(BorderEdge::Bottom),
+        }
+    }
+
+    /// Determines whether there are enough edges with timing marks to build a
+    /// grid, i.e. at least two opposite edges.
+    pub const fn can_form_grid(&self) -> bool {
+        (self.top && self.bottom) || (self.left && self.right)
+    }
+
+    /// Determines whether there are timing marks printed along `edge`.
+    pub const fn has_timing_marks(&self, edge: BorderEdge) -> bool {
+        match edge {
+            BorderEdge::Top => self.top,
+            BorderEdge::Bottom => self.bottom,
+            BorderEdge::Left => self.left,
+            BorderEdge::Right => self.right,
+        }
+    }
+}
+
+/// The resolution scans are resampled to before they are interpreted. The
+/// oval template and the distances in pixels used throughout interpretation
+/// are for this resolution.
+pub const CANONICAL_PIXELS_PER_INCH: u32 = 200;
+
 #[derive(Copy, Clone, Debug, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Geometry {
@@ -28,11 +137,129 @@ pub struct Geometry {
     pub oval_size: Size<u32>,
     pub timing_mark_size: Size<f32>,
     pub grid_size: Size<u32>,
+    pub timing_mark_layout: TimingMarkLayout,
     pub front_usable_area: Rect,
     pub back_usable_area: Rect,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The distance from each edge of the paper to the outer edge of the timing
+/// marks, in inches.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PaperMargins {
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+}
+
+/// A ballot geometry in physical units, so that it can be converted to a
+/// `Geometry` at the resolution of each scan.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalGeometry {
+    /// The size of the paper, in inches.
+    pub paper_size: Size<f32>,
+
+    #[serde(default)]
+    pub margins: PaperMargins,
+
+    /// The size of each timing mark, in inches.
+    pub timing_mark_size: Size<f32>,
+
+    /// The size of each oval, in inches.
+    pub oval_size: Size<f32>,
+
+    /// The number of timing mark columns (width) and rows (height).
+    pub grid_size: Size<u32>,
+
+    /// The grid positions that may contain targets on each side, in timing
+    /// mark columns and rows. Defaults to the whole grid.
+    pub front_usable_area: Option<Rect>,
+    pub back_usable_area: Option<Rect>,
+}
+
+impl PhysicalGeometry {
+    /// Converts the geometry to pixels at `pixels_per_inch`, with timing marks
+    /// printed as described by `timing_mark_layout`.
+    pub fn at_resolution(
+        &self,
+        pixels_per_inch: u32,
+        timing_mark_layout: TimingMarkLayout,
+    ) -> Geometry {
+        let scale = pixels_per_inch as f32;
+        let pixels = |inches: f32| (inches * scale).round() as u32;
+        let canvas_size = Size {
+            width: pixels(self.paper_size.width),
+            height: pixels(self.paper_size.height),
+        };
+        let (top, bottom, left, right) = (
+            pixels(self.margins.top),
+            pixels(self.margins.bottom),
+            pixels(self.margins.left),
+            pixels(self.margins.right),
+        );
+        let whole_grid = Rect::new(0, 0, self.grid_size.width, self.grid_size.height);
+
+        Geometry {
+            ballot_paper_size: BallotPaperSize::from_inches(self.paper_size),
+            pixels_per_inch,
+            canvas_size,
+            content_area: Rect::new(
+                left as i32,
+                top as i32,
+                canvas_size.width.saturating_sub(left + right),
+                canvas_size.height.saturating_sub(top + bottom),
+            ),
+            oval_size: Size {
+                width: pixels(self.oval_size.width),
+                height: pixels(self.oval_size.height),
+            },
+            timing_mark_size: Size {
+                width: self.timing_mark_size.width * scale,
+                height: self.timing_mark_size.height * scale,
+            },
+            grid_size: self.grid_size,
+            timing_mark_layout,
+            front_usable_area: self.front_usable_area.unwrap_or(whole_grid),
+            back_usable_area: self.back_usable_area.unwrap_or(whole_grid),
+        }
+    }
+
+    /// Converts the geometry to pixels for a scan of the given size, if its
+    /// aspect ratio is within `tolerance` of that of the paper. Like the
+    /// built-in geometries, the result is at `CANONICAL_PIXELS_PER_INCH`
+    /// whatever the resolution of the scan, which is resampled to match.
+    pub fn scanned_geometry(
+        &self,
+        size: (u32, u32),
+        tolerance: f32,
+        timing_mark_layout: TimingMarkLayout,
+    ) -> Option<Geometry> {
+        let (width, height) = size;
+        let aspect_ratio = width as f32 / height as f32;
+        let paper_aspect_ratio = self.paper_size.width / self.paper_size.height;
+        // a paper size of zero has no aspect ratio to match
+        if paper_aspect_ratio.is_nan() || (aspect_ratio - paper_aspect_ratio).abs() >= tolerance {
+            return None;
+        }
+
+        Some(self.at_resolution(CANONICAL_PIXELS_PER_INCH, timing_mark_layout))
+    }
+}
+
+impl Geometry {
+    /// The part of the grid that may contain targets on a side of the ballot
+    /// card, in timing mark columns and rows.
+    pub const fn usable_area(&self, side: BallotSide) -> Rect {
+        match side {
+            BallotSide::Front => self.front_usable_area,
+            BallotSide::Back => self.back_usable_area,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BallotSide {
     Front,
     Back,
@@ -95,6 +322,7 @@ pub const fn get_scanned_ballot_card_geometry_8pt5x11() -> Geometry {
             width: 34,
             height: 41,
         },
+        timing_mark_layout: TimingMarkLayout::all_edges(),
         front_usable_area: Rect::new(0, 0, 34, 41),
         back_usable_area: Rect::new(0, 0, 34, 41),
     }
@@ -121,28 +349,93 @@ pub const fn get_scanned_ballot_card_geometry_8pt5x14() -> Geometry {
             width: 34,
             height: 53,
         },
+        timing_mark_layout: TimingMarkLayout::all_edges(),
         front_usable_area: Rect::new(0, 0, 34, 53),
         back_usable_area: Rect::new(0, 0, 34, 53),
     }
 }
 
-pub fn get_scanned_ballot_card_geometry(size: (u32, u32)) -> Option<Geometry> {
+pub const fn get_scanned_ballot_card_geometry_8pt5x17() -> Geometry {
+    Geometry {
+        ballot_paper_size: BallotPaperSize::Custom8Point5X17,
+        pixels_per_inch: 200,
+        canvas_size: Size {
+            width: 1696,
+            height: 3400,
+        },
+        content_area: Rect::new(0, 0, 1696, 3400),
+        oval_size: Size {
+            width: 40,
+            height: 26,
+        },
+        timing_mark_size: Size {
+            width: 37.5,
+            height: 12.5,
+        },
+        grid_size: Size {
+            width: 34,
+            height: 65,
+        },
+        timing_mark_layout: TimingMarkLayout::all_edges(),
+        front_usable_area: Rect::new(0, 0, 34, 65),
+        back_usable_area: Rect::new(0, 0, 34, 65),
+    }
+}
+
+pub const fn get_scanned_ballot_card_geometry_8pt5x18() -> Geometry {
+    Geometry {
+        ballot_paper_size: BallotPaperSize::Custom8Point5X18,
+        pixels_per_inch: 200,
+        canvas_size: Size {
+            width: 1696,
+            height: 3600,
+        },
+        content_area: Rect::new(0, 0, 1696, 3600),
+        oval_size: Size {
+            width: 40,
+            height: 26,
+        },
+        timing_mark_size: Size {
+            width: 37.5,
+            height: 12.5,
+        },
+        grid_size: Size {
+            width: 34,
+            height: 69,
+        },
+        timing_mark_layout: TimingMarkLayout::all_edges(),
+        front_usable_area: Rect::new(0, 0, 34, 69),
+        back_usable_area: Rect::new(0, 0, 34, 69),
+    }
+}
+
+/// The geometries of the supported ballot paper sizes.
+pub const fn built_in_geometries() -> [Geometry; 4] {
+    [
+        get_scanned_ballot_card_geometry_8pt5x11(),
+        get_scanned_ballot_card_geometry_8pt5x14(),
+        get_scanned_ballot_card_geometry_8pt5x17(),
+        get_scanned_ballot_card_geometry_8pt5x18(),
+    ]
+}
+
+/// Finds the geometry of the portrait ballot paper size whose aspect ratio is
+/// within `tolerance` of that of a scan of the given size, preferring the
+/// closest.
+pub fn get_scanned_ballot_card_geometry(size: (u32, u32), tolerance: f32) -> Option<Geometry> {
     let (width, height) = size;
     let aspect_ratio = width as f32 / height as f32;
-    let letter_size = get_scanned_ballot_card_geometry_8pt5x11();
-    let letter_aspect_ratio =
-        letter_size.canvas_size.width as f32 / letter_size.canvas_size.height as f32;
-    let legal_size = get_scanned_ballot_card_geometry_8pt5x14();
-    let letgal_aspect_ratio =
-        legal_size.canvas_size.width as f32 / legal_size.canvas_size.height as f32;
 
-    if (aspect_ratio - letter_aspect_ratio).abs() < 0.01 {
-        Some(letter_size)
-    } else if (aspect_ratio - letgal_aspect_ratio).abs() < 0.01 {
-        Some(legal_size)
-    } else {
-        None
-    }
+    built_in_geometries()
+        .into_iter()
+        .map(|geometry| {
+            let paper_aspect_ratio =
+                geometry.canvas_size.width as f32 / geometry.canvas_size.height as f32;
+            (geometry, (aspect_ratio - paper_aspect_ratio).abs())
+        })
+        .filter(|(_, difference)| *difference < tolerance)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(geometry, _)| geometry)
 }
 
 #[time]
@@ -153,10 +446,13 @@ pub fn load_oval_template() -> Option<GrayImage> {
         Some(image) => image.to_luma8(),
         _ => return None,
     };
-    Some(bleed(
-        &threshold(&oval_scan_image, otsu_level(&oval_scan_image)),
-        Luma([0u8]),
-    ))
+    Some(binarize_target_scan(&oval_scan_image))
+}
+
+/// Binarizes a scan of a blank target for use as a template, thickening the
+/// printed lines slightly to tolerate small differences in print quality.
+pub fn binarize_target_scan(img: &GrayImage) -> GrayImage {
+    bleed(&threshold(img, otsu_level(img)), Luma([0u8]))
 }
 
 #[cfg(test)]
@@ -165,15 +461,217 @@ mod tests {
 
     #[test]
     fn test_get_scanned_ballot_card_geometry() {
+        let tolerance = 0.01;
         assert_eq!(
-            get_scanned_ballot_card_geometry((1696, 2200)),
+            get_scanned_ballot_card_geometry((1696, 2200), tolerance),
             Some(get_scanned_ballot_card_geometry_8pt5x11())
         );
         assert_eq!(
-            get_scanned_ballot_card_geometry((1696, 2800)),
+            get_scanned_ballot_card_geometry((1696, 2800), tolerance),
             Some(get_scanned_ballot_card_geometry_8pt5x14())
         );
-        assert_eq!(get_scanned_ballot_card_geometry((1500, 1500)), None);
+        assert_eq!(
+            get_scanned_ballot_card_geometry((1700, 3400), tolerance),
+            Some(get_scanned_ballot_card_geometry_8pt5x17())
+        );
+        assert_eq!(
+            get_scanned_ballot_card_geometry((1700, 3600), tolerance),
+            Some(get_scanned_ballot_card_geometry_8pt5x18())
+        );
+        assert_eq!(
+            get_scanned_ballot_card_geometry((1500, 1500), tolerance),
+            None
+        );
+        // landscape scans must be rotated first
+        assert_eq!(
+            get_scanned_ballot_card_geometry((2200, 1696), tolerance),
+            None
+        );
+    }
+
+    #[test]
+    fn test_get_scanned_ballot_card_geometry_tolerance() {
+        // a letter page scanned with a little extra at the bottom
+        let size = (1696, 2260);
+        assert_eq!(get_scanned_ballot_card_geometry(size, 0.01), None);
+        assert_eq!(
+            get_scanned_ballot_card_geometry(size, 0.03),
+            Some(get_scanned_ballot_card_geometry_8pt5x11())
+        );
+    }
+
+    fn physical_letter_geometry() -> PhysicalGeometry {
+        serde_json::from_str(
+            r#"{
+                "paperSize": { "width": 8.5, "height": 11 },
+                "timingMarkSize": { "width": 0.1875, "height": 0.0625 },
+                "ovalSize": { "width": 0.2, "height": 0.13 },
+                "gridSize": { "width": 34, "height": 41 }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_physical_geometry_at_resolution() {
+        let physical = physical_letter_geometry();
+        let geometry = physical.at_resolution(200, TimingMarkLayout::all_edges());
+        let letter = get_scanned_ballot_card_geometry_8pt5x11();
+        assert_eq!(geometry.ballot_paper_size, BallotPaperSize::Letter);
+        assert_eq!(geometry.pixels_per_inch, letter.pixels_per_inch);
+        assert_eq!(
+            geometry.canvas_size,
+            Size {
+                width: 1700,
+                height: 2200
+            }
+        );
+        assert_eq!(geometry.oval_size, letter.oval_size);
+        assert_eq!(geometry.timing_mark_size, letter.timing_mark_size);
+        assert_eq!(geometry.grid_size, letter.grid_size);
+        assert_eq!(geometry.timing_mark_layout, letter.timing_mark_layout);
+
+        let timing_mark_layout = TimingMarkLayout {
+            top: false,
+            bottom: false,
+            left: true,
+            right: true,
+            metadata_edge: Some(BorderEdge::Right),
+        };
+        let geometry = PhysicalGeometry {
+            margins: PaperMargins {
+                top: 0.5,
+                bottom: 0.25,
+                left: 0.25,
+                right: 0.25,
+            },
+            back_usable_area: Some(Rect::new(0, 0, 34, 20)),
+            ..physical
+        }
+        .at_resolution(300, timing_mark_layout);
+        assert_eq!(geometry.pixels_per_inch, 300);
+        assert_eq!(
+            geometry.canvas_size,
+            Size {
+                width: 2550,
+                height: 3300
+            }
+        );
+        assert_eq!(geometry.content_area, Rect::new(75, 150, 2400, 3075));
+        assert_eq!(
+            geometry.oval_size,
+            Size {
+                width: 60,
+                height: 39
+            }
+        );
+        assert_eq!(geometry.front_usable_area, Rect::new(0, 0, 34, 41));
+        assert_eq!(geometry.back_usable_area, Rect::new(0, 0, 34, 20));
+        assert_eq!(geometry.timing_mark_layout, timing_mark_layout);
+    }
+
+    #[test]
+    fn test_physical_geometry_scanned_geometry() {
+        let physical = physical_letter_geometry();
+        let timing_mark_layout = TimingMarkLayout::all_edges();
+        // scans are resampled to the canonical resolution
+        for size in [(1700, 2200), (2550, 3300)] {
+            assert_eq!(
+                physical.scanned_geometry(size, 0.01, timing_mark_layout),
+                Some(physical.at_resolution(CANONICAL_PIXELS_PER_INCH, timing_mark_layout))
+            );
+        }
+        assert_eq!(
+            physical.scanned_geometry((1700, 2800), 0.01, timing_mark_layout),
+            None
+        );
+
+        let zero_paper = PhysicalGeometry {
+            paper_size: Size {
+                width: 0.0,
+                height: 0.0,
+            },
+            ..physical
+        };
+        assert_eq!(
+            zero_paper.scanned_geometry((1700, 2200), 0.01, timing_mark_layout),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ballot_paper_size_from_inches() {
+        assert_eq!(
+            BallotPaperSize::from_inches(Size {
+                width: 8.5,
+                height: 17.0
+            }),
+            BallotPaperSize::Custom8Point5X17
+        );
+        assert_eq!(
+            BallotPaperSize::from_inches(Size {
+                width: 11.0,
+                height: 17.0
+            }),
+            BallotPaperSize::Custom
+        );
+    }
+
+    #[test]
+    fn test_timing_mark_layout_deserialize() {
+        assert_eq!(
+            serde_json::from_str::<TimingMarkLayout>("{}").unwrap(),
+            TimingMarkLayout::all_edges()
+        );
+        assert_eq!(
+            serde_json::from_str::<TimingMarkLayout>(
+                r#"{"top": false, "bottom": false, "metadataEdge": "left"}"#
+            )
+            .unwrap(),
+            TimingMarkLayout {
+                top: false,
+                bottom: false,
+                left: true,
+                right: true,
+                metadata_edge: Some(BorderEdge::Left),
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<TimingMarkLayout>(r#"{"metadataEdge": null}"#)
+                .unwrap()
+                .metadata_edge,
+            None
+        );
+    }
+
+    #[test]
+    fn test_timing_mark_layout_can_form_grid() {
+        assert!(TimingMarkLayout::all_edges().can_form_grid());
+        assert!(TimingMarkLayout {
+            top: false,
+            bottom: false,
+            ..TimingMarkLayout::all_edges()
+        }
+        .can_form_grid());
+        assert!(!TimingMarkLayout {
+            top: false,
+            right: false,
+            ..TimingMarkLayout::all_edges()
+        }
+        .can_form_grid());
+    }
+
+    #[test]
+    fn test_timing_mark_layout_has_timing_marks() {
+        let layout = TimingMarkLayout {
+            top: false,
+            bottom: false,
+            ..TimingMarkLayout::all_edges()
+        };
+        assert!(!layout.has_timing_marks(BorderEdge::Top));
+        assert!(!layout.has_timing_marks(BorderEdge::Bottom));
+        assert!(layout.has_timing_marks(BorderEdge::Left));
+        assert!(layout.has_timing_marks(BorderEdge::Right));
     }
 
     #[test]
@@ -0,0 +1,283 @@
+use std::{fmt::Display, str::FromStr};
+
+use image::GrayImage;
+use imageproc::{
+    contrast::{otsu_level, threshold},
+    integral_image::{integral_image, integral_squared_image, sum_image_pixels},
+};
+use serde::Serialize;
+
+use crate::image_utils::{BLACK, WHITE};
+
+/// The default window size for local thresholding. This is larger than an
+/// oval so that the window around a filled oval always includes some of the
+/// paper around it.
+pub const DEFAULT_WINDOW_SIZE: u32 = 51;
+
+pub const DEFAULT_NIBLACK_K: f32 = -0.2;
+pub const DEFAULT_SAUVOLA_K: f32 = 0.2;
+
+/// The dynamic range of the standard deviation used by Sauvola's method.
+const SAUVOLA_R: f64 = 128.0;
+
+/// How to separate ink from paper in a grayscale image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BinarizationMethod {
+    /// A single threshold for the whole image chosen using Otsu's method. This
+    /// works well for evenly lit scans with a white background.
+    #[default]
+    Otsu,
+
+    /// A threshold for each pixel of `mean + k * standard_deviation` over the
+    /// window around it.
+    #[serde(rename_all = "camelCase")]
+    Niblack { window_size: u32, k: f32 },
+
+    /// A threshold for each pixel of
+    /// `mean * (1 + k * (standard_deviation / R - 1))` over the window around
+    /// it. This copes with uneven illumination and shaded backgrounds without
+    /// Niblack's noise in blank areas.
+    #[serde(rename_all = "camelCase")]
+    Sauvola { window_size: u32, k: f32 },
+
+    /// Use the image as is. This is for scans that are already black and
+    /// white, such as bi-tonal TIFFs.
+    Bitonal,
+}
+
+impl Display for BinarizationMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Otsu => write!(f, "otsu"),
+            Self::Bitonal => write!(f, "bitonal"),
+            Self::Niblack { window_size, k } => write!(f, "niblack:{window_size}:{k}"),
+            Self::Sauvola { window_size, k } => write!(f, "sauvola:{window_size}:{k}"),
+        }
+    }
+}
+
+/// Parses a method in the form `otsu`, `bitonal`, `niblack[:WINDOW_SIZE[:K]]`,
+/// or `sauvola[:WINDOW_SIZE[:K]]`.
+impl FromStr for BinarizationMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default();
+        let window_size = match parts.next() {
+            Some(window_size) => match window_size.parse::<u32>() {
+                Ok(window_size) if window_size > 0 => window_size,
+                _ => return Err(format!("invalid window size: {window_size}")),
+            },
+            None => DEFAULT_WINDOW_SIZE,
+        };
+        let k = match parts.next() {
+            Some(k) => match k.parse::<f32>() {
+                Ok(k) if k.is_finite() => Some(k),
+                _ => return Err(format!("invalid k: {k}")),
+            },
+            None => None,
+        };
+
+        if parts.next().is_some() {
+            return Err(format!("invalid binarization method: {s}"));
+        }
+
+        match name {
+            "otsu" if s == "otsu" => Ok(Self::Otsu),
+            "bitonal" if s == "bitonal" => Ok(Self::Bitonal),
+            "niblack" => Ok(Self::Niblack {
+                window_size,
+                k: k.unwrap_or(DEFAULT_NIBLACK_K),
+            }),
+            "sauvola" => Ok(Self::Sauvola {
+                window_size,
+                k: k.unwrap_or(DEFAULT_SAUVOLA_K),
+            }),
+            _ => Err(format!("invalid binarization method: {s}")),
+        }
+    }
+}
+
+/// The binarization method to use for each stage of interpretation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinarizationOptions {
+    pub timing_marks: BinarizationMethod,
+    pub ovals: BinarizationMethod,
+    pub stray_marks: BinarizationMethod,
+}
+
+impl BinarizationOptions {
+    /// Options for images that are already black and white, which skip
+    /// binarization entirely.
+    pub const BITONAL: Self = Self {
+        timing_marks: BinarizationMethod::Bitonal,
+        ovals: BinarizationMethod::Bitonal,
+        stray_marks: BinarizationMethod::Bitonal,
+    };
+}
+
+/// Binarizes an image so that ink is black (0) and paper is white (255).
+pub fn binarize(img: &GrayImage, method: BinarizationMethod) -> GrayImage {
+    match method {
+        BinarizationMethod::Otsu => threshold(img, otsu_level(img)),
+        BinarizationMethod::Bitonal => img.clone(),
+        BinarizationMethod::Niblack { window_size, k } => {
+            binarize_locally(img, window_size, |mean, standard_deviation| {
+                f64::from(k).mul_add(standard_deviation, mean)
+            })
+        }
+        BinarizationMethod::Sauvola { window_size, k } => {
+            binarize_locally(img, window_size, |mean, standard_deviation| {
+                mean * f64::from(k).mul_add(standard_deviation / SAUVOLA_R - 1.0, 1.0)
+            })
+        }
+    }
+}
+
+/// Binarizes an image using a threshold computed for each pixel from the mean
+/// and standard deviation of the window centered on it. Windows are clipped
+/// to the image, and integral images keep the cost independent of the window
+/// size.
+fn binarize_locally(
+    img: &GrayImage,
+    window_size: u32,
+    compute_threshold: impl Fn(f64, f64) -> f64,
+) -> GrayImage {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return img.clone();
+    }
+
+    let sums = integral_image::<_, u64>(img);
+    let squared_sums = integral_squared_image::<_, u64>(img);
+    let radius = window_size / 2;
+
+    GrayImage::from_fn(width, height, |x, y| {
+        let left = x.saturating_sub(radius);
+        let top = y.saturating_sub(radius);
+        let right = (x + radius).min(width - 1);
+        let bottom = (y + radius).min(height - 1);
+        let count = f64::from((right - left + 1) * (bottom - top + 1));
+
+        let sum = sum_image_pixels(&sums, left, top, right, bottom)[0] as f64;
+        let squared_sum = sum_image_pixels(&squared_sums, left, top, right, bottom)[0] as f64;
+        let mean = sum / count;
+        let variance = (squared_sum / count - mean * mean).max(0.0);
+
+        let value = f64::from(img.get_pixel(x, y).0[0]);
+        if value < compute_threshold(mean, variance.sqrt()) {
+            BLACK
+        } else {
+            WHITE
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+    use imageproc::{drawing::draw_filled_rect_mut, rect::Rect as ImageprocRect};
+
+    use super::*;
+
+    /// Counts the pixels in `img` that are black after binarization within
+    /// `bounds`. Used to check how much of a mark survives binarization.
+    fn count_black_pixels(img: &GrayImage, bounds: (u32, u32, u32, u32)) -> u32 {
+        let (left, top, width, height) = bounds;
+        (top..top + height)
+            .flat_map(|y| (left..left + width).map(move |x| (x, y)))
+            .filter(|(x, y)| *img.get_pixel(*x, *y) == Luma([0]))
+            .count() as u32
+    }
+
+    /// Builds a page that goes from white on the left to mid-gray on the
+    /// right, as if lit unevenly, with a light pencil mark on each side.
+    fn gradient_page_with_light_marks() -> GrayImage {
+        let mut img = GrayImage::from_fn(400, 200, |x, _| Luma([255 - (x * 110 / 400) as u8]));
+        for left in [40, 320] {
+            let background = img.get_pixel(left, 100).0[0];
+            draw_filled_rect_mut(
+                &mut img,
+                ImageprocRect::at(left as i32, 90).of_size(30, 20),
+                Luma([background - 60]),
+            );
+        }
+        img
+    }
+
+    #[test]
+    fn test_parse_binarization_method() {
+        assert_eq!("otsu".parse(), Ok(BinarizationMethod::Otsu));
+        assert_eq!(
+            "sauvola".parse(),
+            Ok(BinarizationMethod::Sauvola {
+                window_size: DEFAULT_WINDOW_SIZE,
+                k: DEFAULT_SAUVOLA_K
+            })
+        );
+        assert_eq!(
+            "niblack:25:-0.3".parse(),
+            Ok(BinarizationMethod::Niblack {
+                window_size: 25,
+                k: -0.3
+            })
+        );
+        assert_eq!("bitonal".parse(), Ok(BinarizationMethod::Bitonal));
+        assert!("otsu:25".parse::<BinarizationMethod>().is_err());
+        assert!("sauvola:0".parse::<BinarizationMethod>().is_err());
+        assert!("sauvola:25:x".parse::<BinarizationMethod>().is_err());
+        assert!("bradley".parse::<BinarizationMethod>().is_err());
+
+        let method = BinarizationMethod::Sauvola {
+            window_size: 31,
+            k: 0.3,
+        };
+        assert_eq!(method.to_string().parse(), Ok(method));
+    }
+
+    #[test]
+    fn test_otsu_loses_light_marks_on_gradient() {
+        let img = gradient_page_with_light_marks();
+        let binarized = binarize(&img, BinarizationMethod::Otsu);
+
+        // the shaded side of the page is entirely black, so the mark there
+        // can't be told apart from the background
+        assert_eq!(count_black_pixels(&binarized, (280, 20, 20, 20)), 400);
+    }
+
+    #[test]
+    fn test_sauvola_finds_light_marks_on_gradient() {
+        let img = gradient_page_with_light_marks();
+        let binarized = binarize(&img, "sauvola".parse().unwrap());
+
+        // both marks are mostly black
+        assert!(count_black_pixels(&binarized, (40, 90, 30, 20)) > 500);
+        assert!(count_black_pixels(&binarized, (320, 90, 30, 20)) > 500);
+
+        // the background on both sides is white
+        assert_eq!(count_black_pixels(&binarized, (0, 0, 20, 20)), 0);
+        assert_eq!(count_black_pixels(&binarized, (380, 0, 20, 20)), 0);
+        assert_eq!(count_black_pixels(&binarized, (150, 150, 100, 40)), 0);
+    }
+
+    #[test]
+    fn test_niblack_finds_light_marks_on_gradient() {
+        let img = gradient_page_with_light_marks();
+        let binarized = binarize(&img, "niblack".parse().unwrap());
+
+        assert!(count_black_pixels(&binarized, (40, 90, 30, 20)) > 500);
+        assert!(count_black_pixels(&binarized, (320, 90, 30, 20)) > 500);
+    }
+
+    #[test]
+    fn test_binarize_empty_image() {
+        let img = GrayImage::new(0, 0);
+        assert_eq!(
+            binarize(&img, "sauvola".parse().unwrap()).dimensions(),
+            (0, 0)
+        );
+    }
+}
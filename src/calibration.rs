@@ -0,0 +1,295 @@
+use image::{
+    imageops::{resize, FilterType},
+    GrayImage,
+};
+use imageproc::contrast::threshold;
+use serde::Serialize;
+
+use crate::{
+    ballot_card::Geometry,
+    geometry::{center_of_rect, median, Rect, Segment, Size},
+    timing_marks::DEFAULT_MAXIMUM_SEARCH_DISTANCE,
+};
+
+/// The minimum number of timing mark-like shapes needed to calibrate. With
+/// fewer than this the measurements are not trustworthy and the expected
+/// geometry is used as-is.
+pub const MINIMUM_CALIBRATION_SAMPLE_SIZE: usize = 8;
+
+/// Scale deviations smaller than this are treated as measurement noise rather
+/// than a difference in how the ballot was printed.
+pub const CALIBRATION_SCALE_TOLERANCE: f32 = 0.05;
+
+/// Measurements of the timing marks on a scanned page. Ballots from different
+/// print vendors use slightly different timing mark sizes and spacing, so the
+/// expected geometry is adjusted per page to match what was actually printed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Calibration {
+    /// The measured size of a timing mark, i.e. the median size of the timing
+    /// mark-like shapes found along the edges of the page.
+    pub timing_mark_size: Size<f32>,
+
+    /// The measured distance between the centers of adjacent timing marks.
+    pub timing_mark_pitch: f32,
+
+    /// The ratio of the measured timing mark pitch to the expected pitch. The
+    /// oval size and search window are scaled by this amount.
+    pub scale: f32,
+
+    /// The maximum distance to search around each expected oval location.
+    pub maximum_search_distance: u32,
+
+    /// The number of shapes the measurements were taken from.
+    pub sample_size: usize,
+}
+
+impl Calibration {
+    /// Builds a calibration that leaves the expected geometry unchanged.
+    pub fn nominal(geometry: &Geometry) -> Self {
+        Self {
+            timing_mark_size: geometry.timing_mark_size,
+            timing_mark_pitch: expected_timing_mark_pitch(geometry),
+            scale: 1.0,
+            maximum_search_distance: DEFAULT_MAXIMUM_SEARCH_DISTANCE,
+            sample_size: 0,
+        }
+    }
+
+    /// Returns a copy of `geometry` adjusted to match this calibration.
+    pub fn apply(&self, geometry: &Geometry) -> Geometry {
+        Geometry {
+            timing_mark_size: self.timing_mark_size,
+            oval_size: Size {
+                width: (geometry.oval_size.width as f32 * self.scale).round() as u32,
+                height: (geometry.oval_size.height as f32 * self.scale).round() as u32,
+            },
+            ..*geometry
+        }
+    }
+
+    /// Scales a binarized oval template to match this calibration. The result
+    /// is binarized as well.
+    pub fn scale_oval_template(&self, oval_template: &GrayImage) -> GrayImage {
+        if self.scale == 1.0 {
+            return oval_template.clone();
+        }
+
+        let scaled = resize(
+            oval_template,
+            (oval_template.width() as f32 * self.scale).round() as u32,
+            (oval_template.height() as f32 * self.scale).round() as u32,
+            FilterType::Triangle,
+        );
+        threshold(&scaled, u8::MAX / 2)
+    }
+}
+
+/// Computes the expected distance between the centers of adjacent timing
+/// marks, assuming the marks span the width of the content area.
+pub fn expected_timing_mark_pitch(geometry: &Geometry) -> f32 {
+    (geometry.content_area.width() as f32 - geometry.timing_mark_size.width)
+        / (geometry.grid_size.width - 1) as f32
+}
+
+/// Measures the size and spacing of the timing marks from the rectangular
+/// shapes found in the image. Only shapes near the edges of the page and
+/// within a factor of two of the expected timing mark size are considered.
+pub fn calibrate_timing_marks(geometry: &Geometry, shapes: &[Rect]) -> Calibration {
+    let nominal = Calibration::nominal(geometry);
+    let edge_band_width = geometry.canvas_size.width as f32 / 10.0;
+    let edge_band_height = geometry.canvas_size.height as f32 / 10.0;
+
+    let samples = shapes
+        .iter()
+        .filter(|rect| {
+            let width_ratio = rect.width() as f32 / geometry.timing_mark_size.width;
+            let height_ratio = rect.height() as f32 / geometry.timing_mark_size.height;
+            (0.5..=2.0).contains(&width_ratio) && (0.5..=2.0).contains(&height_ratio)
+        })
+        .filter(|rect| {
+            let center = center_of_rect(rect);
+            center.x < edge_band_width
+                || center.x > geometry.canvas_size.width as f32 - edge_band_width
+                || center.y < edge_band_height
+                || center.y > geometry.canvas_size.height as f32 - edge_band_height
+        })
+        .copied()
+        .collect::<Vec<_>>();
+
+    if samples.len() < MINIMUM_CALIBRATION_SAMPLE_SIZE {
+        return nominal;
+    }
+
+    let nearest_neighbor_distances = samples
+        .iter()
+        .filter_map(|rect| {
+            samples
+                .iter()
+                .filter(|other| *other != rect)
+                .map(|other| Segment::new(center_of_rect(rect), center_of_rect(other)).length())
+                .min_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        })
+        .collect::<Vec<_>>();
+
+    let (width, height, pitch) = match (
+        median(samples.iter().map(|rect| rect.width() as f32).collect()),
+        median(samples.iter().map(|rect| rect.height() as f32).collect()),
+        median(nearest_neighbor_distances),
+    ) {
+        (Some(width), Some(height), Some(pitch)) => (width, height, pitch),
+        _ => return nominal,
+    };
+
+    let scale = pitch / nominal.timing_mark_pitch;
+    let scale = if (scale - 1.0).abs() < CALIBRATION_SCALE_TOLERANCE {
+        1.0
+    } else {
+        scale
+    };
+
+    Calibration {
+        timing_mark_size: Size { width, height },
+        timing_mark_pitch: pitch,
+        scale,
+        maximum_search_distance: ((DEFAULT_MAXIMUM_SEARCH_DISTANCE as f32 * scale).round() as u32)
+            .max(1),
+        sample_size: samples.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ballot_card::get_scanned_ballot_card_geometry_8pt5x11;
+
+    /// Builds a row of timing marks along the top of the page.
+    fn top_row(count: u32, pitch: f32, size: Size<u32>) -> Vec<Rect> {
+        (0..count)
+            .map(|i| {
+                Rect::new(
+                    (i as f32 * pitch).round() as i32,
+                    0,
+                    size.width,
+                    size.height,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_calibrate_nominal_timing_marks() {
+        let geometry = get_scanned_ballot_card_geometry_8pt5x11();
+        let pitch = expected_timing_mark_pitch(&geometry);
+        let shapes = top_row(
+            34,
+            pitch,
+            Size {
+                width: 38,
+                height: 13,
+            },
+        );
+
+        let calibration = calibrate_timing_marks(&geometry, &shapes);
+        assert_eq!(
+            calibration.timing_mark_size,
+            Size {
+                width: 38.0,
+                height: 13.0
+            }
+        );
+        assert_eq!(calibration.scale, 1.0);
+        assert_eq!(
+            calibration.maximum_search_distance,
+            DEFAULT_MAXIMUM_SEARCH_DISTANCE
+        );
+        assert_eq!(calibration.sample_size, 34);
+    }
+
+    #[test]
+    fn test_calibrate_larger_timing_marks() {
+        let geometry = get_scanned_ballot_card_geometry_8pt5x11();
+        let pitch = expected_timing_mark_pitch(&geometry) * 1.3;
+        let shapes = top_row(
+            26,
+            pitch,
+            Size {
+                width: 45,
+                height: 16,
+            },
+        );
+
+        let calibration = calibrate_timing_marks(&geometry, &shapes);
+        assert_eq!(
+            calibration.timing_mark_size,
+            Size {
+                width: 45.0,
+                height: 16.0
+            }
+        );
+        assert!((calibration.scale - 1.3).abs() < 0.02);
+        assert_eq!(calibration.maximum_search_distance, 9);
+
+        let calibrated_geometry = calibration.apply(&geometry);
+        assert_eq!(
+            calibrated_geometry.oval_size,
+            Size {
+                width: 52,
+                height: 34
+            }
+        );
+        assert_eq!(calibrated_geometry.grid_size, geometry.grid_size);
+
+        let oval_template = GrayImage::new(40, 26);
+        assert_eq!(
+            calibration.scale_oval_template(&oval_template).dimensions(),
+            (52, 34)
+        );
+    }
+
+    #[test]
+    fn test_calibrate_ignores_interior_and_odd_shapes() {
+        let geometry = get_scanned_ballot_card_geometry_8pt5x11();
+        let pitch = expected_timing_mark_pitch(&geometry);
+        let mut shapes = top_row(
+            34,
+            pitch,
+            Size {
+                width: 38,
+                height: 13,
+            },
+        );
+        // shapes in the middle of the page
+        shapes.extend((0..50).map(|i| Rect::new(800, 1000 + i * 15, 30, 15)));
+        // shapes that are too big to be timing marks
+        shapes.extend((0..50).map(|i| Rect::new(i * 30, 2150, 200, 40)));
+
+        let calibration = calibrate_timing_marks(&geometry, &shapes);
+        assert_eq!(
+            calibration.timing_mark_size,
+            Size {
+                width: 38.0,
+                height: 13.0
+            }
+        );
+        assert_eq!(calibration.sample_size, 34);
+    }
+
+    #[test]
+    fn test_calibrate_too_few_samples() {
+        let geometry = get_scanned_ballot_card_geometry_8pt5x11();
+        let shapes = top_row(
+            3,
+            80.0,
+            Size {
+                width: 50,
+                height: 20,
+            },
+        );
+
+        assert_eq!(
+            calibrate_timing_marks(&geometry, &shapes),
+            Calibration::nominal(&geometry)
+        );
+    }
+}
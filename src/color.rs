@@ -0,0 +1,356 @@
+use std::{fmt::Display, str::FromStr};
+
+use image::{GrayImage, Luma, Pixel, Rgb, RgbImage};
+use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
+use serde::Serialize;
+
+use crate::{
+    calibration::Calibration,
+    image_utils::WHITE,
+    mark_features::{paper_luma, INK_MINIMUM_CONTRAST},
+    target_templates::TargetTemplates,
+    timing_marks::{OvalMark, ScoredOvalMarks},
+};
+
+/// Colors with less saturation than this are shades of gray.
+const MINIMUM_SATURATION: f32 = 0.25;
+
+/// Unsaturated ink darker than this is black, and lighter is gray.
+const MAXIMUM_BLACK_VALUE: u8 = 100;
+
+/// The minimum number of ink pixels in an oval needed to report its color.
+const MINIMUM_INK_PIXELS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ColorChannel {
+    Red,
+    Green,
+    Blue,
+}
+
+impl ColorChannel {
+    const fn index(self) -> usize {
+        match self {
+            Self::Red => 0,
+            Self::Green => 1,
+            Self::Blue => 2,
+        }
+    }
+}
+
+impl Display for ColorChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Red => write!(f, "red"),
+            Self::Green => write!(f, "green"),
+            Self::Blue => write!(f, "blue"),
+        }
+    }
+}
+
+impl FromStr for ColorChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "red" => Ok(Self::Red),
+            "green" => Ok(Self::Green),
+            "blue" => Ok(Self::Blue),
+            _ => Err(format!("invalid color channel: {s}")),
+        }
+    }
+}
+
+/// How to convert a color scan to grayscale for interpretation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GrayscaleConversion {
+    /// The perceived brightness of each pixel.
+    #[default]
+    Luma,
+
+    /// A single color channel. Ink of the same color as the channel is as
+    /// bright as the paper in it, so e.g. the red channel drops out red ink
+    /// while keeping pencil marks, whose gray is dark in every channel.
+    #[serde(rename_all = "camelCase")]
+    Channel { channel: ColorChannel },
+
+    /// The perceived brightness of each pixel, except that saturated pixels of
+    /// the given color are replaced with white. This keeps the contrast of
+    /// other colors of ink, unlike converting using a single channel.
+    #[serde(rename_all = "camelCase")]
+    DropOut { color: ColorChannel },
+}
+
+impl Display for GrayscaleConversion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Luma => write!(f, "luma"),
+            Self::Channel { channel } => write!(f, "channel:{channel}"),
+            Self::DropOut { color } => write!(f, "dropout:{color}"),
+        }
+    }
+}
+
+/// Parses a conversion in the form `luma`, `channel:COLOR`, or
+/// `dropout:COLOR`, where `COLOR` is `red`, `green`, or `blue`.
+impl FromStr for GrayscaleConversion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "luma" => Ok(Self::Luma),
+            Some(("channel", channel)) => Ok(Self::Channel {
+                channel: channel.parse()?,
+            }),
+            Some(("dropout", color)) => Ok(Self::DropOut {
+                color: color.parse()?,
+            }),
+            _ => Err(format!("invalid grayscale conversion: {s}")),
+        }
+    }
+}
+
+/// The color of ink, as seen by the scanner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InkColor {
+    Black,
+    Gray,
+    Red,
+    Green,
+    Blue,
+    Other,
+}
+
+/// The color of the ink in an oval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkInkColor {
+    pub color: InkColor,
+
+    /// The average color of the ink pixels.
+    pub rgb: [u8; 3],
+}
+
+/// Converts a color scan to grayscale.
+pub fn to_grayscale(img: &RgbImage, conversion: GrayscaleConversion) -> GrayImage {
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let pixel = img.get_pixel(x, y);
+        match conversion {
+            GrayscaleConversion::Luma => pixel.to_luma(),
+            GrayscaleConversion::Channel { channel } => Luma([pixel.0[channel.index()]]),
+            GrayscaleConversion::DropOut { color } => {
+                if classify_ink_color(pixel) == InkColor::from(color) {
+                    WHITE
+                } else {
+                    pixel.to_luma()
+                }
+            }
+        }
+    })
+}
+
+impl From<ColorChannel> for InkColor {
+    fn from(channel: ColorChannel) -> Self {
+        match channel {
+            ColorChannel::Red => Self::Red,
+            ColorChannel::Green => Self::Green,
+            ColorChannel::Blue => Self::Blue,
+        }
+    }
+}
+
+/// Names the color of a pixel by its hue, or by its brightness if it has
+/// little color.
+pub fn classify_ink_color(pixel: &Rgb<u8>) -> InkColor {
+    let [red, green, blue] = pixel.0;
+    let max = red.max(green).max(blue);
+    let min = red.min(green).min(blue);
+
+    if max == 0 || f32::from(max - min) / f32::from(max) < MINIMUM_SATURATION {
+        return if max <= MAXIMUM_BLACK_VALUE {
+            InkColor::Black
+        } else {
+            InkColor::Gray
+        };
+    }
+
+    let [red, green, blue] = [red, green, blue].map(f32::from);
+    let chroma = f32::from(max - min);
+    let hue = if max == pixel.0[0] {
+        60.0 * ((green - blue) / chroma).rem_euclid(6.0)
+    } else if max == pixel.0[1] {
+        60.0 * ((blue - red) / chroma + 2.0)
+    } else {
+        60.0 * ((red - green) / chroma + 4.0)
+    };
+
+    match hue {
+        hue if !(20.0..330.0).contains(&hue) => InkColor::Red,
+        hue if (80.0..170.0).contains(&hue) => InkColor::Green,
+        hue if (190.0..260.0).contains(&hue) => InkColor::Blue,
+        _ => InkColor::Other,
+    }
+}
+
+/// Finds the color of the ink in `img`, a color scan of an oval, ignoring the
+/// printed outline where `template` is black.
+pub fn detect_ink_color(img: &RgbImage, template: &GrayImage) -> Option<MarkInkColor> {
+    let gray = image::imageops::grayscale(img);
+    let paper = paper_luma(&gray);
+
+    let ink_pixels = img
+        .enumerate_pixels()
+        .filter(|(x, y, _)| {
+            paper.saturating_sub(gray.get_pixel(*x, *y).0[0]) >= INK_MINIMUM_CONTRAST
+                && template
+                    .get_pixel_checked(*x, *y)
+                    .is_some_and(|template_pixel| *template_pixel == WHITE)
+        })
+        .map(|(_, _, pixel)| pixel)
+        .collect::<Vec<_>>();
+
+    if ink_pixels.len() < MINIMUM_INK_PIXELS {
+        return None;
+    }
+
+    let mut sums = [0u64; 3];
+    for pixel in &ink_pixels {
+        for (sum, channel) in sums.iter_mut().zip(pixel.0) {
+            *sum += u64::from(channel);
+        }
+    }
+    let rgb = sums.map(|sum| (sum / ink_pixels.len() as u64) as u8);
+
+    Some(MarkInkColor {
+        color: classify_ink_color(&Rgb(rgb)),
+        rgb,
+    })
+}
+
+/// Detects the ink color of each scored oval mark from the color scan of its
+/// page, storing the result in each mark's `ink_color`.
+pub fn detect_oval_mark_ink_colors(
+    scored_oval_marks: &mut ScoredOvalMarks,
+    color_image: &RgbImage,
+    target_templates: &TargetTemplates,
+    calibration: &Calibration,
+) {
+    let target_templates =
+        &target_templates.map(|template| calibration.scale_oval_template(template));
+
+    scored_oval_marks
+        .par_iter_mut()
+        .for_each(|(grid_position, oval_mark)| {
+            if let OvalMark::Scored(scored_oval_mark) = oval_mark {
+                let bounds = scored_oval_mark.matched_bounds;
+                let oval_image = image::imageops::crop_imm(
+                    color_image,
+                    bounds.left().max(0) as u32,
+                    bounds.top().max(0) as u32,
+                    bounds.width(),
+                    bounds.height(),
+                )
+                .to_image();
+                scored_oval_mark.ink_color =
+                    detect_ink_color(&oval_image, target_templates.template_for(grid_position));
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use imageproc::drawing::{draw_filled_ellipse_mut, draw_hollow_ellipse_mut};
+
+    use super::*;
+    use crate::image_utils::BLACK;
+
+    const PAPER: Rgb<u8> = Rgb([250, 248, 245]);
+    const RED_INK: Rgb<u8> = Rgb([220, 60, 70]);
+    const BLUE_INK: Rgb<u8> = Rgb([30, 50, 160]);
+    const PENCIL: Rgb<u8> = Rgb([70, 70, 75]);
+
+    /// A red drop-out oval, optionally marked with the given ink.
+    fn color_oval(mark: Option<Rgb<u8>>) -> RgbImage {
+        let mut img = RgbImage::from_pixel(40, 26, PAPER);
+        draw_hollow_ellipse_mut(&mut img, (20, 13), 18, 11, RED_INK);
+        if let Some(mark) = mark {
+            draw_filled_ellipse_mut(&mut img, (20, 13), 14, 8, mark);
+        }
+        img
+    }
+
+    fn oval_template() -> GrayImage {
+        let mut template = GrayImage::from_pixel(40, 26, WHITE);
+        draw_hollow_ellipse_mut(&mut template, (20, 13), 18, 11, BLACK);
+        template
+    }
+
+    #[test]
+    fn test_parse_grayscale_conversion() {
+        assert_eq!("luma".parse(), Ok(GrayscaleConversion::Luma));
+        assert_eq!(
+            "channel:red".parse(),
+            Ok(GrayscaleConversion::Channel {
+                channel: ColorChannel::Red
+            })
+        );
+        let conversion = GrayscaleConversion::DropOut {
+            color: ColorChannel::Blue,
+        };
+        assert_eq!("dropout:blue".parse(), Ok(conversion));
+        assert_eq!(conversion.to_string().parse(), Ok(conversion));
+        assert!("channel:purple".parse::<GrayscaleConversion>().is_err());
+        assert!("luma:red".parse::<GrayscaleConversion>().is_err());
+    }
+
+    #[test]
+    fn test_classify_ink_color() {
+        assert_eq!(classify_ink_color(&RED_INK), InkColor::Red);
+        assert_eq!(classify_ink_color(&BLUE_INK), InkColor::Blue);
+        assert_eq!(classify_ink_color(&Rgb([40, 140, 60])), InkColor::Green);
+        assert_eq!(classify_ink_color(&PENCIL), InkColor::Black);
+        assert_eq!(classify_ink_color(&Rgb([160, 160, 160])), InkColor::Gray);
+        assert_eq!(classify_ink_color(&Rgb([200, 200, 40])), InkColor::Other);
+    }
+
+    #[test]
+    fn test_red_drop_out() {
+        let img = color_oval(None);
+        let outline = (2, 13);
+
+        // the luma of red ink is dark enough to be mistaken for a mark
+        let gray = to_grayscale(&img, GrayscaleConversion::Luma);
+        assert!(gray.get_pixel(outline.0, outline.1).0[0] < 110);
+
+        for conversion in ["channel:red", "dropout:red"] {
+            let gray = to_grayscale(&img, conversion.parse().unwrap());
+            assert!(
+                gray.get_pixel(outline.0, outline.1).0[0] > 200,
+                "{conversion}"
+            );
+        }
+
+        // a pencil mark stays dark either way
+        let img = color_oval(Some(PENCIL));
+        for conversion in ["luma", "channel:red", "dropout:red"] {
+            let gray = to_grayscale(&img, conversion.parse().unwrap());
+            assert!(gray.get_pixel(20, 13).0[0] < 80, "{conversion}");
+        }
+    }
+
+    #[test]
+    fn test_detect_ink_color() {
+        let template = oval_template();
+        assert_eq!(detect_ink_color(&color_oval(None), &template), None);
+
+        let blue = detect_ink_color(&color_oval(Some(BLUE_INK)), &template).unwrap();
+        assert_eq!(blue.color, InkColor::Blue);
+        assert_eq!(blue.rgb, BLUE_INK.0);
+
+        let pencil = detect_ink_color(&color_oval(Some(PENCIL)), &template).unwrap();
+        assert_eq!(pencil.color, InkColor::Black);
+    }
+}
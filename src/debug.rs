@@ -10,13 +10,13 @@ use rusttype::{Font, Scale};
 
 use crate::{
     ballot_card::Geometry,
-    election::GridPosition,
     geometry::{segment_with_length, Rect, Segment},
     image_utils::{
-        BLUE, CYAN, DARK_BLUE, DARK_CYAN, DARK_GREEN, DARK_RED, GREEN, PINK, RAINBOW, RED,
+        BLUE, CYAN, DARK_BLUE, DARK_CYAN, DARK_GREEN, DARK_RED, GREEN, ORANGE, PINK, RAINBOW, RED,
         WHITE_RGB,
     },
-    timing_marks::{Partial, ScoredOvalMark, TimingMarkGrid},
+    stray_marks::StrayMark,
+    timing_marks::{OvalMark, Partial, ScoredOvalMarks, TimingMarkGrid},
 };
 
 /// Draws a debug image of the rectangles found using the contour algorithm.
@@ -92,33 +92,33 @@ pub fn draw_timing_mark_debug_image_mut(
         CYAN,
     );
 
-    for rect in &partial_timing_marks.top_rects {
-        draw_filled_rect_mut(canvas, (*rect).into(), GREEN);
+    for timing_mark in &partial_timing_marks.top_marks {
+        draw_filled_rect_mut(canvas, timing_mark.rect.into(), GREEN);
     }
-    for rect in &partial_timing_marks.bottom_rects {
-        draw_filled_rect_mut(canvas, (*rect).into(), BLUE);
+    for timing_mark in &partial_timing_marks.bottom_marks {
+        draw_filled_rect_mut(canvas, timing_mark.rect.into(), BLUE);
     }
-    for rect in &partial_timing_marks.left_rects {
-        draw_filled_rect_mut(canvas, (*rect).into(), RED);
+    for timing_mark in &partial_timing_marks.left_marks {
+        draw_filled_rect_mut(canvas, timing_mark.rect.into(), RED);
     }
-    for rect in &partial_timing_marks.right_rects {
-        draw_filled_rect_mut(canvas, (*rect).into(), CYAN);
+    for timing_mark in &partial_timing_marks.right_marks {
+        draw_filled_rect_mut(canvas, timing_mark.rect.into(), CYAN);
     }
 
-    if let Some(top_left_corner) = partial_timing_marks.top_left_rect {
-        draw_filled_rect_mut(canvas, top_left_corner.into(), PINK);
+    if let Some(top_left_corner) = partial_timing_marks.top_left_mark {
+        draw_filled_rect_mut(canvas, top_left_corner.rect.into(), PINK);
     }
 
-    if let Some(top_right_corner) = partial_timing_marks.top_right_rect {
-        draw_filled_rect_mut(canvas, top_right_corner.into(), PINK);
+    if let Some(top_right_corner) = partial_timing_marks.top_right_mark {
+        draw_filled_rect_mut(canvas, top_right_corner.rect.into(), PINK);
     }
 
-    if let Some(bottom_left_corner) = partial_timing_marks.bottom_left_rect {
-        draw_filled_rect_mut(canvas, bottom_left_corner.into(), PINK);
+    if let Some(bottom_left_corner) = partial_timing_marks.bottom_left_mark {
+        draw_filled_rect_mut(canvas, bottom_left_corner.rect.into(), PINK);
     }
 
-    if let Some(bottom_right_corner) = partial_timing_marks.bottom_right_rect {
-        draw_filled_rect_mut(canvas, bottom_right_corner.into(), PINK);
+    if let Some(bottom_right_corner) = partial_timing_marks.bottom_right_mark {
+        draw_filled_rect_mut(canvas, bottom_right_corner.rect.into(), PINK);
     }
 
     draw_cross_mut(
@@ -250,7 +250,9 @@ pub fn draw_timing_mark_grid_debug_image_mut(
 ) {
     for column in 0..geometry.grid_size.width {
         for row in 0..geometry.grid_size.height {
-            let point = timing_mark_grid.point_for_location(column, row).expect("grid point is defined");
+            let point = timing_mark_grid
+                .point_for_location(column, row)
+                .expect("grid point is defined");
             draw_cross_mut(canvas, PINK, point.x.round() as i32, point.y.round() as i32);
         }
     }
@@ -263,18 +265,26 @@ fn monospace_font() -> Font<'static> {
 /// Draws a debug image outlining all the scored oval marks.
 pub fn draw_scored_oval_marks_debug_image_mut(
     canvas: &mut RgbImage,
-    scored_oval_marks: &Vec<(GridPosition, Option<ScoredOvalMark>)>,
+    scored_oval_marks: &ScoredOvalMarks,
 ) {
     let option_color = PINK;
     let matched_oval_color = DARK_GREEN;
     let original_oval_color = DARK_BLUE;
+    let out_of_bounds_color = RED;
     let score_color = DARK_GREEN;
     let font = &monospace_font();
     let font_scale = 20.0;
     let scale = Scale::uniform(font_scale);
 
-    for (grid_position, scored_oval_mark) in scored_oval_marks {
-        if let Some(scored_oval_mark) = scored_oval_mark {
+    for (grid_position, oval_mark) in scored_oval_marks {
+        if let OvalMark::OutOfBounds {
+            expected_bounds, ..
+        } = oval_mark
+        {
+            draw_hollow_rect_mut(canvas, (*expected_bounds).into(), out_of_bounds_color);
+        }
+
+        if let Some(scored_oval_mark) = oval_mark.scored() {
             let mut option_text = grid_position.to_string();
             option_text.truncate(25);
 
@@ -282,6 +292,12 @@ pub fn draw_scored_oval_marks_debug_image_mut(
                 text_size(scale, font, option_text.as_str());
 
             let score_text = scored_oval_mark.fill_score.to_string();
+            let score_color = match scored_oval_mark.status {
+                _ if scored_oval_mark.anomaly.is_some() => ORANGE,
+                Some(status) if status.needs_review() => ORANGE,
+                Some(status) if !status.is_marked() => DARK_BLUE,
+                _ => score_color,
+            };
             let (score_text_width, _) = text_size(scale, font, score_text.as_str());
 
             draw_text_with_background_mut(
@@ -347,6 +363,38 @@ pub fn draw_scored_oval_marks_debug_image_mut(
     }
 }
 
+/// Draws a debug image outlining the area searched for stray marks and the
+/// stray marks found there, labeled with the nearest grid position.
+pub fn draw_stray_marks_debug_image_mut(
+    canvas: &mut RgbImage,
+    search_area: &Rect,
+    stray_marks: &[StrayMark],
+) {
+    let font = &monospace_font();
+    let scale = Scale::uniform(20.0);
+
+    draw_hollow_rect_mut(canvas, (*search_area).into(), DARK_CYAN);
+
+    for stray_mark in stray_marks {
+        draw_hollow_rect_mut(canvas, stray_mark.bounds.into(), ORANGE);
+
+        if let Some(grid_position) = &stray_mark.nearest_grid_position {
+            let mut text = grid_position.to_string();
+            text.truncate(25);
+            draw_text_with_background_mut(
+                canvas,
+                &text,
+                stray_mark.bounds.left(),
+                stray_mark.bounds.bottom() + 5,
+                scale,
+                font,
+                ORANGE,
+                WHITE_RGB,
+            );
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn draw_text_with_background_mut(
     canvas: &mut RgbImage,
@@ -0,0 +1,486 @@
+//! Reproducible degradations of ballot images, like the ones scanners and
+//! voters introduce, for testing that interpretation is robust to them.
+
+use std::io::Cursor;
+
+use image::{codecs::jpeg::JpegEncoder, GrayImage, ImageFormat, Luma};
+use imageproc::{
+    drawing::{draw_filled_rect_mut, draw_polygon_mut},
+    filter::gaussian_blur_f32,
+    geometric_transformations::{warp, warp_with, Interpolation, Projection},
+    point::Point as ImageprocPoint,
+    rect::Rect as ImageprocRect,
+};
+
+use crate::{ballot_card::BallotSide, image_utils::WHITE, synthetic_ballot::SyntheticMark};
+
+/// A small deterministic pseudo-random number generator (SplitMix64), so that
+/// degradations generated from a seed are the same on every platform and
+/// with every version of the dependencies.
+#[derive(Debug, Clone)]
+pub struct SeededRng(u64);
+
+impl SeededRng {
+    pub const fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0.0..1.0`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// A number in `min..max`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        self.next_f32().mul_add(max - min, min)
+    }
+
+    /// Whether an event with the given probability happens.
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+}
+
+/// A corner of a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A way a ballot image can be degraded. Each one is fully described by its
+/// parameters, so applying it again gives the same image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Degradation {
+    /// Skew, rotating the page about its center.
+    Rotation { degrees: f32 },
+
+    /// Scaling about the center of the page, keeping the image size. Different
+    /// horizontal and vertical factors stretch the page in one direction.
+    Scale { horizontal: f32, vertical: f32 },
+
+    /// A vertical stretch that changes down the page, as when the scanner
+    /// feeds the paper at an uneven speed. The page is stretched by `top` at
+    /// its top edge and by `bottom` at its bottom edge.
+    FeedStretch { top: f32, bottom: f32 },
+
+    /// Gaussian blur, e.g. from a scan that is out of focus.
+    Blur { sigma: f32 },
+
+    /// Compression artifacts from saving as a JPEG of the given quality.
+    JpegArtifacts { quality: u8 },
+
+    /// Uniform noise of up to `amplitude` added to every pixel.
+    Noise { amplitude: u8, seed: u64 },
+
+    /// A vertical line down the whole page, e.g. from dust on the scanner
+    /// glass.
+    Streak { x: u32, width: u32, luma: Luma<u8> },
+
+    /// A triangle torn off a corner, showing the scanner background.
+    TornCorner {
+        corner: Corner,
+        size: u32,
+        luma: Luma<u8>,
+    },
+
+    /// Voter marks made with lighter ink. Applies to the marks drawn on a
+    /// synthetic ballot rather than to the image.
+    FaintMarks { luma: Luma<u8> },
+}
+
+/// Warps an image by `projection` about its center, filling in with white.
+fn warp_about_center(img: &GrayImage, projection: Projection) -> GrayImage {
+    let (center_x, center_y) = (img.width() as f32 / 2.0, img.height() as f32 / 2.0);
+    warp(
+        img,
+        &(Projection::translate(center_x, center_y)
+            * projection
+            * Projection::translate(-center_x, -center_y)),
+        Interpolation::Bilinear,
+        WHITE,
+    )
+}
+
+/// Finds the row of the original image that ends up at `y` after a feed
+/// stretch, by inverting `y = top * y0 + (bottom - top) * y0^2 / (2 * height)`.
+fn feed_stretch_source_y(y: f32, top: f32, bottom: f32, height: f32) -> f32 {
+    let a = (bottom - top) / (2.0 * height);
+    if a.abs() < f32::EPSILON {
+        return y / top;
+    }
+    (-top + top.mul_add(top, 4.0 * a * y).sqrt()) / (2.0 * a)
+}
+
+impl Degradation {
+    /// Applies the degradation to a page image.
+    pub fn apply(&self, img: &GrayImage) -> GrayImage {
+        match *self {
+            Self::Rotation { degrees } => {
+                warp_about_center(img, Projection::rotate(degrees.to_radians()))
+            }
+            Self::Scale {
+                horizontal,
+                vertical,
+            } => warp_about_center(img, Projection::scale(horizontal, vertical)),
+            Self::FeedStretch { top, bottom } => {
+                let height = img.height() as f32;
+                warp_with(
+                    img,
+                    move |x, y| (x, feed_stretch_source_y(y, top, bottom, height)),
+                    Interpolation::Bilinear,
+                    WHITE,
+                )
+            }
+            Self::Blur { sigma } => gaussian_blur_f32(img, sigma),
+            Self::JpegArtifacts { quality } => {
+                let mut jpeg = Vec::new();
+                JpegEncoder::new_with_quality(&mut jpeg, quality)
+                    .encode_image(img)
+                    .expect("encoding to memory succeeds");
+                image::load(Cursor::new(jpeg), ImageFormat::Jpeg)
+                    .expect("decoding what was just encoded succeeds")
+                    .into_luma8()
+            }
+            Self::Noise { amplitude, seed } => {
+                let mut rng = SeededRng::new(seed);
+                let amplitude = f32::from(amplitude);
+                let mut out = img.clone();
+                for pixel in out.pixels_mut() {
+                    let noise = rng.range(-amplitude, amplitude);
+                    pixel.0[0] = (f32::from(pixel.0[0]) + noise).round().clamp(0.0, 255.0) as u8;
+                }
+                out
+            }
+            Self::Streak { x, width, luma } => {
+                let mut out = img.clone();
+                if x < img.width() && width > 0 {
+                    draw_filled_rect_mut(
+                        &mut out,
+                        ImageprocRect::at(x as i32, 0)
+                            .of_size(width.min(img.width() - x), img.height()),
+                        luma,
+                    );
+                }
+                out
+            }
+            Self::TornCorner { corner, size, luma } => {
+                let (right, bottom) = (img.width() as i32 - 1, img.height() as i32 - 1);
+                let size = size as i32;
+                let (x, y, dx, dy) = match corner {
+                    Corner::TopLeft => (0, 0, size, size),
+                    Corner::TopRight => (right, 0, -size, size),
+                    Corner::BottomLeft => (0, bottom, size, -size),
+                    Corner::BottomRight => (right, bottom, -size, -size),
+                };
+                let mut out = img.clone();
+                draw_polygon_mut(
+                    &mut out,
+                    &[
+                        ImageprocPoint::new(x, y),
+                        ImageprocPoint::new(x + dx, y),
+                        ImageprocPoint::new(x, y + dy),
+                    ],
+                    luma,
+                );
+                out
+            }
+            Self::FaintMarks { .. } => img.clone(),
+        }
+    }
+
+    /// Applies the degradation to the marks drawn on a synthetic ballot.
+    pub fn apply_to_marks(&self, marks: &[SyntheticMark]) -> Vec<SyntheticMark> {
+        match *self {
+            Self::FaintMarks { luma } => marks
+                .iter()
+                .map(|mark| SyntheticMark { luma, ..*mark })
+                .collect(),
+            _ => marks.to_vec(),
+        }
+    }
+}
+
+/// Applies each degradation in turn to a page image.
+pub fn degrade_image(img: &GrayImage, degradations: &[Degradation]) -> GrayImage {
+    degradations
+        .iter()
+        .fold(img.clone(), |img, degradation| degradation.apply(&img))
+}
+
+/// Applies each degradation in turn to the marks on a synthetic ballot.
+pub fn degrade_marks(marks: &[SyntheticMark], degradations: &[Degradation]) -> Vec<SyntheticMark> {
+    degradations
+        .iter()
+        .fold(marks.to_vec(), |marks, degradation| {
+            degradation.apply_to_marks(&marks)
+        })
+}
+
+/// Applies the degradations for each side of a synthetic ballot card to the
+/// marks on that side.
+pub fn degrade_card_marks(
+    marks: &[SyntheticMark],
+    front_degradations: &[Degradation],
+    back_degradations: &[Degradation],
+) -> Vec<SyntheticMark> {
+    let (front_marks, back_marks): (Vec<_>, Vec<_>) = marks
+        .iter()
+        .cloned()
+        .partition(|mark| mark.location.side == BallotSide::Front);
+    [
+        degrade_marks(&front_marks, front_degradations),
+        degrade_marks(&back_marks, back_degradations),
+    ]
+    .concat()
+}
+
+/// Generates a random combination of degradations for a page of the given
+/// size, each within the range seen on ordinary scans that interpretation is
+/// expected to handle. The same seed always gives the same degradations.
+pub fn random_scan_degradations(seed: u64, size: (u32, u32)) -> Vec<Degradation> {
+    let (width, height) = size;
+    let mut rng = SeededRng::new(seed);
+    let mut degradations = vec![Degradation::FaintMarks {
+        luma: Luma([rng.range(60.0, 150.0) as u8]),
+    }];
+
+    if rng.chance(0.5) {
+        let corner = match rng.next_u64() % 4 {
+            0 => Corner::TopLeft,
+            1 => Corner::TopRight,
+            2 => Corner::BottomLeft,
+            _ => Corner::BottomRight,
+        };
+        degradations.push(Degradation::TornCorner {
+            corner,
+            size: rng.range(10.0, 150.0) as u32,
+            luma: Luma([if rng.chance(0.5) { 30 } else { 255 }]),
+        });
+    }
+
+    // timing marks may be printed right up to the edges of the page, so
+    // shrink the page enough that rotating it doesn't push them off the image
+    let degrees = rng.range(-2.0, 2.0);
+    let maximum_scale =
+        (height as f32 / width as f32).mul_add(-degrees.to_radians().sin().abs(), 0.998);
+    let scale = rng.range(maximum_scale - 0.02, maximum_scale);
+    degradations.push(Degradation::Scale {
+        horizontal: scale,
+        vertical: scale * rng.range(0.99, 1.01),
+    });
+    if rng.chance(0.5) {
+        degradations.push(Degradation::FeedStretch {
+            top: rng.range(0.98, 1.02),
+            bottom: rng.range(0.98, 1.02),
+        });
+    }
+    degradations.push(Degradation::Rotation { degrees });
+
+    if rng.chance(0.3) {
+        degradations.push(Degradation::Streak {
+            x: rng.range(0.0, width as f32) as u32,
+            width: rng.range(1.0, 8.0) as u32,
+            luma: Luma([rng.range(0.0, 160.0) as u8]),
+        });
+    }
+    if rng.chance(0.5) {
+        degradations.push(Degradation::Blur {
+            sigma: rng.range(0.3, 1.5),
+        });
+    }
+    degradations.push(Degradation::Noise {
+        amplitude: rng.range(0.0, 32.0) as u8,
+        seed: rng.next_u64(),
+    });
+    if rng.chance(0.5) {
+        degradations.push(Degradation::JpegArtifacts {
+            quality: rng.range(30.0, 95.0) as u8,
+        });
+    }
+
+    degradations
+}
+
+#[cfg(test)]
+mod tests {
+    use image::GenericImageView;
+
+    use super::*;
+    use crate::{
+        ballot_card::BallotSide, election::GridLocation, image_utils::BLACK,
+        synthetic_ballot::DEFAULT_MARK_LUMA,
+    };
+
+    /// A white page with a black square in the middle.
+    fn page_with_square() -> GrayImage {
+        let mut img = GrayImage::from_pixel(100, 100, WHITE);
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(40, 40).of_size(20, 20), BLACK);
+        img
+    }
+
+    #[test]
+    fn test_seeded_rng_is_reproducible() {
+        let (mut a, mut b) = (SeededRng::new(7), SeededRng::new(7));
+        assert_eq!(
+            (0..10).map(|_| a.next_u64()).collect::<Vec<_>>(),
+            (0..10).map(|_| b.next_u64()).collect::<Vec<_>>()
+        );
+        assert_ne!(SeededRng::new(8).next_u64(), SeededRng::new(7).next_u64());
+
+        let mut rng = SeededRng::new(7);
+        assert!((0..1000).all(|_| (2.0..3.0).contains(&rng.range(2.0, 3.0))));
+    }
+
+    #[test]
+    fn test_random_scan_degradations_are_reproducible() {
+        assert_eq!(
+            random_scan_degradations(42, (1696, 2200)),
+            random_scan_degradations(42, (1696, 2200))
+        );
+        assert_ne!(
+            random_scan_degradations(42, (1696, 2200)),
+            random_scan_degradations(43, (1696, 2200))
+        );
+    }
+
+    #[test]
+    fn test_geometric_degradations_keep_size() {
+        let img = page_with_square();
+        for degradation in [
+            Degradation::Rotation { degrees: 5.0 },
+            Degradation::Scale {
+                horizontal: 1.1,
+                vertical: 0.9,
+            },
+            Degradation::FeedStretch {
+                top: 1.0,
+                bottom: 1.1,
+            },
+        ] {
+            let degraded = degradation.apply(&img);
+            assert_eq!(degraded.dimensions(), img.dimensions(), "{degradation:?}");
+            // the middle of the square stays put
+            assert_eq!(degraded.get_pixel(50, 50), &BLACK, "{degradation:?}");
+        }
+    }
+
+    #[test]
+    fn test_scale_degradation() {
+        let degraded = Degradation::Scale {
+            horizontal: 2.0,
+            vertical: 1.0,
+        }
+        .apply(&page_with_square());
+        assert_eq!(degraded.get_pixel(32, 50), &BLACK);
+        assert_eq!(degraded.get_pixel(25, 50), &WHITE);
+        assert_eq!(degraded.get_pixel(50, 35), &WHITE);
+    }
+
+    #[test]
+    fn test_feed_stretch_source_y() {
+        assert!((feed_stretch_source_y(50.0, 1.0, 1.0, 100.0) - 50.0).abs() < 1e-4);
+        // a uniform stretch of 2 maps each row to half of its position
+        assert!((feed_stretch_source_y(50.0, 2.0, 2.0, 100.0) - 25.0).abs() < 1e-4);
+        // stretching only towards the bottom leaves the top in place
+        let y = feed_stretch_source_y(10.0, 1.0, 1.2, 100.0);
+        assert!(y < 10.0 && y > 9.9, "{y}");
+    }
+
+    #[test]
+    fn test_image_degradations() {
+        let img = page_with_square();
+
+        let noisy = Degradation::Noise {
+            amplitude: 10,
+            seed: 1,
+        }
+        .apply(&img);
+        assert_ne!(noisy, img);
+        assert!(noisy
+            .pixels()
+            .zip(img.pixels())
+            .all(|(a, b)| a.0[0].abs_diff(b.0[0]) <= 10));
+
+        let blurred = Degradation::Blur { sigma: 2.0 }.apply(&img);
+        assert!(blurred.get_pixel(40, 50).0[0] > 0 && blurred.get_pixel(40, 50).0[0] < 255);
+
+        let jpeg = Degradation::JpegArtifacts { quality: 50 }.apply(&img);
+        assert_eq!(jpeg.dimensions(), img.dimensions());
+        assert!(jpeg.get_pixel(50, 50).0[0] < 64);
+
+        let streaked = Degradation::Streak {
+            x: 10,
+            width: 2,
+            luma: Luma([50]),
+        }
+        .apply(&img);
+        assert!((0..100).all(|y| streaked.get_pixel(11, y) == &Luma([50])));
+        assert_eq!(streaked.get_pixel(12, 0), &WHITE);
+
+        let torn = Degradation::TornCorner {
+            corner: Corner::BottomRight,
+            size: 20,
+            luma: Luma([0]),
+        }
+        .apply(&img);
+        assert_eq!(torn.get_pixel(98, 98), &Luma([0]));
+        assert_eq!(torn.get_pixel(1, 1), &WHITE);
+        assert_eq!(
+            torn.view(0, 0, 70, 70).to_image(),
+            img.view(0, 0, 70, 70).to_image()
+        );
+    }
+
+    #[test]
+    fn test_faint_marks() {
+        let marks = [SyntheticMark::new(GridLocation::new(
+            BallotSide::Front,
+            5,
+            6,
+        ))];
+        let degradations = [
+            Degradation::Blur { sigma: 1.0 },
+            Degradation::FaintMarks { luma: Luma([120]) },
+        ];
+        assert_eq!(marks[0].luma, DEFAULT_MARK_LUMA);
+        assert_eq!(degrade_marks(&marks, &degradations)[0].luma, Luma([120]));
+        assert_eq!(
+            degrade_image(&page_with_square(), &degradations[1..]),
+            page_with_square()
+        );
+    }
+
+    #[test]
+    fn test_faint_marks_on_each_side() {
+        let marks = [
+            SyntheticMark::new(GridLocation::new(BallotSide::Front, 5, 6)),
+            SyntheticMark::new(GridLocation::new(BallotSide::Back, 5, 6)),
+        ];
+        let degraded = degrade_card_marks(
+            &marks,
+            &[Degradation::FaintMarks { luma: Luma([120]) }],
+            &[Degradation::FaintMarks { luma: Luma([80]) }],
+        );
+        assert_eq!(
+            degraded
+                .iter()
+                .map(|mark| (mark.location.side, mark.luma))
+                .collect::<Vec<_>>(),
+            vec![
+                (BallotSide::Front, Luma([120])),
+                (BallotSide::Back, Luma([80]))
+            ]
+        );
+    }
+}
@@ -1,8 +1,15 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{ballot_card::BallotSide, types::idtype};
+use crate::{
+    ballot_card::{
+        built_in_geometries, get_scanned_ballot_card_geometry, BallotSide, Geometry,
+        PhysicalGeometry, TimingMarkLayout, CANONICAL_PIXELS_PER_INCH,
+    },
+    geometry::{Rect, Size},
+    types::idtype,
+};
 
 // import idtype macro from types.rs
 
@@ -17,6 +24,125 @@ pub struct Election {
     pub title: String,
     pub grid_layouts: Vec<GridLayout>,
     pub mark_thresholds: Option<MarkThresholds>,
+
+    /// The geometry of this election's ballots, used instead of the built-in
+    /// geometries.
+    pub geometry: Option<PhysicalGeometry>,
+
+    /// Overrides the timing mark layout of the built-in ballot geometries.
+    pub timing_mark_layout: Option<TimingMarkLayout>,
+
+    /// Overrides the number of timing mark columns (width) and rows (height)
+    /// of the built-in ballot geometries.
+    pub grid_size: Option<Size<u32>>,
+
+    /// The kinds of targets voters mark on this election's ballots, by name.
+    /// Grid positions refer to these with `targetShape`.
+    #[serde(default)]
+    pub target_shapes: HashMap<String, TargetShape>,
+
+    /// The name of the target shape to use for grid positions that don't
+    /// specify one. If not set, the built-in oval is used.
+    pub default_target_shape: Option<String>,
+}
+
+impl Election {
+    /// The geometries this election's ballots may have, with any overrides
+    /// applied. Like the built-in geometries, an election-defined geometry is
+    /// at `CANONICAL_PIXELS_PER_INCH`.
+    pub fn geometries(&self) -> Vec<Geometry> {
+        match &self.geometry {
+            Some(geometry) => vec![geometry.at_resolution(
+                CANONICAL_PIXELS_PER_INCH,
+                self.timing_mark_layout.unwrap_or_default(),
+            )],
+            None => built_in_geometries().to_vec(),
+        }
+        .iter()
+        .map(|geometry| self.apply_geometry_overrides(geometry))
+        .collect()
+    }
+
+    /// Finds the geometry for a scan of the given size: the election's own
+    /// geometry if it has one, otherwise the built-in geometry for the
+    /// matching paper size.
+    pub fn scanned_ballot_card_geometry(
+        &self,
+        size: (u32, u32),
+        tolerance: f32,
+    ) -> Option<Geometry> {
+        match &self.geometry {
+            Some(geometry) => geometry.scanned_geometry(
+                size,
+                tolerance,
+                self.timing_mark_layout.unwrap_or_default(),
+            ),
+            None => get_scanned_ballot_card_geometry(size, tolerance),
+        }
+    }
+
+    /// Applies any geometry overrides from the election definition to one of
+    /// the built-in geometries. A usable area that covers the whole grid grows
+    /// or shrinks with an overridden grid size; one that was restricted to
+    /// part of the grid is kept as is.
+    pub fn apply_geometry_overrides(&self, geometry: &Geometry) -> Geometry {
+        let mut geometry = *geometry;
+
+        if let Some(timing_mark_layout) = self.timing_mark_layout {
+            geometry.timing_mark_layout = timing_mark_layout;
+        }
+
+        if let Some(grid_size) = self.grid_size {
+            let old_grid = Rect::new(0, 0, geometry.grid_size.width, geometry.grid_size.height);
+            let new_grid = Rect::new(0, 0, grid_size.width, grid_size.height);
+            geometry.grid_size = grid_size;
+            if geometry.front_usable_area == old_grid {
+                geometry.front_usable_area = new_grid;
+            }
+            if geometry.back_usable_area == old_grid {
+                geometry.back_usable_area = new_grid;
+            }
+        }
+
+        geometry
+    }
+}
+
+/// A kind of target that voters mark to make a selection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TargetShape {
+    /// The built-in oval.
+    Oval,
+
+    /// A template image, e.g. a scan of a blank target. Relative paths are
+    /// resolved from the directory containing the election definition.
+    #[serde(rename_all = "camelCase")]
+    Image { path: PathBuf },
+
+    /// A hollow rectangle.
+    #[serde(rename_all = "camelCase")]
+    Rectangle {
+        width: u32,
+        height: u32,
+        #[serde(default = "default_target_line_width")]
+        line_width: u32,
+    },
+
+    /// A "connect the arrow" target: a horizontal arrow pointing right with a
+    /// gap in its shaft that the voter fills in by drawing a line.
+    #[serde(rename_all = "camelCase")]
+    Arrow {
+        width: u32,
+        height: u32,
+        gap_width: u32,
+        #[serde(default = "default_target_line_width")]
+        line_width: u32,
+    },
+}
+
+const fn default_target_line_width() -> u32 {
+    2
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +153,36 @@ pub struct GridLayout {
     pub columns: u32,
     pub rows: u32,
     pub grid_positions: Vec<GridPosition>,
+
+    /// Scans or renderings of this ballot style with nothing marked. If given,
+    /// marks are also scored by how much they differ from the blank ballot.
+    pub blank_ballot: Option<BlankBallotImages>,
+}
+
+impl GridLayout {
+    /// Finds the grid positions that lie outside the usable area of their
+    /// side of the ballot card.
+    pub fn grid_positions_outside_usable_area(&self, geometry: &Geometry) -> Vec<GridPosition> {
+        self.grid_positions
+            .iter()
+            .filter(|grid_position| {
+                let location = grid_position.location();
+                !geometry
+                    .usable_area(location.side)
+                    .contains(location.column as i32, location.row as i32)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Paths to images of both sides of an unmarked ballot card. Relative paths
+/// are resolved from the directory containing the election definition.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlankBallotImages {
+    pub front: PathBuf,
+    pub back: PathBuf,
 }
 
 /// A position on the ballot grid defined by timing marks and the contest/option
@@ -42,6 +198,8 @@ pub enum GridPosition {
         row: u32,
         contest_id: ContestId,
         option_id: OptionId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target_shape: Option<String>,
     },
 
     /// A write-in option on the ballot.
@@ -52,6 +210,8 @@ pub enum GridPosition {
         row: u32,
         contest_id: ContestId,
         write_in_index: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target_shape: Option<String>,
     },
 }
 
@@ -78,9 +238,19 @@ impl GridPosition {
             } => GridLocation::new(*side, *column, *row),
         }
     }
+
+    /// The name of the election's target shape used at this position, if it
+    /// isn't the default.
+    pub fn target_shape(&self) -> Option<&str> {
+        match self {
+            Self::Option { target_shape, .. } | Self::WriteIn { target_shape, .. } => {
+                target_shape.as_deref()
+            }
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 pub struct GridLocation {
     pub side: BallotSide,
     pub column: u32,
@@ -103,6 +273,131 @@ pub struct MarkThresholds {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ballot_card::{get_scanned_ballot_card_geometry_8pt5x11, BorderEdge};
+
+    #[test]
+    fn test_election_geometry_overrides() {
+        let election: Election = serde_json::from_str(
+            r#"{
+                "title": "Test",
+                "gridLayouts": [],
+                "timingMarkLayout": {
+                    "top": false,
+                    "bottom": false,
+                    "metadataEdge": "right"
+                },
+                "gridSize": { "width": 40, "height": 41 }
+            }"#,
+        )
+        .unwrap();
+
+        let geometry =
+            election.apply_geometry_overrides(&get_scanned_ballot_card_geometry_8pt5x11());
+        assert_eq!(
+            geometry.grid_size,
+            Size {
+                width: 40,
+                height: 41
+            }
+        );
+        assert_eq!(geometry.front_usable_area, Rect::new(0, 0, 40, 41));
+        assert!(!geometry.timing_mark_layout.top);
+        assert!(geometry.timing_mark_layout.left);
+        assert_eq!(
+            geometry.timing_mark_layout.metadata_edge,
+            Some(BorderEdge::Right)
+        );
+    }
+
+    #[test]
+    fn test_election_geometry_overrides_keep_physical_geometry() {
+        let election: Election = serde_json::from_str(
+            r#"{
+                "title": "Test",
+                "gridLayouts": [],
+                "geometry": {
+                    "paperSize": { "width": 8.5, "height": 11 },
+                    "timingMarkSize": { "width": 0.1875, "height": 0.0625 },
+                    "ovalSize": { "width": 0.2, "height": 0.13 },
+                    "gridSize": { "width": 34, "height": 41 },
+                    "backUsableArea": { "left": 0, "top": 0, "width": 34, "height": 20 }
+                },
+                "timingMarkLayout": {
+                    "top": false,
+                    "bottom": false,
+                    "metadataEdge": "right"
+                },
+                "gridSize": { "width": 40, "height": 41 }
+            }"#,
+        )
+        .unwrap();
+        let timing_mark_layout = election.timing_mark_layout.unwrap();
+
+        let geometries = election.geometries();
+        assert_eq!(geometries.len(), 1);
+        let geometry = geometries[0];
+        assert_eq!(geometry.front_usable_area, Rect::new(0, 0, 40, 41));
+        assert_eq!(geometry.back_usable_area, Rect::new(0, 0, 34, 20));
+        assert_eq!(geometry.timing_mark_layout, timing_mark_layout);
+
+        let scanned_geometry = election
+            .scanned_ballot_card_geometry((1700, 2200), 0.01)
+            .unwrap();
+        assert_eq!(scanned_geometry.timing_mark_layout, timing_mark_layout);
+        assert_eq!(
+            election.apply_geometry_overrides(&scanned_geometry),
+            geometry
+        );
+    }
+
+    #[test]
+    fn test_election_without_geometry_overrides() {
+        let election: Election =
+            serde_json::from_str(r#"{ "title": "Test", "gridLayouts": [] }"#).unwrap();
+        let geometry = get_scanned_ballot_card_geometry_8pt5x11();
+        assert_eq!(election.apply_geometry_overrides(&geometry), geometry);
+    }
+
+    #[test]
+    fn test_grid_positions_outside_usable_area() {
+        let grid_layout: GridLayout = serde_json::from_str(
+            r#"{
+                "precinctId": "precinct-1",
+                "ballotStyleId": "card-number-1",
+                "columns": 34,
+                "rows": 41,
+                "gridPositions": [
+                    {
+                        "type": "option",
+                        "side": "front",
+                        "column": 5,
+                        "row": 30,
+                        "contestId": "mayor",
+                        "optionId": "alice"
+                    },
+                    {
+                        "type": "write-in",
+                        "side": "back",
+                        "column": 5,
+                        "row": 30,
+                        "contestId": "mayor",
+                        "writeInIndex": 0
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut geometry = get_scanned_ballot_card_geometry_8pt5x11();
+        assert!(grid_layout
+            .grid_positions_outside_usable_area(&geometry)
+            .is_empty());
+
+        geometry.back_usable_area = Rect::new(0, 0, 34, 20);
+        let outside = grid_layout.grid_positions_outside_usable_area(&geometry);
+        assert_eq!(outside.len(), 1);
+        assert_eq!(outside[0].location().side, BallotSide::Back);
+    }
 
     #[test]
     fn test_grid_location() {
@@ -120,6 +415,7 @@ mod tests {
             row: 2,
             contest_id: ContestId::from("contest-1".to_string()),
             option_id: OptionId::from("option-1".to_string()),
+            target_shape: None,
         };
         assert_eq!(position.location().side, BallotSide::Front);
         assert_eq!(position.location().column, 1);
@@ -143,12 +439,14 @@ mod tests {
                 row,
                 contest_id,
                 option_id,
+                target_shape,
             } => {
                 assert_eq!(side, BallotSide::Front);
                 assert_eq!(column, 1);
                 assert_eq!(row, 2);
                 assert_eq!(contest_id, ContestId::from("contest-1".to_string()));
                 assert_eq!(option_id, OptionId::from("option-1".to_string()));
+                assert_eq!(target_shape, None);
             }
             _ => panic!("expected Option"),
         }
@@ -171,14 +469,82 @@ mod tests {
                 row,
                 contest_id,
                 write_in_index,
+                target_shape,
             } => {
                 assert_eq!(side, BallotSide::Front);
                 assert_eq!(column, 1);
                 assert_eq!(row, 2);
                 assert_eq!(contest_id, ContestId::from("contest-1".to_string()));
                 assert_eq!(write_in_index, 3);
+                assert_eq!(target_shape, None);
             }
             _ => panic!("expected WriteIn"),
         }
     }
+
+    #[test]
+    fn test_target_shapes_deserialize() {
+        let election: Election = serde_json::from_str(
+            r#"{
+                "title": "Test",
+                "targetShapes": {
+                    "box": { "type": "rectangle", "width": 30, "height": 20 },
+                    "arrow": {
+                        "type": "arrow",
+                        "width": 80,
+                        "height": 20,
+                        "gapWidth": 30,
+                        "lineWidth": 3
+                    },
+                    "scan": { "type": "image", "path": "target.png" }
+                },
+                "defaultTargetShape": "box",
+                "gridLayouts": [{
+                    "precinctId": "precinct-1",
+                    "ballotStyleId": "card-number-1",
+                    "columns": 34,
+                    "rows": 41,
+                    "gridPositions": [{
+                        "type": "option",
+                        "side": "front",
+                        "column": 1,
+                        "row": 2,
+                        "contestId": "contest-1",
+                        "optionId": "option-1",
+                        "targetShape": "arrow"
+                    }]
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            election.target_shapes.get("box"),
+            Some(&TargetShape::Rectangle {
+                width: 30,
+                height: 20,
+                line_width: 2
+            })
+        );
+        assert_eq!(
+            election.target_shapes.get("arrow"),
+            Some(&TargetShape::Arrow {
+                width: 80,
+                height: 20,
+                gap_width: 30,
+                line_width: 3
+            })
+        );
+        assert_eq!(
+            election.target_shapes.get("scan"),
+            Some(&TargetShape::Image {
+                path: PathBuf::from("target.png")
+            })
+        );
+        assert_eq!(election.default_target_shape.as_deref(), Some("box"));
+        assert_eq!(
+            election.grid_layouts[0].grid_positions[0].target_shape(),
+            Some("arrow")
+        );
+    }
 }
@@ -0,0 +1,262 @@
+use std::io::Cursor;
+
+use fax::{
+    decoder::{decode_g3, decode_g4, pels},
+    Color,
+};
+use image::GrayImage;
+use serde::Serialize;
+use tiff::{decoder::Decoder, tags::Tag};
+
+use crate::image_utils::{ImageOpenFailureCause, BLACK, WHITE};
+
+/// TIFF compression schemes for bi-tonal images from the CCITT fax standards.
+const COMPRESSION_CCITT_GROUP_3: u16 = 3;
+const COMPRESSION_CCITT_GROUP_4: u16 = 4;
+
+const PHOTOMETRIC_BLACK_IS_ZERO: u16 = 1;
+const FILL_ORDER_LSB_FIRST: u16 = 2;
+
+/// The `T4Options` tag, which `tiff` doesn't name.
+const T4_OPTIONS_TAG: u16 = 292;
+
+/// The `T4Options` flag for two-dimensional Group 3 coding.
+const T4_OPTIONS_2D_CODING: u32 = 1;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Error {
+    /// The file's TIFF structure could not be read.
+    InvalidTiff { message: String },
+
+    /// The file is a fax TIFF, but uses a feature that isn't supported.
+    Unsupported { message: String },
+
+    /// The compressed data for a strip of the image is invalid.
+    #[serde(rename_all = "camelCase")]
+    DecodingFailed { strip_index: usize },
+}
+
+impl From<tiff::TiffError> for Error {
+    fn from(error: tiff::TiffError) -> Self {
+        Self::InvalidTiff {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<Error> for ImageOpenFailureCause {
+    fn from(error: Error) -> Self {
+        let format = Some("Tiff".to_string());
+        match error {
+            Error::InvalidTiff { message } => Self::Decoding { format, message },
+            Error::Unsupported { message } => Self::UnsupportedFormat {
+                format,
+                message: format!("unsupported fax TIFF feature: {message}"),
+            },
+            Error::DecodingFailed { strip_index } => Self::Decoding {
+                format,
+                message: format!("invalid CCITT data in strip {strip_index}"),
+            },
+        }
+    }
+}
+
+/// Determines whether `data` is a TIFF compressed with CCITT Group 3 or
+/// Group 4, which `image` can't decode.
+pub fn is_fax_tiff(data: &[u8]) -> bool {
+    Decoder::new(Cursor::new(data))
+        .and_then(|mut decoder| decoder.find_tag_unsigned::<u16>(Tag::Compression))
+        .is_ok_and(|compression| {
+            matches!(
+                compression,
+                Some(COMPRESSION_CCITT_GROUP_3 | COMPRESSION_CCITT_GROUP_4)
+            )
+        })
+}
+
+/// Decodes the first image of a CCITT Group 3 or Group 4 compressed TIFF into
+/// a grayscale image that is black (0) and white (255) only.
+pub fn decode_fax_tiff(data: &[u8]) -> Result<GrayImage, Error> {
+    let mut decoder = Decoder::new(Cursor::new(data))?;
+    let (width, height) = decoder.dimensions()?;
+    let compression = decoder.get_tag_unsigned::<u16>(Tag::Compression)?;
+    let black_is_zero = decoder.find_tag_unsigned::<u16>(Tag::PhotometricInterpretation)?
+        == Some(PHOTOMETRIC_BLACK_IS_ZERO);
+    let lsb_first = decoder.find_tag_unsigned::<u16>(Tag::FillOrder)? == Some(FILL_ORDER_LSB_FIRST);
+    let t4_options = decoder
+        .find_tag_unsigned::<u32>(Tag::Unknown(T4_OPTIONS_TAG))?
+        .unwrap_or(0);
+
+    let unsupported = |message: &str| Error::Unsupported {
+        message: message.to_string(),
+    };
+    if compression == COMPRESSION_CCITT_GROUP_3 && t4_options & T4_OPTIONS_2D_CODING != 0 {
+        return Err(unsupported("two-dimensional Group 3 coding"));
+    }
+    if decoder.find_tag(Tag::TileOffsets)?.is_some() {
+        return Err(unsupported("tiled images"));
+    }
+    let line_width = u16::try_from(width).map_err(|_| unsupported("images this wide"))?;
+
+    let strip_offsets = decoder.get_tag_u64_vec(Tag::StripOffsets)?;
+    let strip_byte_counts = decoder.get_tag_u64_vec(Tag::StripByteCounts)?;
+    let rows_per_strip = decoder
+        .find_tag_unsigned::<u32>(Tag::RowsPerStrip)?
+        .unwrap_or(height)
+        .min(height);
+    if strip_offsets.len() != strip_byte_counts.len() || rows_per_strip == 0 {
+        return Err(Error::InvalidTiff {
+            message: "invalid strip layout".to_string(),
+        });
+    }
+
+    let (ink, paper) = if black_is_zero {
+        (WHITE, BLACK)
+    } else {
+        (BLACK, WHITE)
+    };
+    let mut img = GrayImage::from_pixel(width, height, paper);
+    let mut y = 0;
+
+    for (strip_index, (offset, byte_count)) in
+        strip_offsets.iter().zip(&strip_byte_counts).enumerate()
+    {
+        let strip_rows = rows_per_strip.min(height - y);
+        if strip_rows == 0 {
+            break;
+        }
+
+        let strip = usize::try_from(*offset)
+            .ok()
+            .zip(usize::try_from(*byte_count).ok())
+            .and_then(|(offset, byte_count)| data.get(offset..offset.checked_add(byte_count)?))
+            .ok_or(Error::DecodingFailed { strip_index })?;
+        let bytes = strip.iter().map(|byte| {
+            if lsb_first {
+                byte.reverse_bits()
+            } else {
+                *byte
+            }
+        });
+
+        let mut rows_decoded = 0;
+        let mut draw_line = |transitions: &[u16]| {
+            if rows_decoded < strip_rows {
+                for (x, color) in pels(transitions, line_width).enumerate() {
+                    if color == Color::Black {
+                        img.put_pixel(x as u32, y + rows_decoded, ink);
+                    }
+                }
+            }
+            rows_decoded += 1;
+        };
+
+        // the decoders report an error for some trailing padding, so only
+        // fail if the strip is missing rows
+        if compression == COMPRESSION_CCITT_GROUP_4 {
+            let _ = decode_g4(bytes, line_width, Some(strip_rows as u16), &mut draw_line);
+        } else {
+            let _ = decode_g3(bytes, &mut draw_line);
+        }
+        if rows_decoded < strip_rows {
+            return Err(Error::DecodingFailed { strip_index });
+        }
+
+        y += strip_rows;
+    }
+
+    if y < height {
+        return Err(Error::InvalidTiff {
+            message: "image data is missing strips".to_string(),
+        });
+    }
+
+    Ok(img)
+}
+
+/// Determines whether an image contains only black and white pixels, as
+/// opposed to shades of gray.
+pub fn is_bitonal(img: &GrayImage) -> bool {
+    img.pixels().all(|pixel| *pixel == BLACK || *pixel == WHITE)
+}
+
+#[cfg(test)]
+mod tests {
+    use fax::{encoder::Encoder, VecWriter};
+    use image::Luma;
+
+    use super::*;
+
+    /// Encodes a page as a Group 4 TIFF, the way high-speed scanners do.
+    fn encode_g4_tiff(img: &GrayImage) -> Vec<u8> {
+        let mut encoder = Encoder::new(VecWriter::new());
+        for row in img.rows() {
+            encoder
+                .encode_line(
+                    row.map(|pixel| {
+                        if *pixel == BLACK {
+                            Color::Black
+                        } else {
+                            Color::White
+                        }
+                    }),
+                    img.width() as u16,
+                )
+                .unwrap();
+        }
+        let data = encoder.finish().unwrap().finish();
+        fax::tiff::wrap(&data, img.width(), img.height())
+    }
+
+    fn page() -> GrayImage {
+        GrayImage::from_fn(120, 80, |x, y| {
+            if (10..30).contains(&x) && (5..12).contains(&y) || (x + y) % 17 == 0 {
+                BLACK
+            } else {
+                WHITE
+            }
+        })
+    }
+
+    #[test]
+    fn test_decode_g4_tiff() {
+        let img = page();
+        let data = encode_g4_tiff(&img);
+        assert!(is_fax_tiff(&data));
+        assert!(image::load_from_memory(&data).is_err());
+
+        let decoded = decode_fax_tiff(&data).unwrap();
+        assert_eq!(decoded, img);
+        assert!(is_bitonal(&decoded));
+    }
+
+    #[test]
+    fn test_truncated_g4_tiff() {
+        let data = encode_g4_tiff(&page());
+        // the image data comes last, so cutting the file short loses rows
+        let truncated = &data[..data.len() - 40];
+        assert!(matches!(
+            decode_fax_tiff(truncated),
+            Err(Error::DecodingFailed { strip_index: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_not_fax_tiff() {
+        let mut png = Vec::new();
+        page()
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        assert!(!is_fax_tiff(&png));
+        assert!(!is_fax_tiff(b"not an image"));
+    }
+
+    #[test]
+    fn test_is_bitonal() {
+        assert!(is_bitonal(&page()));
+        let mut img = page();
+        img.put_pixel(0, 0, Luma([128]));
+        assert!(!is_bitonal(&img));
+    }
+}
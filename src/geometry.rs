@@ -4,7 +4,7 @@ use std::{
 };
 
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
 pub struct Point<T> {
@@ -33,7 +33,7 @@ impl<T: AddAssign + Copy> AddAssign for Point<T> {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Rect {
     left: i32,
     top: i32,
@@ -75,9 +75,47 @@ impl Rect {
         self.top + self.height as i32 - 1
     }
 
+    pub const fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.left && x <= self.right() && y >= self.top && y <= self.bottom()
+    }
+
     pub const fn offset(&self, dx: i32, dy: i32) -> Self {
         Self::new(self.left + dx, self.top + dy, self.width, self.height)
     }
+
+    /// Returns the overlapping area of the two rects, if any.
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let left = self.left.max(other.left);
+        let top = self.top.max(other.top);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if left > right || top > bottom {
+            return None;
+        }
+        Some(Self::new(
+            left,
+            top,
+            (right - left + 1) as u32,
+            (bottom - top + 1) as u32,
+        ))
+    }
+}
+
+/// Something with rectangular bounds in an image, such as a timing mark.
+pub trait Bounded {
+    /// The bounds of the item.
+    fn bounds(&self) -> Rect;
+
+    /// The center of the item, which by default is the center of its bounds.
+    fn center(&self) -> Point<f32> {
+        center_of_rect(&self.bounds())
+    }
+}
+
+impl Bounded for Rect {
+    fn bounds(&self) -> Rect {
+        *self
+    }
 }
 
 impl From<Rect> for imageproc::rect::Rect {
@@ -86,7 +124,7 @@ impl From<Rect> for imageproc::rect::Rect {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Size<T> {
     pub width: T,
     pub height: T,
@@ -233,6 +271,17 @@ pub fn center_of_rect(rect: &Rect) -> Point<f32> {
     )
 }
 
+/// Computes the median of the given values, taking the upper of the two
+/// middle values if there are an even number of them.
+pub fn median(mut values: Vec<f32>) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    Some(values[values.len() / 2])
+}
+
 #[cfg(test)]
 mod normalize_angle_tests {
     use std::{f32::consts::PI, ops::Range};
@@ -289,6 +338,17 @@ mod normalize_angle_tests {
     }
 }
 
+#[cfg(test)]
+mod median_tests {
+    #[test]
+    fn test_median() {
+        assert_eq!(super::median(vec![]), None);
+        assert_eq!(super::median(vec![3.0]), Some(3.0));
+        assert_eq!(super::median(vec![5.0, 1.0, 3.0]), Some(3.0));
+        assert_eq!(super::median(vec![4.0, 1.0, 3.0, 2.0]), Some(3.0));
+    }
+}
+
 #[cfg(test)]
 mod normalize_center_of_rect {
     use proptest::prelude::*;
@@ -322,48 +382,77 @@ mod normalize_center_of_rect {
     }
 }
 
-pub fn find_best_line_through_items(rects: &Vec<Rect>, angle: f32, tolerance: f32) -> Vec<Rect> {
-    if rects.is_empty() {
+/// Finds the largest set of items that lie along a single line at roughly
+/// the given angle, within the given tolerance.
+pub fn find_best_line_through_items<T>(items: &[T], angle: f32, tolerance: f32) -> Vec<T>
+where
+    T: Bounded + Copy + Sync,
+{
+    if items.is_empty() {
         return vec![];
     }
 
-    let best_rects: Vec<&Rect> = rects
+    let best_items: Vec<&T> = items
         .par_iter()
-        .fold_with(vec![], |best_rects, rect| {
-            let mut best_rects = best_rects;
+        .fold_with(vec![], |best_items, item| {
+            let mut best_items = best_items;
 
-            for other_rect in rects.iter() {
-                let rect_center = center_of_rect(rect);
-                let other_rect_center = center_of_rect(other_rect);
-                let line_angle = (other_rect_center.y - rect_center.y)
-                    .atan2(other_rect_center.x - rect_center.x);
+            for other_item in items.iter() {
+                let item_center = item.center();
+                let other_item_center = other_item.center();
+                let line_angle = (other_item_center.y - item_center.y)
+                    .atan2(other_item_center.x - item_center.x);
 
                 if angle_diff(line_angle, angle) > tolerance {
                     continue;
                 }
 
-                let rects_intsersecting_line = rects
+                let items_intersecting_line = items
                     .iter()
-                    .filter(|r| {
-                        rect_intersects_line(r, &Segment::new(rect_center, other_rect_center))
+                    .filter(|i| {
+                        rect_intersects_line(
+                            &i.bounds(),
+                            &Segment::new(item_center, other_item_center),
+                        )
                     })
-                    .collect::<Vec<&Rect>>();
+                    .collect::<Vec<&T>>();
 
-                if rects_intsersecting_line.len() > best_rects.len() {
-                    best_rects = rects_intsersecting_line;
+                if items_intersecting_line.len() > best_items.len() {
+                    best_items = items_intersecting_line;
                 }
             }
 
-            best_rects
+            best_items
         })
-        .reduce_with(|best_rects, other_best_rects| {
-            if other_best_rects.len() > best_rects.len() {
-                other_best_rects
+        .reduce_with(|best_items, other_best_items| {
+            if other_best_items.len() > best_items.len() {
+                other_best_items
             } else {
-                best_rects
+                best_items
             }
         })
-        .expect("at least one result because we have at least one rect");
+        .expect("at least one result because we have at least one item");
 
-    return best_rects.iter().map(|r| **r).collect();
+    best_items.iter().map(|i| **i).collect()
+}
+
+#[cfg(test)]
+mod rect_tests {
+    use super::Rect;
+
+    #[test]
+    fn test_intersect() {
+        let a = Rect::new(0, 0, 10, 10);
+        let b = Rect::new(5, 5, 10, 10);
+        assert_eq!(a.intersect(&b), Some(Rect::new(5, 5, 5, 5)));
+        assert_eq!(b.intersect(&a), Some(Rect::new(5, 5, 5, 5)));
+        assert_eq!(a.intersect(&a), Some(a));
+    }
+
+    #[test]
+    fn test_intersect_disjoint() {
+        let a = Rect::new(0, 0, 10, 10);
+        let b = Rect::new(10, 0, 10, 10);
+        assert_eq!(a.intersect(&b), None);
+    }
 }
@@ -1,8 +1,10 @@
 use image::{
+    error::{ImageFormatHint, LimitErrorKind},
     imageops::{resize, FilterType::Lanczos3},
-    GenericImage, GrayImage, ImageError, Luma, Rgb,
+    GenericImage, GrayImage, ImageBuffer, ImageError, Luma, Pixel, Rgb,
 };
 use logging_timer::time;
+use serde::Serialize;
 
 pub const WHITE: Luma<u8> = Luma([255]);
 pub const BLACK: Luma<u8> = Luma([0]);
@@ -64,6 +66,7 @@ pub fn bleed(img: &GrayImage, luma: Luma<u8>) -> GrayImage {
 /// └───────────────────┘  └───────────────────┘  └───────────────────┘
 /// ```
 ///
+#[cfg(test)]
 pub fn diff(base: &GrayImage, compare: &GrayImage) -> GrayImage {
     assert_eq!(base.dimensions(), compare.dimensions());
 
@@ -84,11 +87,14 @@ pub fn diff(base: &GrayImage, compare: &GrayImage) -> GrayImage {
 }
 
 /// Determines the number of pixels in an image that match the given luma.
+#[cfg(test)]
 pub fn count_pixels(img: &GrayImage, luma: Luma<u8>) -> u32 {
     img.pixels().filter(|p| **p == luma).count() as u32
 }
 
-/// Determines the ratio of pixels in an image that match the given luma.
+/// Determines the ratio of pixels in an image that match the given luma. Oval
+/// scoring works on `BitImage`s instead, and is checked against this.
+#[cfg(test)]
 pub fn ratio(img: &GrayImage, luma: Luma<u8>) -> f32 {
     let total = img.width() * img.height();
     count_pixels(img, luma) as f32 / total as f32
@@ -97,7 +103,11 @@ pub fn ratio(img: &GrayImage, luma: Luma<u8>) -> f32 {
 /// Resizes an image to fit within the given dimensions while maintaining the
 /// aspect ratio.
 #[time]
-pub fn size_image_to_fit(img: &GrayImage, max_width: u32, max_height: u32) -> GrayImage {
+pub fn size_image_to_fit<P: Pixel + 'static>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    max_width: u32,
+    max_height: u32,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
     let aspect_ratio = img.width() as f32 / img.height() as f32;
     let new_width = if aspect_ratio > 1.0 {
         max_width
@@ -127,3 +137,105 @@ pub fn expand_image(
     out.copy_from(img, border_size, border_size)?;
     Ok(out)
 }
+
+/// Why an image file could not be opened.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ImageOpenFailureCause {
+    /// The file could not be read, e.g. because it doesn't exist.
+    Io { kind: String, message: String },
+
+    /// The file's format, or a feature it uses, isn't supported.
+    UnsupportedFormat {
+        format: Option<String>,
+        message: String,
+    },
+
+    /// The file's data is invalid, e.g. because it was truncated.
+    Decoding {
+        format: Option<String>,
+        message: String,
+    },
+
+    /// The image is too large to decode.
+    DimensionsLimitExceeded { message: String },
+
+    /// Any other failure reported by the decoder.
+    Other { message: String },
+}
+
+impl From<ImageError> for ImageOpenFailureCause {
+    fn from(error: ImageError) -> Self {
+        let message = error.to_string();
+        match error {
+            ImageError::IoError(error) => Self::Io {
+                kind: format!("{:?}", error.kind()),
+                message,
+            },
+            ImageError::Unsupported(error) => Self::UnsupportedFormat {
+                format: format_name(&error.format_hint()),
+                message,
+            },
+            ImageError::Decoding(error) => Self::Decoding {
+                format: format_name(&error.format_hint()),
+                message,
+            },
+            ImageError::Limits(error) if matches!(error.kind(), LimitErrorKind::DimensionError) => {
+                Self::DimensionsLimitExceeded { message }
+            }
+            _ => Self::Other { message },
+        }
+    }
+}
+
+/// Gets the name of the format the decoder thinks an image is in, if any.
+fn format_name(hint: &ImageFormatHint) -> Option<String> {
+    match hint {
+        ImageFormatHint::Exact(format) => Some(format!("{format:?}")),
+        ImageFormatHint::Name(name) => Some(name.clone()),
+        ImageFormatHint::PathExtension(extension) => Some(extension.display().to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_image_open_failure_cause() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let missing = dir.path().join("missing.png");
+        assert!(matches!(
+            ImageOpenFailureCause::from(image::open(missing).unwrap_err()),
+            ImageOpenFailureCause::Io { kind, .. } if kind == "NotFound"
+        ));
+
+        let mut png = Vec::new();
+        GrayImage::from_pixel(40, 40, WHITE)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        let truncated = dir.path().join("truncated.png");
+        std::fs::File::create(&truncated)
+            .unwrap()
+            .write_all(&png[..png.len() / 2])
+            .unwrap();
+        assert!(matches!(
+            ImageOpenFailureCause::from(image::open(truncated).unwrap_err()),
+            ImageOpenFailureCause::Decoding { format: Some(format), .. } if format == "Png"
+        ));
+
+        let unknown = dir.path().join("ballot.xyz");
+        std::fs::write(&unknown, b"not an image").unwrap();
+        assert!(matches!(
+            ImageOpenFailureCause::from(image::open(unknown).unwrap_err()),
+            ImageOpenFailureCause::UnsupportedFormat { .. }
+        ));
+    }
+}
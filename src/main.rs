@@ -5,64 +5,115 @@ use std::env;
 use std::path::Path;
 use std::process::exit;
 
-use clap::{arg, command, Command};
+use clap::{arg, command, value_parser, Command};
 use serde::Serialize;
 
 use crate::ballot_card::load_oval_template;
+use crate::binarization::{BinarizationMethod, BinarizationOptions};
+use crate::color::GrayscaleConversion;
 use crate::election::Election;
-use crate::interpret::{interpret_ballot_card, Options};
+use crate::interpret::{
+    generate_ballot_card_grid_layout, interpret_ballot_card, interpret_side_by_side_ballot_card,
+    proof_ballot_card, BlankBallotCache, Options,
+};
+use crate::mark_classification::ClassificationMode;
+use crate::preprocessing::PreprocessingOptions;
+use crate::target_templates::TargetTemplates;
+use crate::validation::{validate_election, ValidationProblem};
 
 mod ballot_card;
+mod binarization;
+mod calibration;
+mod color;
 mod debug;
+#[cfg(test)]
+mod degradation;
 mod election;
+mod fax_tiff;
 mod geometry;
 mod image_utils;
 mod interpret;
+mod mark_classification;
+mod mark_features;
+mod mark_shapes;
 mod metadata;
+mod preprocessing;
+mod proofing;
+mod reference_ballot;
+mod side_by_side;
+mod stray_marks;
+#[cfg(test)]
+mod synthetic_ballot;
+mod target_templates;
+mod template_matching;
 mod timing_marks;
 mod types;
+mod validation;
 
 #[derive(Debug, Serialize)]
 enum Error {
     InvalidElectionDefinition { message: String },
     OvalTemplateReadFailure { message: String },
+    TargetTemplateLoadFailure(crate::target_templates::Error),
     InterpretFailure(Box<crate::interpret::Error>),
     SerializationFailure { message: String },
+    ElectionValidationFailure { problems: Vec<ValidationProblem> },
 }
 
 fn try_main() -> Result<(), Box<Error>> {
     pretty_env_logger::init_custom_env("LOG");
 
     let matches = cli().get_matches();
+    if let Some(("validate", matches)) = matches.subcommand() {
+        let election_definition_path = matches
+            .get_one::<String>("election")
+            .expect("election path is required");
+        return validate(&load_election(election_definition_path)?);
+    }
+
     let debug = matches.get_flag("debug");
-    let side_a_path = matches
+    let binarization_method = |name: &str| {
+        *matches
+            .get_one::<BinarizationMethod>(name)
+            .expect("binarization method has a default")
+    };
+    let classification_mode = *matches
+        .get_one::<ClassificationMode>("classification")
+        .expect("classification mode has a default");
+    let binarization = BinarizationOptions {
+        timing_marks: binarization_method("timing-mark-binarization"),
+        ovals: binarization_method("oval-binarization"),
+        stray_marks: binarization_method("stray-mark-binarization"),
+    };
+    let preprocessing = PreprocessingOptions {
+        despeckle_max_area: *matches
+            .get_one::<u32>("despeckle")
+            .expect("despeckle area has a default"),
+        fail_on_oval_streaks: matches.get_flag("fail-on-oval-streaks"),
+    };
+    let grayscale_conversion = *matches
+        .get_one::<GrayscaleConversion>("grayscale")
+        .expect("grayscale conversion has a default");
+    let report_ink_color = matches.get_flag("ink-color");
+    let aspect_ratio_tolerance = *matches
+        .get_one::<f32>("aspect-ratio-tolerance")
+        .expect("aspect ratio tolerance has a default");
+    // proofing and generating a layout take the same options as
+    // interpreting, but their own election and images
+    let subcommand = match matches.subcommand() {
+        Some((name @ ("proof" | "layout"), subcommand_matches)) => Some((name, subcommand_matches)),
+        _ => None,
+    };
+    let input_matches = subcommand.map_or(&matches, |(_, subcommand_matches)| subcommand_matches);
+    let side_a_path = input_matches
         .get_one::<String>("side_a_path")
         .expect("side A image path is required");
-    let side_b_path = matches
-        .get_one::<String>("side_b_path")
-        .expect("side B image path is required");
-    let election_definition_path = matches
+    let side_b_path = input_matches.get_one::<String>("side_b_path");
+    let election_definition_path = input_matches
         .get_one::<String>("election")
         .expect("election path is required");
 
-    let election_definition_json = match std::fs::read_to_string(election_definition_path) {
-        Ok(json) => json,
-        Err(e) => {
-            return Err(Box::new(Error::InvalidElectionDefinition {
-                message: format!("Error reading election definition: {e}"),
-            }));
-        }
-    };
-
-    // parse contents of election_definition_path with serde_json
-    let election: Election = match serde_json::from_str(&election_definition_json) {
-        Ok(election_definition) => election_definition,
-        Err(e) => {
-            return Err(Box::new(Error::InvalidElectionDefinition {
-                message: format!("Error parsing election definition: {e}"),
-            }));
-        }
-    };
+    let election = load_election(election_definition_path)?;
 
     let oval_template = match load_oval_template() {
         Some(template) => template,
@@ -73,31 +124,106 @@ fn try_main() -> Result<(), Box<Error>> {
         }
     };
 
+    let election_dir = Path::new(election_definition_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let target_templates =
+        match TargetTemplates::from_election(&election, &oval_template, election_dir) {
+            Ok(target_templates) => target_templates,
+            Err(error) => {
+                return Err(Box::new(Error::TargetTemplateLoadFailure(error)));
+            }
+        };
+
     let options = Options {
         debug,
-        oval_template,
+        target_templates,
+        binarization,
+        preprocessing,
+        grayscale_conversion,
+        aspect_ratio_tolerance,
+        report_ink_color,
+        classification_mode,
         election,
+        election_dir: election_dir.to_path_buf(),
+        blank_ballots: BlankBallotCache::default(),
     };
 
-    let card =
-        match interpret_ballot_card(Path::new(&side_a_path), Path::new(&side_b_path), &options) {
-            Ok(card) => card,
-            Err(error) => {
-                return Err(Box::new(Error::InterpretFailure(Box::new(error))));
-            }
+    if let Some((name, _)) = subcommand {
+        let side_a_path = Path::new(&side_a_path);
+        let side_b_path = Path::new(side_b_path.expect("side B image path is required"));
+        let result = if name == "proof" {
+            proof_ballot_card(side_a_path, side_b_path, &options)
+                .map(|proof| print_json(&proof, "layout proof"))
+        } else {
+            generate_ballot_card_grid_layout(side_a_path, side_b_path, &options)
+                .map(|grid_layout| print_json(&grid_layout, "grid layout"))
         };
+        return result
+            .unwrap_or_else(|error| Err(Box::new(Error::InterpretFailure(Box::new(error)))));
+    }
 
-    // use serde_json to serialize the ballot card to JSON
-    let card_json = match serde_json::to_string_pretty(&card) {
+    let result = match side_b_path {
+        Some(side_b_path) => {
+            interpret_ballot_card(Path::new(&side_a_path), Path::new(&side_b_path), &options)
+        }
+        // the side A image has both sides of the card side by side
+        None => interpret_side_by_side_ballot_card(Path::new(&side_a_path), &options),
+    };
+    let card = match result {
+        Ok(card) => card,
+        Err(error) => {
+            return Err(Box::new(Error::InterpretFailure(Box::new(error))));
+        }
+    };
+
+    print_json(&card, "ballot card")
+}
+
+/// Prints `value` as JSON. `description` names it in any error.
+fn print_json<T: Serialize>(value: &T, description: &str) -> Result<(), Box<Error>> {
+    // use serde_json to serialize the value to JSON
+    let json = match serde_json::to_string_pretty(value) {
         Ok(json) => json,
         Err(error) => {
             return Err(Box::new(Error::SerializationFailure {
-                message: format!("Error serializing ballot card: {error}"),
+                message: format!("Error serializing {description}: {error}"),
+            }));
+        }
+    };
+
+    println!("{json}");
+    Ok(())
+}
+
+/// Reads and parses an election definition.
+fn load_election(election_definition_path: &str) -> Result<Election, Box<Error>> {
+    let election_definition_json = match std::fs::read_to_string(election_definition_path) {
+        Ok(json) => json,
+        Err(e) => {
+            return Err(Box::new(Error::InvalidElectionDefinition {
+                message: format!("Error reading election definition: {e}"),
             }));
         }
     };
 
-    println!("{card_json}");
+    // parse contents of election_definition_path with serde_json
+    serde_json::from_str(&election_definition_json).map_err(|e| {
+        Box::new(Error::InvalidElectionDefinition {
+            message: format!("Error parsing election definition: {e}"),
+        })
+    })
+}
+
+/// Prints the problems found in an election definition, failing if there are
+/// any.
+fn validate(election: &Election) -> Result<(), Box<Error>> {
+    let problems = validate_election(election);
+    if !problems.is_empty() {
+        return Err(Box::new(Error::ElectionValidationFailure { problems }));
+    }
+
+    println!("[]");
     Ok(())
 }
 
@@ -114,8 +240,81 @@ fn main() {
 #[allow(clippy::cognitive_complexity)]
 fn cli() -> Command {
     command!()
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("validate")
+                .about("Check an election definition for mistakes")
+                .arg(arg!(-e --election <PATH> "Path to election.json file").required(true)),
+        )
+        .subcommand(
+            Command::new("proof")
+                .about("Compare the targets printed on a blank ballot card with its grid layout")
+                .arg(arg!(-e --election <PATH> "Path to election.json file").required(true))
+                .arg(arg!(side_a_path: <SIDE_A_IMAGE> "Path to blank ballot image for side A").required(true))
+                .arg(arg!(side_b_path: <SIDE_B_IMAGE> "Path to blank ballot image for side B").required(true)),
+        )
+        .subcommand(
+            Command::new("layout")
+                .about("Generate a skeleton grid layout from the targets printed on a blank ballot card")
+                .arg(arg!(-e --election <PATH> "Path to election.json file").required(true))
+                .arg(arg!(side_a_path: <SIDE_A_IMAGE> "Path to blank ballot image for side A").required(true))
+                .arg(arg!(side_b_path: <SIDE_B_IMAGE> "Path to blank ballot image for side B").required(true)),
+        )
         .arg(arg!(-e --election <PATH> "Path to election.json file").required(true))
         .arg(arg!(-d --debug "Enable debug mode"))
-        .arg(arg!(side_a_path: <SIDE_A_IMAGE> "Path to image for side A").required(true))
-        .arg(arg!(side_b_path: <SIDE_B_IMAGE> "Path to image for side B").required(true))
+        .arg(
+            arg!(--classification <MODE> "How to classify marks: absolute, or relative to the other ovals on the page")
+                .value_parser(value_parser!(ClassificationMode))
+                .default_value("absolute"),
+        )
+        .arg(
+            arg!(--"timing-mark-binarization" <METHOD> "Binarization for finding timing marks: otsu, bitonal, niblack[:WINDOW[:K]], or sauvola[:WINDOW[:K]]")
+                .value_parser(value_parser!(BinarizationMethod))
+                .default_value("otsu"),
+        )
+        .arg(
+            arg!(--"oval-binarization" <METHOD> "Binarization for scoring ovals")
+                .value_parser(value_parser!(BinarizationMethod))
+                .default_value("otsu"),
+        )
+        .arg(
+            arg!(--"stray-mark-binarization" <METHOD> "Binarization for finding stray marks")
+                .value_parser(value_parser!(BinarizationMethod))
+                .default_value("otsu"),
+        )
+        .arg(
+            arg!(--despeckle <MAX_AREA> "Remove isolated specks of at most this many pixels, or 0 to keep them")
+                .value_parser(value_parser!(u32))
+                .default_value("0"),
+        )
+        .arg(arg!(--"fail-on-oval-streaks" "Fail if a scanner streak runs through any ovals"))
+        .arg(
+            arg!(--grayscale <CONVERSION> "How to convert color scans to grayscale: luma, channel:COLOR, or dropout:COLOR, where COLOR is red, green, or blue")
+                .value_parser(value_parser!(GrayscaleConversion))
+                .default_value("luma"),
+        )
+        .arg(arg!(--"ink-color" "Report the color of the ink in each oval of color scans"))
+        .arg(
+            arg!(--"aspect-ratio-tolerance" <TOLERANCE> "How far the aspect ratio of a scan may be from that of a ballot paper size")
+                .value_parser(value_parser!(f32))
+                .default_value("0.01"),
+        )
+        .arg(arg!(--"side-by-side" "Interpret a single image with side A on the left and side B on the right"))
+        .arg(arg!(side_a_path: <SIDE_A_IMAGE> "Path to image for side A, or for both sides with --side-by-side").required(true))
+        .arg(
+            arg!(side_b_path: <SIDE_B_IMAGE> "Path to image for side B")
+                .required(false)
+                .required_unless_present("side-by-side")
+                .conflicts_with("side-by-side"),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        cli().debug_assert();
+    }
 }
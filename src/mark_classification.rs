@@ -0,0 +1,325 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{
+    election::MarkThresholds,
+    geometry::median,
+    timing_marks::{OvalMark, ScoredOvalMark, ScoredOvalMarks},
+};
+
+/// Thresholds used when the election definition does not specify any.
+pub const DEFAULT_MARK_THRESHOLDS: MarkThresholds = MarkThresholds {
+    definite: 0.07,
+    marginal: 0.05,
+};
+
+/// The minimum number of scored ovals on a page needed for its fill score
+/// statistics to be meaningful.
+pub const MINIMUM_RELATIVE_SAMPLE_SIZE: usize = 4;
+
+/// How far above the typical fill score on a page, in multiples of the
+/// spread of fill scores, a mark must be to count as relatively marked.
+const RELATIVE_SPREAD_MULTIPLIER: f32 = 6.0;
+
+/// The minimum difference between a relatively marked oval's fill score and
+/// the typical fill score on the page. This keeps a page of perfectly clean
+/// ovals, whose spread is almost zero, from treating smudges as marks.
+const MINIMUM_RELATIVE_CONTRAST: f32 = 0.02;
+
+/// Scales the median absolute deviation to be comparable to a standard
+/// deviation for normally distributed scores.
+const MEDIAN_ABSOLUTE_DEVIATION_SCALE: f32 = 1.4826;
+
+/// How oval marks are classified as marked or not.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClassificationMode {
+    /// Compare each fill score against the election's mark thresholds only.
+    #[default]
+    Absolute,
+
+    /// Also treat marks that are much darker than the other ovals on the same
+    /// page as marked, even if they are below the definite threshold. This
+    /// catches voters who consistently mark lightly.
+    Relative,
+}
+
+impl Display for ClassificationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Absolute => write!(f, "absolute"),
+            Self::Relative => write!(f, "relative"),
+        }
+    }
+}
+
+impl FromStr for ClassificationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => Ok(Self::Absolute),
+            "relative" => Ok(Self::Relative),
+            _ => Err(format!("invalid classification mode: {s}")),
+        }
+    }
+}
+
+/// The classification of a scored oval mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkStatus {
+    /// The fill score is at or above the definite threshold.
+    Marked,
+
+    /// The fill score is below the definite threshold, but the oval is much
+    /// darker than the other ovals on the page. This counts as a mark but
+    /// should be reviewed.
+    RelativelyMarked,
+
+    /// The fill score is at or above the marginal threshold but the oval is
+    /// not marked. This should be reviewed.
+    Marginal,
+
+    /// The fill score is below the marginal threshold.
+    Unmarked,
+}
+
+impl MarkStatus {
+    /// Determines whether a mark with this status counts as a vote.
+    pub const fn is_marked(self) -> bool {
+        matches!(self, Self::Marked | Self::RelativelyMarked)
+    }
+
+    /// Determines whether a mark with this status should be reviewed by a
+    /// person to confirm voter intent.
+    pub const fn needs_review(self) -> bool {
+        matches!(self, Self::RelativelyMarked | Self::Marginal)
+    }
+}
+
+/// The distribution of fill scores across all the scored ovals on a page.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FillScoreStatistics {
+    pub count: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+
+    /// The typical fill score of an oval on the page. Most ovals on a ballot
+    /// are not marked, so this approximates the fill score of an unmarked
+    /// oval.
+    pub median: f32,
+
+    /// The median absolute deviation from `median`, a measure of how much
+    /// unmarked ovals vary that is not thrown off by the marked ones.
+    pub median_absolute_deviation: f32,
+
+    /// Fill scores at or above this are much darker than is typical for the
+    /// page.
+    pub relative_threshold: f32,
+}
+
+/// Gets the fill score used for classification, preferring the score relative
+/// to a blank ballot when there is one.
+pub fn effective_fill_score(scored_oval_mark: &ScoredOvalMark) -> f32 {
+    scored_oval_mark
+        .reference_fill_score
+        .as_ref()
+        .unwrap_or(&scored_oval_mark.fill_score)
+        .0
+}
+
+/// Computes statistics of the given fill scores, if there are enough of them.
+pub fn compute_fill_score_statistics(fill_scores: &[f32]) -> Option<FillScoreStatistics> {
+    if fill_scores.len() < MINIMUM_RELATIVE_SAMPLE_SIZE {
+        return None;
+    }
+
+    let typical_fill_score = median(fill_scores.to_vec())?;
+    let median_absolute_deviation = median(
+        fill_scores
+            .iter()
+            .map(|fill_score| (fill_score - typical_fill_score).abs())
+            .collect(),
+    )?;
+    let spread = median_absolute_deviation * MEDIAN_ABSOLUTE_DEVIATION_SCALE;
+
+    Some(FillScoreStatistics {
+        count: fill_scores.len(),
+        min: fill_scores.iter().copied().fold(f32::INFINITY, f32::min),
+        max: fill_scores
+            .iter()
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max),
+        mean: fill_scores.iter().sum::<f32>() / fill_scores.len() as f32,
+        median: typical_fill_score,
+        median_absolute_deviation,
+        relative_threshold: typical_fill_score
+            + (spread * RELATIVE_SPREAD_MULTIPLIER).max(MINIMUM_RELATIVE_CONTRAST),
+    })
+}
+
+/// Classifies a single fill score. `statistics` is only used in relative mode.
+pub fn classify_fill_score(
+    fill_score: f32,
+    thresholds: &MarkThresholds,
+    mode: ClassificationMode,
+    statistics: Option<&FillScoreStatistics>,
+) -> MarkStatus {
+    if fill_score >= thresholds.definite {
+        return MarkStatus::Marked;
+    }
+
+    if let (ClassificationMode::Relative, Some(statistics)) = (mode, statistics) {
+        if fill_score >= statistics.relative_threshold {
+            return MarkStatus::RelativelyMarked;
+        }
+    }
+
+    if fill_score >= thresholds.marginal {
+        MarkStatus::Marginal
+    } else {
+        MarkStatus::Unmarked
+    }
+}
+
+/// Classifies every scored oval mark on a page, storing the result in each
+/// mark's `status`. Returns the page's fill score statistics, if there are
+/// enough scored ovals to compute them.
+pub fn classify_oval_marks(
+    scored_oval_marks: &mut ScoredOvalMarks,
+    thresholds: &MarkThresholds,
+    mode: ClassificationMode,
+) -> Option<FillScoreStatistics> {
+    let fill_scores = scored_oval_marks
+        .iter()
+        .filter_map(|(_, oval_mark)| oval_mark.scored().map(effective_fill_score))
+        .collect::<Vec<_>>();
+    let statistics = compute_fill_score_statistics(&fill_scores);
+
+    for (_, oval_mark) in scored_oval_marks.iter_mut() {
+        if let OvalMark::Scored(scored_oval_mark) = oval_mark {
+            scored_oval_mark.status = Some(classify_fill_score(
+                effective_fill_score(scored_oval_mark),
+                thresholds,
+                mode,
+                statistics.as_ref(),
+            ));
+        }
+    }
+
+    statistics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A page marked with a light pencil: most ovals are nearly empty, and the
+    /// three marks are clearly darker but below the definite threshold.
+    const FAINT_PAGE: [f32; 12] = [
+        0.004, 0.006, 0.005, 0.041, 0.003, 0.007, 0.045, 0.005, 0.004, 0.038, 0.006, 0.005,
+    ];
+
+    #[test]
+    fn test_fill_score_statistics() {
+        let statistics = compute_fill_score_statistics(&FAINT_PAGE).unwrap();
+        assert_eq!(statistics.count, 12);
+        assert_eq!(statistics.min, 0.003);
+        assert_eq!(statistics.max, 0.045);
+        assert_eq!(statistics.median, 0.006);
+        assert!((statistics.median_absolute_deviation - 0.002).abs() < 1e-6);
+        assert!((statistics.relative_threshold - 0.026).abs() < 1e-6);
+
+        assert_eq!(compute_fill_score_statistics(&FAINT_PAGE[..3]), None);
+    }
+
+    #[test]
+    fn test_absolute_classification_misses_faint_marks() {
+        let statistics = compute_fill_score_statistics(&FAINT_PAGE);
+        let statuses = FAINT_PAGE
+            .iter()
+            .map(|fill_score| {
+                classify_fill_score(
+                    *fill_score,
+                    &DEFAULT_MARK_THRESHOLDS,
+                    ClassificationMode::Absolute,
+                    statistics.as_ref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert!(statuses
+            .iter()
+            .all(|status| *status == MarkStatus::Unmarked));
+    }
+
+    #[test]
+    fn test_relative_classification_finds_faint_marks() {
+        let statistics = compute_fill_score_statistics(&FAINT_PAGE);
+        let marked = FAINT_PAGE
+            .iter()
+            .map(|fill_score| {
+                classify_fill_score(
+                    *fill_score,
+                    &DEFAULT_MARK_THRESHOLDS,
+                    ClassificationMode::Relative,
+                    statistics.as_ref(),
+                )
+            })
+            .enumerate()
+            .filter(|(_, status)| *status == MarkStatus::RelativelyMarked)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        assert_eq!(marked, vec![3, 6, 9]);
+        assert!(MarkStatus::RelativelyMarked.is_marked());
+        assert!(MarkStatus::RelativelyMarked.needs_review());
+    }
+
+    #[test]
+    fn test_relative_classification_ignores_clean_pages() {
+        // a page with no marks at all and almost no variation should not turn
+        // the slightly darkest oval into a mark
+        let fill_scores = [0.004, 0.005, 0.005, 0.006, 0.005, 0.012];
+        let statistics = compute_fill_score_statistics(&fill_scores);
+        for fill_score in fill_scores {
+            assert_eq!(
+                classify_fill_score(
+                    fill_score,
+                    &DEFAULT_MARK_THRESHOLDS,
+                    ClassificationMode::Relative,
+                    statistics.as_ref(),
+                ),
+                MarkStatus::Unmarked
+            );
+        }
+    }
+
+    #[test]
+    fn test_definite_and_marginal_thresholds() {
+        let thresholds = MarkThresholds {
+            definite: 0.2,
+            marginal: 0.1,
+        };
+        for (fill_score, expected) in [
+            (0.5, MarkStatus::Marked),
+            (0.2, MarkStatus::Marked),
+            (0.15, MarkStatus::Marginal),
+            (0.05, MarkStatus::Unmarked),
+        ] {
+            assert_eq!(
+                classify_fill_score(fill_score, &thresholds, ClassificationMode::Absolute, None),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_classification_mode() {
+        assert_eq!("absolute".parse(), Ok(ClassificationMode::Absolute));
+        assert_eq!("relative".parse(), Ok(ClassificationMode::Relative));
+        assert!("other".parse::<ClassificationMode>().is_err());
+    }
+}
@@ -0,0 +1,364 @@
+use image::{GenericImageView, GrayImage, Luma};
+use serde::Serialize;
+
+use crate::{geometry::Rect, image_utils::WHITE};
+
+/// How much darker than the paper a pixel must be to count as ink. This is
+/// low enough to include the gray residue left by erasing a pencil mark.
+pub const INK_MINIMUM_CONTRAST: u8 = 32;
+
+/// The number of rows and columns the oval interior is divided into when
+/// measuring how evenly ink covers it.
+const COVERAGE_GRID_SIZE: usize = 3;
+
+/// A mark covering at least this much of the oval interior with ink lighter
+/// than `ERASURE_MAXIMUM_INK_DARKNESS` looks like an erased mark.
+const ERASURE_MINIMUM_INK_COVERAGE: f32 = 0.3;
+const ERASURE_MAXIMUM_INK_DARKNESS: f32 = 0.45;
+
+/// A dot covering at least this much of the center of the oval interior,
+/// with at most `HESITATION_MAXIMUM_OUTER_COVERAGE` of the rest covered, looks
+/// like the voter rested their pen in the oval without filling it in.
+const HESITATION_MINIMUM_CENTER_COVERAGE: f32 = 0.2;
+const HESITATION_MAXIMUM_OUTER_COVERAGE: f32 = 0.05;
+
+/// Measurements of the ink inside an oval that help tell deliberate marks
+/// from erasures and stray pen contact.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkFeatures {
+    /// The ratio of pixels inside the oval that have any ink on them,
+    /// including light ink that does not survive binarization.
+    pub ink_coverage: f32,
+
+    /// The ink coverage of each cell when the inside of the oval is divided
+    /// into a 3x3 grid, in row-major order. A solid mark covers every cell
+    /// evenly, whereas a check mark or dot covers only some of them.
+    pub cell_ink_coverage: [f32; COVERAGE_GRID_SIZE * COVERAGE_GRID_SIZE],
+
+    /// The average darkness of the ink pixels, from 0 (as light as the paper)
+    /// to 1 (black). Erased marks leave light gray residue.
+    pub mean_ink_darkness: f32,
+
+    /// Whether the only ink in the oval is a small dot near its center.
+    pub has_central_dot: bool,
+}
+
+/// A pattern in a mark suggesting the voter's intent is unclear, so that it
+/// can be reviewed during adjudication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkAnomaly {
+    /// Much of the oval is covered by light ink, as if a mark was erased.
+    PossibleErasure,
+
+    /// There is a small dot in the middle of the oval, as if the voter
+    /// started to mark it and stopped.
+    HesitationMark,
+}
+
+impl MarkFeatures {
+    /// Determines whether these features suggest an erasure or hesitation.
+    pub fn anomaly(&self) -> Option<MarkAnomaly> {
+        if self.has_central_dot {
+            Some(MarkAnomaly::HesitationMark)
+        } else if self.ink_coverage >= ERASURE_MINIMUM_INK_COVERAGE
+            && self.mean_ink_darkness < ERASURE_MAXIMUM_INK_DARKNESS
+        {
+            Some(MarkAnomaly::PossibleErasure)
+        } else {
+            None
+        }
+    }
+}
+
+/// Computes the mark features of `source_image`, the scan of an oval, from the
+/// pixels at `interior`, the inside of the template it was matched against as
+/// found by `find_template_interior`. `paper` is the luma of the paper around
+/// the oval, from `paper_luma_around`.
+pub fn compute_mark_features(
+    source_image: &impl GenericImageView<Pixel = Luma<u8>>,
+    interior: &[(u32, u32)],
+    paper: u8,
+) -> MarkFeatures {
+    let ink_darkness = |x: u32, y: u32| {
+        let luma = source_image.get_pixel(x, y).0[0];
+        if paper.saturating_sub(luma) >= INK_MINIMUM_CONTRAST {
+            Some(f32::from(paper - luma) / f32::from(paper))
+        } else {
+            None
+        }
+    };
+
+    let (min_x, min_y, max_x, max_y) = interior.iter().fold(
+        (u32::MAX, u32::MAX, 0, 0),
+        |(min_x, min_y, max_x, max_y), &(x, y)| {
+            (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+        },
+    );
+    let cell_for = |x: u32, y: u32| {
+        let column = ((x - min_x) as usize * COVERAGE_GRID_SIZE) / (max_x - min_x + 1) as usize;
+        let row = ((y - min_y) as usize * COVERAGE_GRID_SIZE) / (max_y - min_y + 1) as usize;
+        row * COVERAGE_GRID_SIZE + column
+    };
+
+    let mut cell_pixels = [0u32; COVERAGE_GRID_SIZE * COVERAGE_GRID_SIZE];
+    let mut cell_ink_pixels = [0u32; COVERAGE_GRID_SIZE * COVERAGE_GRID_SIZE];
+    let mut total_darkness = 0.0;
+
+    for &(x, y) in interior {
+        let cell = cell_for(x, y);
+        cell_pixels[cell] += 1;
+        if let Some(darkness) = ink_darkness(x, y) {
+            cell_ink_pixels[cell] += 1;
+            total_darkness += darkness;
+        }
+    }
+
+    let ink_pixels = cell_ink_pixels.iter().sum::<u32>();
+    let cell_ink_coverage = std::array::from_fn(|cell| {
+        if cell_pixels[cell] == 0 {
+            0.0
+        } else {
+            cell_ink_pixels[cell] as f32 / cell_pixels[cell] as f32
+        }
+    });
+
+    let center_cell = COVERAGE_GRID_SIZE * COVERAGE_GRID_SIZE / 2;
+    let outer_pixels = interior.len() as u32 - cell_pixels[center_cell];
+    let outer_ink_pixels = ink_pixels - cell_ink_pixels[center_cell];
+    let has_central_dot = outer_pixels > 0
+        && cell_ink_coverage[center_cell] >= HESITATION_MINIMUM_CENTER_COVERAGE
+        && (outer_ink_pixels as f32 / outer_pixels as f32) <= HESITATION_MAXIMUM_OUTER_COVERAGE;
+
+    MarkFeatures {
+        ink_coverage: if interior.is_empty() {
+            0.0
+        } else {
+            ink_pixels as f32 / interior.len() as f32
+        },
+        cell_ink_coverage,
+        mean_ink_darkness: if ink_pixels == 0 {
+            0.0
+        } else {
+            total_darkness / ink_pixels as f32
+        },
+        has_central_dot,
+    }
+}
+
+/// Finds the white pixels of a binarized template enclosed by its outline,
+/// i.e. those not connected to the edge of the template. Templates without an
+/// enclosed area, such as arrows, use all of their white pixels instead.
+pub fn find_template_interior(template: &GrayImage) -> Vec<(u32, u32)> {
+    let (width, height) = template.dimensions();
+    let is_white = |x: u32, y: u32| template.get_pixel(x, y) == &WHITE;
+    let mut exterior = vec![false; (width * height) as usize];
+    let mut stack = (0..width)
+        .flat_map(|x| [(x, 0), (x, height.saturating_sub(1))])
+        .chain((0..height).flat_map(|y| [(0, y), (width.saturating_sub(1), y)]))
+        .filter(|&(x, y)| x < width && y < height)
+        .collect::<Vec<_>>();
+
+    while let Some((x, y)) = stack.pop() {
+        let index = (y * width + x) as usize;
+        if exterior[index] || !is_white(x, y) {
+            continue;
+        }
+
+        exterior[index] = true;
+        if x > 0 {
+            stack.push((x - 1, y));
+        }
+        if x + 1 < width {
+            stack.push((x + 1, y));
+        }
+        if y > 0 {
+            stack.push((x, y - 1));
+        }
+        if y + 1 < height {
+            stack.push((x, y + 1));
+        }
+    }
+
+    let white_pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter(|&(x, y)| is_white(x, y));
+    let interior = white_pixels
+        .clone()
+        .filter(|&(x, y)| !exterior[(y * width + x) as usize])
+        .collect::<Vec<_>>();
+
+    if interior.is_empty() {
+        white_pixels.collect()
+    } else {
+        interior
+    }
+}
+
+/// Estimates the brightness of the paper in an image as the 90th percentile
+/// luma, which ignores the ink and any specks brighter than the paper.
+pub fn paper_luma(img: &GrayImage) -> u8 {
+    percentile_luma(img.pixels().map(|pixel| pixel.0[0]), 9, 10).unwrap_or(u8::MAX)
+}
+
+/// Estimates the brightness of the paper around an oval from `img`, a scan of
+/// the oval and a margin around it, leaving out the oval at `oval_bounds`
+/// within `img`. A voter may fill the whole oval with ink, so the paper has
+/// to be sampled from outside it.
+pub fn paper_luma_around(img: &impl GenericImageView<Pixel = Luma<u8>>, oval_bounds: &Rect) -> u8 {
+    let margin = img
+        .pixels()
+        .filter(|(x, y, _)| !oval_bounds.contains(*x as i32, *y as i32))
+        .map(|(_, _, pixel)| pixel.0[0]);
+    percentile_luma(margin, 9, 10)
+        .or_else(|| percentile_luma(img.pixels().map(|(_, _, pixel)| pixel.0[0]), 9, 10))
+        .unwrap_or(u8::MAX)
+}
+
+/// Finds the luma that `numerator / denominator` of `lumas` are at or below,
+/// if there are any.
+fn percentile_luma(
+    lumas: impl Iterator<Item = u8>,
+    numerator: u32,
+    denominator: u32,
+) -> Option<u8> {
+    let mut histogram = [0u32; 256];
+    let mut total = 0;
+    for luma in lumas {
+        histogram[luma as usize] += 1;
+        total += 1;
+    }
+    if total == 0 {
+        return None;
+    }
+
+    let target = total * numerator / denominator;
+    let mut count = 0;
+    for (luma, pixels) in histogram.iter().enumerate() {
+        count += pixels;
+        if count > target {
+            return Some(luma as u8);
+        }
+    }
+    Some(u8::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+    use imageproc::{
+        drawing::{draw_filled_ellipse_mut, draw_hollow_ellipse_mut},
+        rect::Rect as ImageprocRect,
+    };
+
+    use super::*;
+    use crate::image_utils::BLACK;
+
+    const CENTER: (i32, i32) = (20, 13);
+
+    fn oval_template() -> GrayImage {
+        let mut template = GrayImage::from_pixel(40, 26, WHITE);
+        draw_hollow_ellipse_mut(&mut template, CENTER, 18, 11, BLACK);
+        draw_hollow_ellipse_mut(&mut template, CENTER, 17, 10, BLACK);
+        template
+    }
+
+    /// An oval printed on slightly off-white paper with the inside filled
+    /// with the given luma, if any.
+    fn oval_scan(fill: Option<u8>) -> GrayImage {
+        let mut img = GrayImage::from_pixel(40, 26, Luma([235]));
+        draw_hollow_ellipse_mut(&mut img, CENTER, 18, 11, Luma([30]));
+        draw_hollow_ellipse_mut(&mut img, CENTER, 17, 10, Luma([30]));
+        if let Some(fill) = fill {
+            draw_filled_ellipse_mut(&mut img, CENTER, 16, 9, Luma([fill]));
+        }
+        img
+    }
+
+    #[test]
+    fn test_template_interior() {
+        let interior = find_template_interior(&oval_template());
+        assert!(interior.contains(&(20, 13)));
+        assert!(!interior.contains(&(0, 0)));
+        assert!(!interior.contains(&(39, 25)));
+        assert!(interior.len() > 300);
+    }
+
+    fn features(img: &GrayImage) -> MarkFeatures {
+        compute_mark_features(img, &find_template_interior(&oval_template()), 235)
+    }
+
+    #[test]
+    fn test_unmarked_oval() {
+        let features = features(&oval_scan(None));
+        assert!(features.ink_coverage < 0.05, "{features:?}");
+        assert!(!features.has_central_dot);
+        assert_eq!(features.anomaly(), None);
+    }
+
+    #[test]
+    fn test_solid_mark() {
+        let features = features(&oval_scan(Some(50)));
+        assert!(features.ink_coverage > 0.9, "{features:?}");
+        assert!(features.mean_ink_darkness > 0.7, "{features:?}");
+        assert!(features
+            .cell_ink_coverage
+            .iter()
+            .all(|coverage| *coverage > 0.5));
+        assert_eq!(features.anomaly(), None);
+    }
+
+    #[test]
+    fn test_erased_mark() {
+        let features = features(&oval_scan(Some(175)));
+        assert!(features.ink_coverage > 0.9, "{features:?}");
+        assert!(features.mean_ink_darkness < 0.3, "{features:?}");
+        assert_eq!(features.anomaly(), Some(MarkAnomaly::PossibleErasure));
+    }
+
+    #[test]
+    fn test_hesitation_mark() {
+        let mut img = oval_scan(None);
+        imageproc::drawing::draw_filled_rect_mut(
+            &mut img,
+            ImageprocRect::at(18, 11).of_size(5, 4),
+            Luma([40]),
+        );
+
+        let features = features(&img);
+        assert!(features.has_central_dot, "{features:?}");
+        assert!(features.ink_coverage < 0.1, "{features:?}");
+        assert_eq!(features.anomaly(), Some(MarkAnomaly::HesitationMark));
+    }
+
+    #[test]
+    fn test_overfilled_mark() {
+        // an oval with a margin around it, filled in past its outline
+        let mut img = GrayImage::from_pixel(60, 46, Luma([235]));
+        draw_filled_ellipse_mut(&mut img, (30, 23), 23, 15, Luma([50]));
+        let oval_bounds = Rect::new(10, 10, 40, 26);
+        let oval = image::imageops::crop_imm(&img, 10, 10, 40, 26).to_image();
+
+        // the oval alone is all ink, with no paper to compare it with
+        assert_eq!(paper_luma(&oval), 50);
+        let paper = paper_luma_around(&img, &oval_bounds);
+        assert_eq!(paper, 235);
+
+        let features =
+            compute_mark_features(&oval, &find_template_interior(&oval_template()), paper);
+        assert!(features.ink_coverage > 0.9, "{features:?}");
+        assert!(features.mean_ink_darkness > 0.7, "{features:?}");
+        assert_eq!(features.anomaly(), None);
+    }
+
+    #[test]
+    fn test_paper_luma_ignores_ink() {
+        let mut img = GrayImage::from_pixel(10, 10, Luma([230]));
+        for x in 0..10 {
+            img.put_pixel(x, 0, Luma([0]));
+        }
+        img.put_pixel(5, 5, Luma([255]));
+        assert_eq!(paper_luma(&img), 230);
+    }
+}
@@ -0,0 +1,533 @@
+use std::f32::consts::PI;
+
+use image::{GenericImageView, GrayImage, Luma};
+use serde::Serialize;
+
+use crate::{
+    image_utils::BLACK,
+    mark_features::{MarkFeatures, INK_MINIMUM_CONTRAST},
+};
+
+/// How far beyond the oval, as a fraction of its height, to look for the
+/// strokes of a check or X. Voters rarely keep these inside the oval.
+pub const MARK_SHAPE_MARGIN_RATIO: f32 = 0.4;
+
+/// Ovals with at least this much of their inside covered by ink are filled.
+const FILLED_MINIMUM_INK_COVERAGE: f32 = 0.5;
+
+/// The number of angles tried when looking for strokes, i.e. a resolution of
+/// 2 degrees.
+const STROKE_ANGLE_STEPS: usize = 90;
+
+/// The maximum distance from a stroke's center line for an ink pixel to count
+/// as part of the stroke.
+const STROKE_HALF_WIDTH: f32 = 3.0;
+
+/// Strokes must be at least this steep and no steeper than
+/// `MAXIMUM_STROKE_SLOPE_DEGREES` to be part of a check or X.
+const MINIMUM_STROKE_SLOPE_DEGREES: f32 = 15.0;
+const MAXIMUM_STROKE_SLOPE_DEGREES: f32 = 80.0;
+
+/// The shorter and longer strokes of a check or X must be at least these
+/// fractions of the oval's height long.
+const MINIMUM_SHORT_STROKE_LENGTH_RATIO: f32 = 0.3;
+const MINIMUM_LONG_STROKE_LENGTH_RATIO: f32 = 0.6;
+
+/// The fraction of the ink that must be part of the two strokes for a mark to
+/// be considered a check or X rather than a scribble.
+const MINIMUM_EXPLAINED_INK_RATIO: f32 = 0.75;
+
+/// The strokes of an X cross within this fraction of each end of both strokes
+/// from their middles, while the strokes of a check meet near one end.
+const CROSSING_POSITION_TOLERANCE: f32 = 0.3;
+
+/// The overall shape of the ink in and around an oval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkType {
+    /// There is no ink in or around the oval.
+    Blank,
+
+    /// The oval is mostly filled in.
+    Filled,
+
+    /// Two strokes meeting at the bottom in a V, with one usually longer.
+    Check,
+
+    /// Two strokes crossing near their middles.
+    X,
+
+    /// Ink that isn't any of the other shapes, e.g. a partial fill, a dot, or
+    /// a scribble.
+    Other,
+}
+
+/// A straight stroke of ink, described by the line through its center in
+/// normal form, `x * cos(angle) + y * sin(angle) = distance`, and the extent
+/// of its ink along that line.
+#[derive(Debug, Clone, Copy)]
+struct Stroke {
+    angle: f32,
+    distance: f32,
+    start: f32,
+    end: f32,
+    pixel_count: usize,
+}
+
+impl Stroke {
+    fn direction(&self) -> (f32, f32) {
+        (-self.angle.sin(), self.angle.cos())
+    }
+
+    const fn length(&self) -> f32 {
+        self.end - self.start
+    }
+
+    /// The angle of the stroke from horizontal, from 0 to 90 degrees.
+    fn slope_degrees(&self) -> f32 {
+        let (dx, dy) = self.direction();
+        dy.abs().atan2(dx.abs()).to_degrees()
+    }
+
+    /// Whether the stroke goes down to the right (`\`) rather than up to the
+    /// right (`/`).
+    fn is_descending(&self) -> bool {
+        let (dx, dy) = self.direction();
+        dx * dy > 0.0
+    }
+
+    /// The position of `point` along the stroke, from 0 at its start to 1 at
+    /// its end.
+    fn relative_position(&self, (x, y): (f32, f32)) -> f32 {
+        let (dx, dy) = self.direction();
+        (x.mul_add(dx, y * dy) - self.start) / self.length()
+    }
+
+    /// The point on the stroke's line at `position` along it.
+    fn point_at(&self, position: f32) -> (f32, f32) {
+        let (dx, dy) = self.direction();
+        let t = position.mul_add(self.length(), self.start);
+        let (nx, ny) = (self.angle.cos(), self.angle.sin());
+        (
+            nx.mul_add(self.distance, dx * t),
+            ny.mul_add(self.distance, dy * t),
+        )
+    }
+
+    /// The point where the lines through two strokes cross, if they aren't
+    /// parallel.
+    fn intersection(&self, other: &Self) -> Option<(f32, f32)> {
+        let determinant = (other.angle - self.angle).sin();
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let x = self
+            .distance
+            .mul_add(other.angle.sin(), -other.distance * self.angle.sin())
+            / determinant;
+        let y = other
+            .distance
+            .mul_add(self.angle.cos(), -self.distance * other.angle.cos())
+            / determinant;
+        Some((x, y))
+    }
+}
+
+/// Recognizes the type of mark in `img`, a scan of an oval and the margin
+/// around it. `template` is the binarized template the oval was matched
+/// against, located at `template_origin` within `img`, `paper` is the luma of
+/// the paper around it, and `features` are the oval's mark features.
+pub fn recognize_mark_type(
+    img: &impl GenericImageView<Pixel = Luma<u8>>,
+    template: &GrayImage,
+    template_origin: (u32, u32),
+    paper: u8,
+    features: &MarkFeatures,
+) -> MarkType {
+    if features.ink_coverage >= FILLED_MINIMUM_INK_COVERAGE {
+        return MarkType::Filled;
+    }
+
+    let ink = find_voter_ink(img, template, template_origin, paper);
+    let oval_height = template.height() as f32;
+    if (ink.len() as f32) < oval_height / 2.0 {
+        return MarkType::Blank;
+    }
+
+    let Some(first) = find_stroke(&ink, None) else {
+        return MarkType::Other;
+    };
+    let remaining_ink = ink
+        .iter()
+        .copied()
+        .filter(|point| distance_from_line(*point, first.angle, first.distance) > STROKE_HALF_WIDTH)
+        .collect::<Vec<_>>();
+    let Some(second) = find_stroke(&remaining_ink, Some(first.angle)) else {
+        return MarkType::Other;
+    };
+
+    let explained_ink = first.pixel_count + second.pixel_count;
+    if (explained_ink as f32) < MINIMUM_EXPLAINED_INK_RATIO * ink.len() as f32 {
+        return MarkType::Other;
+    }
+
+    classify_strokes(&first, &second, oval_height)
+}
+
+/// Determines whether two strokes form a check or an X.
+fn classify_strokes(first: &Stroke, second: &Stroke, oval_height: f32) -> MarkType {
+    let is_diagonal = |stroke: &Stroke| {
+        (MINIMUM_STROKE_SLOPE_DEGREES..=MAXIMUM_STROKE_SLOPE_DEGREES)
+            .contains(&stroke.slope_degrees())
+    };
+    if !is_diagonal(first)
+        || !is_diagonal(second)
+        || first.is_descending() == second.is_descending()
+    {
+        return MarkType::Other;
+    }
+
+    let (short, long) = if first.length() < second.length() {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    if short.length() < MINIMUM_SHORT_STROKE_LENGTH_RATIO * oval_height
+        || long.length() < MINIMUM_LONG_STROKE_LENGTH_RATIO * oval_height
+    {
+        return MarkType::Other;
+    }
+
+    let Some(crossing) = first.intersection(second) else {
+        return MarkType::Other;
+    };
+    let positions = [
+        first.relative_position(crossing),
+        second.relative_position(crossing),
+    ];
+
+    let is_near_middle =
+        |position: f32| (position - 0.5).abs() <= 0.5 - CROSSING_POSITION_TOLERANCE;
+    if positions.into_iter().all(is_near_middle) {
+        return MarkType::X;
+    }
+
+    // the strokes of a check meet at or just past one end of each, and that
+    // end is the bottom of both strokes. the second stroke is found after
+    // removing the ink of the first, so it may stop a stroke's width short of
+    // where they meet.
+    let is_near_end = |stroke: &Stroke, position: f32| {
+        let tolerance = CROSSING_POSITION_TOLERANCE + 2.0 * STROKE_HALF_WIDTH / stroke.length();
+        (-tolerance..=CROSSING_POSITION_TOLERANCE).contains(&position)
+            || (1.0 - CROSSING_POSITION_TOLERANCE..=1.0 + tolerance).contains(&position)
+    };
+    let is_bottom_of = |stroke: &Stroke| {
+        let middle = stroke.point_at(0.5);
+        crossing.1 > middle.1
+    };
+    if is_near_end(first, positions[0])
+        && is_near_end(second, positions[1])
+        && is_bottom_of(first)
+        && is_bottom_of(second)
+    {
+        return MarkType::Check;
+    }
+
+    MarkType::Other
+}
+
+/// Finds the ink pixels in `img` that aren't part of the printed target, i.e.
+/// those not on or right next to the template's outline. The strokes of a
+/// check or X run into the oval, so only ink connected to the oval counts.
+/// This leaves out printed content in the margin, like the option's name,
+/// which is printed apart from the oval.
+fn find_voter_ink(
+    img: &impl GenericImageView<Pixel = Luma<u8>>,
+    template: &GrayImage,
+    template_origin: (u32, u32),
+    paper: u8,
+) -> Vec<(f32, f32)> {
+    let (origin_x, origin_y) = (template_origin.0 as i64, template_origin.1 as i64);
+    let is_printed = |x: u32, y: u32| {
+        (-1..=1).any(|dy| {
+            (-1..=1).any(|dx| {
+                let template_x = x as i64 + dx - origin_x;
+                let template_y = y as i64 + dy - origin_y;
+                template_x >= 0
+                    && template_y >= 0
+                    && template_x < template.width() as i64
+                    && template_y < template.height() as i64
+                    && template.get_pixel(template_x as u32, template_y as u32) == &BLACK
+            })
+        })
+    };
+
+    let (width, height) = img.dimensions();
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+    let mut is_dark = vec![false; (width * height) as usize];
+    for (x, y, luma) in img.pixels() {
+        is_dark[index(x, y)] = paper.saturating_sub(luma.0[0]) >= INK_MINIMUM_CONTRAST;
+    }
+
+    // flood fill the dark pixels, including the printed outline, from those
+    // within the oval's bounds
+    let in_oval_bounds = |x: u32, y: u32| {
+        (x as i64 - origin_x) < template.width() as i64
+            && (y as i64 - origin_y) < template.height() as i64
+            && x as i64 >= origin_x
+            && y as i64 >= origin_y
+    };
+    let mut is_connected = vec![false; is_dark.len()];
+    let mut stack = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            if is_dark[index(x, y)] && in_oval_bounds(x, y) {
+                is_connected[index(x, y)] = true;
+                stack.push((x, y));
+            }
+        }
+    }
+    while let Some((x, y)) = stack.pop() {
+        for (dx, dy) in [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ] {
+            let (neighbor_x, neighbor_y) = (x as i64 + dx, y as i64 + dy);
+            if neighbor_x < 0
+                || neighbor_y < 0
+                || neighbor_x >= width as i64
+                || neighbor_y >= height as i64
+            {
+                continue;
+            }
+            let (neighbor_x, neighbor_y) = (neighbor_x as u32, neighbor_y as u32);
+            let neighbor = index(neighbor_x, neighbor_y);
+            if is_dark[neighbor] && !is_connected[neighbor] {
+                is_connected[neighbor] = true;
+                stack.push((neighbor_x, neighbor_y));
+            }
+        }
+    }
+
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter(|&(x, y)| is_connected[index(x, y)] && !is_printed(x, y))
+        .map(|(x, y)| (x as f32, y as f32))
+        .collect()
+}
+
+fn distance_from_line((x, y): (f32, f32), angle: f32, distance: f32) -> f32 {
+    (x.mul_add(angle.cos(), y * angle.sin()) - distance).abs()
+}
+
+/// Finds the straight stroke covering the most ink pixels using a Hough
+/// transform. If `excluded_angle` is given, strokes within 20 degrees of it
+/// are skipped so that the remains of a thick stroke aren't found again.
+fn find_stroke(ink: &[(f32, f32)], excluded_angle: Option<f32>) -> Option<Stroke> {
+    let max_distance = ink
+        .iter()
+        .map(|(x, y)| x.hypot(*y))
+        .fold(0.0, f32::max)
+        .ceil();
+    let bin_count = (2.0 * max_distance / STROKE_HALF_WIDTH) as usize + 1;
+
+    let mut best: Option<(f32, f32, usize)> = None;
+    for step in 0..STROKE_ANGLE_STEPS {
+        let angle = step as f32 * PI / STROKE_ANGLE_STEPS as f32;
+        if let Some(excluded_angle) = excluded_angle {
+            let difference = (angle - excluded_angle).abs();
+            if difference.min(PI - difference) < 20f32.to_radians() {
+                continue;
+            }
+        }
+
+        let (cos, sin) = (angle.cos(), angle.sin());
+        let mut bins = vec![0usize; bin_count];
+        for (x, y) in ink {
+            let bin = ((x.mul_add(cos, y * sin) + max_distance) / STROKE_HALF_WIDTH) as usize;
+            bins[bin.min(bin_count - 1)] += 1;
+        }
+
+        // count pixels in neighboring bins too, since a stroke's center line
+        // can fall on a bin boundary
+        for bin in 0..bin_count {
+            let votes = bins[bin]
+                + if bin + 1 < bin_count {
+                    bins[bin + 1]
+                } else {
+                    0
+                };
+            if best.is_none_or(|(_, _, best_votes)| votes > best_votes) {
+                let distance = (bin + 1) as f32 * STROKE_HALF_WIDTH - max_distance;
+                best = Some((angle, distance, votes));
+            }
+        }
+    }
+
+    let (angle, distance, _) = best?;
+
+    // refine the line to the middle of the pixels near it
+    let members = ink
+        .iter()
+        .filter(|point| distance_from_line(**point, angle, distance) <= STROKE_HALF_WIDTH)
+        .collect::<Vec<_>>();
+    if members.is_empty() {
+        return None;
+    }
+    let distance = members
+        .iter()
+        .map(|(x, y)| x.mul_add(angle.cos(), y * angle.sin()))
+        .sum::<f32>()
+        / members.len() as f32;
+    let members = ink
+        .iter()
+        .filter(|point| distance_from_line(**point, angle, distance) <= STROKE_HALF_WIDTH)
+        .collect::<Vec<_>>();
+
+    let (dx, dy) = (-angle.sin(), angle.cos());
+    let mut positions = members
+        .iter()
+        .map(|(x, y)| x.mul_add(dx, y * dy))
+        .collect::<Vec<_>>();
+    positions.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    // ignore a few outlying pixels at each end
+    let trim = positions.len() / 20;
+    Some(Stroke {
+        angle,
+        distance,
+        start: *positions.get(trim)?,
+        end: positions[positions.len() - 1 - trim],
+        pixel_count: members.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+    use imageproc::drawing::{
+        draw_filled_ellipse_mut, draw_hollow_ellipse_mut, draw_line_segment_mut,
+    };
+
+    use super::*;
+    use crate::{
+        geometry::Rect,
+        image_utils::WHITE,
+        mark_features::{compute_mark_features, find_template_interior, paper_luma_around},
+    };
+
+    const ORIGIN: (u32, u32) = (16, 16);
+    const TEMPLATE_SIZE: (u32, u32) = (40, 26);
+
+    fn oval_template() -> GrayImage {
+        let mut template = GrayImage::from_pixel(TEMPLATE_SIZE.0, TEMPLATE_SIZE.1, WHITE);
+        draw_hollow_ellipse_mut(&mut template, (20, 13), 18, 11, BLACK);
+        draw_hollow_ellipse_mut(&mut template, (20, 13), 17, 10, BLACK);
+        template
+    }
+
+    /// An oval and its margin, with the oval's center at (36, 29).
+    fn oval_scan() -> GrayImage {
+        let mut img = GrayImage::from_pixel(72, 58, Luma([240]));
+        draw_hollow_ellipse_mut(&mut img, (36, 29), 18, 11, Luma([30]));
+        draw_hollow_ellipse_mut(&mut img, (36, 29), 17, 10, Luma([30]));
+        img
+    }
+
+    /// Draws a pen stroke three pixels thick.
+    fn draw_stroke(img: &mut GrayImage, start: (f32, f32), end: (f32, f32)) {
+        for offset in [-1.0, 0.0, 1.0] {
+            draw_line_segment_mut(
+                img,
+                (start.0 + offset, start.1),
+                (end.0 + offset, end.1),
+                Luma([50]),
+            );
+        }
+    }
+
+    fn recognize(img: &GrayImage) -> MarkType {
+        let template = oval_template();
+        let (width, height) = template.dimensions();
+        let oval = image::imageops::crop_imm(img, ORIGIN.0, ORIGIN.1, width, height).to_image();
+        let paper = paper_luma_around(
+            img,
+            &Rect::new(ORIGIN.0 as i32, ORIGIN.1 as i32, width, height),
+        );
+        let features = compute_mark_features(&oval, &find_template_interior(&template), paper);
+        recognize_mark_type(img, &template, ORIGIN, paper, &features)
+    }
+
+    #[test]
+    fn test_blank_oval() {
+        assert_eq!(recognize(&oval_scan()), MarkType::Blank);
+    }
+
+    #[test]
+    fn test_filled_oval() {
+        let mut img = oval_scan();
+        draw_filled_ellipse_mut(&mut img, (36, 29), 16, 9, Luma([40]));
+        assert_eq!(recognize(&img), MarkType::Filled);
+    }
+
+    #[test]
+    fn test_x_mark() {
+        let mut img = oval_scan();
+        draw_stroke(&mut img, (20.0, 12.0), (52.0, 46.0));
+        draw_stroke(&mut img, (20.0, 46.0), (52.0, 12.0));
+        assert_eq!(recognize(&img), MarkType::X);
+    }
+
+    #[test]
+    fn test_check_mark() {
+        let mut img = oval_scan();
+        draw_stroke(&mut img, (22.0, 24.0), (32.0, 38.0));
+        draw_stroke(&mut img, (32.0, 38.0), (58.0, 6.0));
+        assert_eq!(recognize(&img), MarkType::Check);
+    }
+
+    /// Prints an X and a V to the right of the oval, like the start of an
+    /// option's name, leaving a gap between them and the oval.
+    fn draw_printed_text(img: &mut GrayImage) {
+        for offset in [0.0, 1.0] {
+            draw_line_segment_mut(img, (58.0 + offset, 22.0), (64.0 + offset, 36.0), BLACK);
+            draw_line_segment_mut(img, (58.0 + offset, 36.0), (64.0 + offset, 22.0), BLACK);
+            draw_line_segment_mut(img, (65.0 + offset, 22.0), (68.0 + offset, 36.0), BLACK);
+            draw_line_segment_mut(img, (68.0 + offset, 36.0), (71.0 + offset, 22.0), BLACK);
+        }
+    }
+
+    #[test]
+    fn test_printed_text_beside_oval_is_not_voter_ink() {
+        let mut img = oval_scan();
+        draw_printed_text(&mut img);
+        assert_eq!(recognize(&img), MarkType::Blank);
+
+        draw_stroke(&mut img, (22.0, 24.0), (32.0, 38.0));
+        draw_stroke(&mut img, (32.0, 38.0), (58.0, 6.0));
+        assert_eq!(recognize(&img), MarkType::Check);
+    }
+
+    #[test]
+    fn test_horizontal_line_is_other() {
+        let mut img = oval_scan();
+        draw_stroke(&mut img, (14.0, 29.0), (58.0, 29.0));
+        draw_stroke(&mut img, (14.0, 30.0), (58.0, 30.0));
+        assert_eq!(recognize(&img), MarkType::Other);
+    }
+
+    #[test]
+    fn test_inverted_v_is_not_a_check() {
+        let mut img = oval_scan();
+        draw_stroke(&mut img, (22.0, 40.0), (34.0, 14.0));
+        draw_stroke(&mut img, (34.0, 14.0), (50.0, 40.0));
+        assert_eq!(recognize(&img), MarkType::Other);
+    }
+}
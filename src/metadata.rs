@@ -3,13 +3,17 @@ use std::fmt::{Debug, Formatter};
 use serde::Serialize;
 
 use crate::{
-    geometry::Rect,
-    timing_marks::{Complete, Partial},
+    ballot_card::BorderEdge,
+    timing_marks::{Complete, Partial, TimingMark},
 };
 
 /// Expected number of metadata bits encoded in the bottom row of a ballot card.
 pub const METADATA_BITS: usize = 32;
 
+/// Minimum number of timing marks along the metadata edge: one for each bit
+/// plus the corner timing marks at either end.
+pub const MINIMUM_METADATA_TIMING_MARKS: usize = METADATA_BITS + 2;
+
 /// Ending sequence of bits encoded on the back of a ballot card.
 pub const ENDER_CODE: [bool; 11] = [
     false, true, true, true, true, false, true, true, true, true, false,
@@ -174,14 +178,17 @@ pub enum BallotPageMetadataError {
     },
 }
 
-/// Computes the metadata bits from the bottom row of a ballot page.
-pub fn compute_bits_from_bottom_timing_marks(
-    partial_timing_marks: &[Rect],
-    complete_timing_marks: &[Rect],
+/// Computes the metadata bits from the metadata edge of a ballot page. The
+/// bits are read starting from the timing mark next to the last corner, i.e.
+/// right-to-left for a horizontal edge or bottom-to-top for a vertical edge.
+/// Any timing marks beyond the metadata bits are ignored.
+pub fn compute_bits_from_timing_marks(
+    partial_timing_marks: &[TimingMark],
+    complete_timing_marks: &[TimingMark],
 ) -> Result<[bool; METADATA_BITS], BallotPageMetadataError> {
-    if complete_timing_marks.len() != 34 {
+    if complete_timing_marks.len() < MINIMUM_METADATA_TIMING_MARKS {
         return Err(BallotPageMetadataError::InvalidTimingMarkCount {
-            expected: 34,
+            expected: MINIMUM_METADATA_TIMING_MARKS,
             actual: complete_timing_marks.len(),
         });
     }
@@ -209,17 +216,18 @@ pub fn compute_bits_from_bottom_timing_marks(
         };
 
     for bit in &mut bits {
-        if current_complete == current_partial {
+        if current_complete.rect == current_partial.rect {
             *bit = true;
             current_partial = match partial_iter.next() {
                 Some(partial) => partial,
                 None => break,
             };
         }
-        current_complete = complete_iter.next().map_or_else(
-            || unreachable!("There should be 34 complete timing marks."),
-            |complete| complete,
-        );
+        current_complete = complete_iter.next().unwrap_or_else(|| {
+            unreachable!(
+                "There are at least {MINIMUM_METADATA_TIMING_MARKS} complete timing marks on the metadata edge."
+            )
+        });
     }
 
     Ok(bits)
@@ -330,17 +338,35 @@ pub fn decode_back_metadata_from_bits(
     Ok(back_metadata)
 }
 
-/// Decodes the ballot page metadata from the timing marks. Uses the difference
-/// between the partial and complete timing marks to determine the metadata
-/// bits.
+/// Decodes the ballot page metadata from the timing marks on the given edge.
+/// Uses the difference between the partial and complete timing marks to
+/// determine the metadata bits.
 pub fn decode_metadata_from_timing_marks(
     partial_timing_marks: &Partial,
     complete_timing_marks: &Complete,
+    metadata_edge: BorderEdge,
 ) -> Result<BallotPageMetadata, BallotPageMetadataError> {
-    let bits = compute_bits_from_bottom_timing_marks(
-        &partial_timing_marks.bottom_rects,
-        &complete_timing_marks.bottom_rects,
-    )?;
+    let (partial_edge_timing_marks, complete_edge_timing_marks) = match metadata_edge {
+        BorderEdge::Top => (
+            &partial_timing_marks.top_marks,
+            &complete_timing_marks.top_marks,
+        ),
+        BorderEdge::Bottom => (
+            &partial_timing_marks.bottom_marks,
+            &complete_timing_marks.bottom_marks,
+        ),
+        BorderEdge::Left => (
+            &partial_timing_marks.left_marks,
+            &complete_timing_marks.left_marks,
+        ),
+        BorderEdge::Right => (
+            &partial_timing_marks.right_marks,
+            &complete_timing_marks.right_marks,
+        ),
+    };
+
+    let bits =
+        compute_bits_from_timing_marks(partial_edge_timing_marks, complete_edge_timing_marks)?;
 
     let front_metadata_result = decode_front_metadata_from_bits(&bits);
     let back_metadata_result = decode_back_metadata_from_bits(&bits);
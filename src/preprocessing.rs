@@ -0,0 +1,437 @@
+use image::{GrayImage, Luma};
+use imageproc::{
+    contrast::threshold,
+    region_labelling::{connected_components, Connectivity},
+};
+use logging_timer::time;
+use serde::Serialize;
+
+use crate::{
+    binarization::{binarize, BinarizationMethod},
+    election::GridLocation,
+    geometry::Rect,
+    image_utils::WHITE,
+    mark_features::paper_luma,
+    timing_marks::{OvalMark, ScoredOvalMarks},
+};
+
+/// How far to either side of a column or row to look for the background that
+/// a streak is compared against. Streaks wider than this are not detected.
+const STREAK_NEIGHBOR_DISTANCE: u32 = 6;
+
+/// How much darker than the background on both sides of it a pixel must be to
+/// be part of a streak.
+const STREAK_MINIMUM_CONTRAST: u8 = 20;
+
+/// The fraction of a column or row that must be darker than its neighbors for
+/// it to be a streak. Some of a streak is hidden where it crosses printed
+/// content.
+const STREAK_MINIMUM_COVERAGE: f32 = 0.85;
+
+/// The fraction of a column or row at each end of it, i.e. the paper's margin
+/// and the timing marks, that must be mostly darker than its neighbors for it
+/// to be a streak. Printed lines, like the rules between columns of contests,
+/// can be nearly as long as a streak, but stop short of the timing marks,
+/// while a streak runs from edge to edge of the scan.
+const STREAK_END_LENGTH_RATIO: f32 = 0.025;
+
+/// Settings for cleaning up scanner artifacts before interpretation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreprocessingOptions {
+    /// Isolated dark specks of at most this many pixels are removed. 0 turns
+    /// off despeckling.
+    pub despeckle_max_area: u32,
+
+    /// Whether to fail interpretation if a streak runs through any ovals,
+    /// rather than just warning about it.
+    pub fail_on_oval_streaks: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StreakOrientation {
+    Vertical,
+    Horizontal,
+}
+
+/// A line across the whole scan caused by dirt on the scanner's sensor or
+/// glass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Streak {
+    pub orientation: StreakOrientation,
+
+    /// The first column of a vertical streak, or row of a horizontal one.
+    pub position: u32,
+
+    /// The number of columns or rows the streak covers.
+    pub width: u32,
+}
+
+impl Streak {
+    /// Determines whether the streak passes through `rect`.
+    pub const fn crosses(&self, rect: &Rect) -> bool {
+        let (start, end) = match self.orientation {
+            StreakOrientation::Vertical => (rect.left(), rect.right()),
+            StreakOrientation::Horizontal => (rect.top(), rect.bottom()),
+        };
+        (self.position as i32) <= end && start < (self.position + self.width) as i32
+    }
+}
+
+/// A problem with the scan that suggests the scanner needs attention.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ScannerWarning {
+    /// A streak was found and removed. Any ovals it runs through may have been
+    /// scored incorrectly.
+    #[serde(rename_all = "camelCase")]
+    Streak {
+        streak: Streak,
+        oval_locations: Vec<GridLocation>,
+    },
+}
+
+/// A scan after cleaning up scanner artifacts, along with the streaks found.
+pub struct PreprocessedImage {
+    pub image: GrayImage,
+    pub streaks: Vec<Streak>,
+}
+
+/// Removes speckles and streaks from a scanned ballot page. A bi-tonal page is
+/// kept black and white.
+#[time]
+pub fn preprocess_ballot_page_image(
+    img: &GrayImage,
+    options: &PreprocessingOptions,
+    is_bitonal: bool,
+) -> PreprocessedImage {
+    let streaks = detect_streaks(img);
+    let mut image = remove_streaks(img, &streaks);
+    if is_bitonal && !streaks.is_empty() {
+        image = threshold(&image, u8::MAX / 2);
+    }
+
+    if options.despeckle_max_area > 0 {
+        let binarization = if is_bitonal {
+            BinarizationMethod::Bitonal
+        } else {
+            BinarizationMethod::Otsu
+        };
+        image = despeckle(&image, options.despeckle_max_area, binarization);
+    }
+    PreprocessedImage { image, streaks }
+}
+
+/// Builds scanner warnings for `streaks`, noting which of the ovals in
+/// `scored_oval_marks` each one runs through.
+pub fn streak_warnings(
+    streaks: &[Streak],
+    scored_oval_marks: &ScoredOvalMarks,
+) -> Vec<ScannerWarning> {
+    streaks
+        .iter()
+        .map(|streak| ScannerWarning::Streak {
+            streak: *streak,
+            oval_locations: scored_oval_marks
+                .iter()
+                .filter_map(|(_, oval_mark)| match oval_mark {
+                    OvalMark::Scored(scored_oval_mark)
+                        if streak.crosses(&scored_oval_mark.expected_bounds) =>
+                    {
+                        Some(scored_oval_mark.location)
+                    }
+                    OvalMark::OutOfBounds {
+                        location,
+                        expected_bounds,
+                    } if streak.crosses(expected_bounds) => Some(*location),
+                    _ => None,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Replaces isolated dark specks of at most `max_area` pixels with the color
+/// of the paper so they aren't mistaken for marks or timing marks.
+pub fn despeckle(img: &GrayImage, max_area: u32, binarization: BinarizationMethod) -> GrayImage {
+    let binarized = binarize(img, binarization);
+    let labels = connected_components(&binarized, Connectivity::Eight, WHITE);
+
+    let mut areas =
+        vec![0u32; labels.pixels().map(|label| label.0[0]).max().unwrap_or(0) as usize + 1];
+    for label in labels.pixels() {
+        areas[label.0[0] as usize] += 1;
+    }
+
+    let paper = Luma([paper_luma(img)]);
+    let mut despeckled = img.clone();
+    for (x, y, label) in labels.enumerate_pixels() {
+        let label = label.0[0];
+        if label != 0 && areas[label as usize] <= max_area {
+            despeckled.put_pixel(x, y, paper);
+        }
+    }
+    despeckled
+}
+
+/// Finds vertical and horizontal streaks running across the whole image.
+pub fn detect_streaks(img: &GrayImage) -> Vec<Streak> {
+    let (width, height) = img.dimensions();
+    let mut streaks =
+        find_streak_lines(width, height, |column, row| img.get_pixel(column, row).0[0])
+            .into_iter()
+            .map(|(position, width)| Streak {
+                orientation: StreakOrientation::Vertical,
+                position,
+                width,
+            })
+            .collect::<Vec<_>>();
+
+    streaks.extend(
+        find_streak_lines(height, width, |row, column| img.get_pixel(column, row).0[0])
+            .into_iter()
+            .map(|(position, width)| Streak {
+                orientation: StreakOrientation::Horizontal,
+                position,
+                width,
+            }),
+    );
+
+    streaks
+}
+
+/// Finds runs of adjacent lines that are darker than the lines on either side
+/// of them along most of their length. `luma_at(line, i)` is the luma of the
+/// `i`th pixel of the given line. Returns the first line and number of lines
+/// in each run.
+fn find_streak_lines(
+    line_count: u32,
+    line_length: u32,
+    luma_at: impl Fn(u32, u32) -> u8,
+) -> Vec<(u32, u32)> {
+    if line_count <= STREAK_NEIGHBOR_DISTANCE * 2 || line_length == 0 {
+        return vec![];
+    }
+
+    let minimum_dark_pixels = (line_length as f32 * STREAK_MINIMUM_COVERAGE).ceil() as u32;
+    let end_length =
+        ((line_length as f32 * STREAK_END_LENGTH_RATIO).ceil() as u32).clamp(1, line_length);
+    let is_streak_line = |line: u32| {
+        let is_dark = |i: u32| {
+            let luma = luma_at(line, i);
+            let before = luma_at(line - STREAK_NEIGHBOR_DISTANCE, i);
+            let after = luma_at(line + STREAK_NEIGHBOR_DISTANCE, i);
+            before.min(after).saturating_sub(luma) >= STREAK_MINIMUM_CONTRAST
+        };
+        let is_dark_end = |start: u32| {
+            (start..start + end_length).filter(|&i| is_dark(i)).count() as u32 * 2 >= end_length
+        };
+        is_dark_end(0)
+            && is_dark_end(line_length.saturating_sub(end_length))
+            && (0..line_length).filter(|&i| is_dark(i)).count() as u32 >= minimum_dark_pixels
+    };
+
+    let mut runs: Vec<(u32, u32)> = vec![];
+    for line in STREAK_NEIGHBOR_DISTANCE..line_count - STREAK_NEIGHBOR_DISTANCE {
+        if !is_streak_line(line) {
+            continue;
+        }
+
+        match runs.last_mut() {
+            Some((start, count)) if *start + *count == line => *count += 1,
+            _ => runs.push((line, 1)),
+        }
+    }
+    runs
+}
+
+/// Paints over each streak by blending the pixels on either side of it, which
+/// keeps any marks that cross the streak.
+pub fn remove_streaks(img: &GrayImage, streaks: &[Streak]) -> GrayImage {
+    let (width, height) = img.dimensions();
+    let mut cleaned = img.clone();
+
+    for streak in streaks {
+        let (line_count, line_length) = match streak.orientation {
+            StreakOrientation::Vertical => (width, height),
+            StreakOrientation::Horizontal => (height, width),
+        };
+        let pixel = |line: u32, i: u32| match streak.orientation {
+            StreakOrientation::Vertical => (line, i),
+            StreakOrientation::Horizontal => (i, line),
+        };
+
+        let before = streak.position.saturating_sub(1);
+        let after = (streak.position + streak.width).min(line_count - 1);
+        for i in 0..line_length {
+            let (x, y) = pixel(before, i);
+            let before_luma = f32::from(img.get_pixel(x, y).0[0]);
+            let (x, y) = pixel(after, i);
+            let after_luma = f32::from(img.get_pixel(x, y).0[0]);
+
+            for line in streak.position..streak.position + streak.width {
+                let t = (line - before) as f32 / (after - before).max(1) as f32;
+                let (x, y) = pixel(line, i);
+                cleaned.put_pixel(
+                    x,
+                    y,
+                    Luma([t.mul_add(after_luma - before_luma, before_luma).round() as u8]),
+                );
+            }
+        }
+    }
+
+    cleaned
+}
+
+#[cfg(test)]
+mod tests {
+    use imageproc::{drawing::draw_filled_rect_mut, rect::Rect as ImageprocRect};
+
+    use super::*;
+    use crate::{ballot_card::BallotSide, image_utils::BLACK};
+
+    /// A page with a printed box, a printed divider that doesn't span the
+    /// page, and a faint vertical streak at columns 60 and 61.
+    fn streaked_page() -> GrayImage {
+        let mut img = GrayImage::from_pixel(120, 100, Luma([235]));
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(20, 30).of_size(30, 20), BLACK);
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(90, 10).of_size(2, 70), BLACK);
+        for y in 0..100 {
+            for x in 60..62 {
+                let luma = img.get_pixel(x, y).0[0];
+                img.put_pixel(x, y, Luma([luma.saturating_sub(60)]));
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn test_despeckle_removes_only_small_specks() {
+        let mut img = GrayImage::from_pixel(50, 50, WHITE);
+        img.put_pixel(5, 5, BLACK);
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(10, 10).of_size(2, 2), BLACK);
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(30, 30).of_size(5, 5), BLACK);
+
+        let despeckled = despeckle(&img, 4, BinarizationMethod::Otsu);
+        assert_eq!(despeckled.get_pixel(5, 5), &WHITE);
+        assert_eq!(despeckled.get_pixel(10, 10), &WHITE);
+        assert_eq!(despeckled.get_pixel(32, 32), &BLACK);
+
+        let despeckled = despeckle(&img, 1, BinarizationMethod::Bitonal);
+        assert_eq!(despeckled.get_pixel(5, 5), &WHITE);
+        assert_eq!(despeckled.get_pixel(10, 10), &BLACK);
+    }
+
+    #[test]
+    fn test_detect_vertical_streak() {
+        assert_eq!(
+            detect_streaks(&streaked_page()),
+            vec![Streak {
+                orientation: StreakOrientation::Vertical,
+                position: 60,
+                width: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_printed_rule_is_not_a_streak() {
+        // a rule between columns of contests, running between the timing
+        // marks at the top and bottom of the page
+        let mut img = GrayImage::from_pixel(120, 400, Luma([235]));
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(40, 20).of_size(2, 370), BLACK);
+        for x in [10, 40, 100] {
+            draw_filled_rect_mut(&mut img, ImageprocRect::at(x - 4, 2).of_size(10, 4), BLACK);
+            draw_filled_rect_mut(
+                &mut img,
+                ImageprocRect::at(x - 4, 394).of_size(10, 4),
+                BLACK,
+            );
+        }
+        assert!(detect_streaks(&img).is_empty());
+
+        // a streak running through the rule and the timing marks is found
+        for y in 0..400 {
+            let luma = img.get_pixel(100, y).0[0];
+            img.put_pixel(100, y, Luma([luma.saturating_sub(60)]));
+        }
+        assert_eq!(
+            detect_streaks(&img),
+            vec![Streak {
+                orientation: StreakOrientation::Vertical,
+                position: 100,
+                width: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_detect_horizontal_streak() {
+        let mut img = GrayImage::from_pixel(100, 80, WHITE);
+        for x in 0..100 {
+            img.put_pixel(x, 40, Luma([120]));
+        }
+        assert_eq!(
+            detect_streaks(&img),
+            vec![Streak {
+                orientation: StreakOrientation::Horizontal,
+                position: 40,
+                width: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_remove_streaks_keeps_marks() {
+        let img = streaked_page();
+        let cleaned = remove_streaks(&img, &detect_streaks(&img));
+        assert_eq!(cleaned.get_pixel(60, 5), &Luma([235]));
+        assert_eq!(cleaned.get_pixel(61, 95), &Luma([235]));
+        assert!(detect_streaks(&cleaned).is_empty());
+
+        // a mark crossing the streak is still there
+        let mut img = streaked_page();
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(55, 40).of_size(12, 6), BLACK);
+        let cleaned = remove_streaks(&img, &detect_streaks(&img));
+        assert_eq!(cleaned.get_pixel(60, 42), &BLACK);
+    }
+
+    #[test]
+    fn test_streak_crosses_oval() {
+        let streak = Streak {
+            orientation: StreakOrientation::Vertical,
+            position: 60,
+            width: 2,
+        };
+        let location = GridLocation::new(BallotSide::Front, 1, 1);
+        assert!(streak.crosses(&Rect::new(50, 0, 11, 10)));
+        assert!(streak.crosses(&Rect::new(61, 0, 10, 10)));
+        assert!(!streak.crosses(&Rect::new(62, 0, 10, 10)));
+        assert!(!streak.crosses(&Rect::new(40, 0, 20, 10)));
+
+        let warnings = streak_warnings(
+            &[streak],
+            &vec![(
+                crate::election::GridPosition::WriteIn {
+                    side: BallotSide::Front,
+                    column: 1,
+                    row: 1,
+                    contest_id: crate::election::ContestId::from("contest".to_string()),
+                    write_in_index: 0,
+                    target_shape: None,
+                },
+                OvalMark::OutOfBounds {
+                    location,
+                    expected_bounds: Rect::new(55, 0, 10, 10),
+                },
+            )],
+        );
+        assert!(matches!(
+            warnings.as_slice(),
+            [ScannerWarning::Streak { oval_locations, .. }] if oval_locations.len() == 1
+        ));
+    }
+}
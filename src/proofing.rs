@@ -0,0 +1,409 @@
+use image::GrayImage;
+use logging_timer::time;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use serde::Serialize;
+
+use crate::{
+    ballot_card::BallotSide,
+    binarization::{binarize, BinarizationMethod},
+    election::{
+        BallotStyleId, ContestId, GridLayout, GridLocation, GridPosition, OptionId, PrecinctId,
+    },
+    geometry::{Point, Size},
+    target_templates::TargetTemplates,
+    template_matching::{BinaryTemplate, BitImage},
+    timing_marks::TimingMarkGrid,
+};
+
+/// How much of a target's outline must be printed at a grid location for a
+/// target to be considered printed there.
+const PRINTED_TARGET_MINIMUM_OUTLINE_COVERAGE: f32 = 0.8;
+
+/// How filled in a printed target may be. Solid printed areas, e.g. shaded
+/// headers, cover the whole outline too but aren't targets.
+const PRINTED_TARGET_MAXIMUM_FILL_SCORE: f32 = 0.1;
+
+/// The differences between the targets printed on a blank ballot card and the
+/// grid positions listed in its grid layout.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutProof {
+    pub ballot_style_id: BallotStyleId,
+
+    /// Grid positions in the layout with no target printed at their location.
+    pub listed_but_not_printed: Vec<GridPosition>,
+
+    /// Locations with a printed target that aren't in the layout.
+    pub printed_but_not_listed: Vec<GridLocation>,
+}
+
+/// Determines whether a target matching `template` is printed near
+/// `expected_center`, allowing it to be up to `maximum_search_distance`
+/// pixels away.
+pub fn is_target_printed_at(
+    binarized: &BitImage,
+    template: &BinaryTemplate,
+    expected_center: Point<f32>,
+    maximum_search_distance: u32,
+) -> bool {
+    let (width, height) = (template.width(), template.height());
+    let left = expected_center.x.round() as i32 - (width / 2) as i32;
+    let top = expected_center.y.round() as i32 - (height / 2) as i32;
+    let max_x = binarized.width() as i32 - width as i32;
+    let max_y = binarized.height() as i32 - height as i32;
+    let search_distance = maximum_search_distance as i32;
+
+    (-search_distance..=search_distance)
+        .map(|offset_x| left + offset_x)
+        .filter(|x| (0..=max_x).contains(x))
+        .any(|x| {
+            (-search_distance..=search_distance)
+                .map(|offset_y| top + offset_y)
+                .filter(|y| (0..=max_y).contains(y))
+                .any(|y| {
+                    let (x, y) = (x as u32, y as u32);
+                    template.outline_coverage(binarized, x, y)
+                        >= PRINTED_TARGET_MINIMUM_OUTLINE_COVERAGE
+                        && template.fill_score(binarized, x, y) <= PRINTED_TARGET_MAXIMUM_FILL_SCORE
+                })
+        })
+}
+
+/// Finds the grid locations inside the timing marks on one side of a blank
+/// ballot card that have a printed target. Locations of `grid_positions` are
+/// checked for their own target shape, and the rest for any of the election's
+/// target shapes.
+#[time]
+pub fn find_printed_targets(
+    img: &GrayImage,
+    target_templates: &TargetTemplates,
+    timing_mark_grid: &TimingMarkGrid,
+    grid_positions: &[GridPosition],
+    side: BallotSide,
+    binarization: BinarizationMethod,
+) -> Vec<GridLocation> {
+    let calibration = &timing_mark_grid.calibration;
+    let target_templates = &target_templates.binary_templates(calibration);
+    let binarized = &BitImage::from_gray_image(&binarize(img, binarization), u8::MAX / 2);
+    let grid_size = timing_mark_grid.geometry.grid_size;
+
+    let mut printed = (1..grid_size.width.saturating_sub(1))
+        .into_par_iter()
+        .flat_map_iter(|column| {
+            (1..grid_size.height.saturating_sub(1))
+                .map(move |row| GridLocation::new(side, column, row))
+        })
+        .filter(|location| {
+            let Some(center) = timing_mark_grid.point_for_location(location.column, location.row)
+            else {
+                return false;
+            };
+            let is_printed = |template| {
+                is_target_printed_at(
+                    binarized,
+                    template,
+                    center,
+                    calibration.maximum_search_distance,
+                )
+            };
+            match grid_positions
+                .iter()
+                .find(|grid_position| grid_position.location() == *location)
+            {
+                Some(grid_position) => is_printed(target_templates.template_for(grid_position)),
+                None => target_templates.templates().any(is_printed),
+            }
+        })
+        .collect::<Vec<_>>();
+    printed.sort_by_key(|location| (location.column, location.row));
+    printed
+}
+
+/// Compares the printed targets found on both sides of a blank ballot card
+/// with the grid positions in its grid layout.
+pub fn proof_grid_layout(grid_layout: &GridLayout, printed: &[GridLocation]) -> LayoutProof {
+    LayoutProof {
+        ballot_style_id: grid_layout.ballot_style_id.clone(),
+        listed_but_not_printed: grid_layout
+            .grid_positions
+            .iter()
+            .filter(|grid_position| !printed.contains(&grid_position.location()))
+            .cloned()
+            .collect(),
+        printed_but_not_listed: printed
+            .iter()
+            .filter(|location| {
+                !grid_layout
+                    .grid_positions
+                    .iter()
+                    .any(|grid_position| grid_position.location() == **location)
+            })
+            .copied()
+            .collect(),
+    }
+}
+
+/// Builds a skeleton grid layout with an option at each printed target, to be
+/// edited into the real one. Targets in the same column on the same side are
+/// assumed to be one contest, and the contest and option ids are
+/// placeholders numbered in reading order.
+pub fn generate_grid_layout(
+    precinct_id: PrecinctId,
+    ballot_style_id: BallotStyleId,
+    grid_size: Size<u32>,
+    printed: &[GridLocation],
+) -> GridLayout {
+    let mut printed = printed.to_vec();
+    printed.sort_by_key(|location| {
+        (
+            location.side != BallotSide::Front,
+            location.column,
+            location.row,
+        )
+    });
+
+    let mut contest_count = 0;
+    let mut previous: Option<GridLocation> = None;
+    let grid_positions = printed
+        .iter()
+        .enumerate()
+        .map(|(index, location)| {
+            if !previous.is_some_and(|previous| {
+                previous.side == location.side && previous.column == location.column
+            }) {
+                contest_count += 1;
+            }
+            previous = Some(*location);
+            GridPosition::Option {
+                side: location.side,
+                column: location.column,
+                row: location.row,
+                contest_id: ContestId::from(format!("contest-{contest_count}")),
+                option_id: OptionId::from(format!("option-{}", index + 1)),
+                target_shape: None,
+            }
+        })
+        .collect();
+
+    GridLayout {
+        precinct_id,
+        ballot_style_id,
+        columns: grid_size.width,
+        rows: grid_size.height,
+        grid_positions,
+        blank_ballot: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{imageops::overlay, Luma};
+    use imageproc::{drawing::draw_filled_rect_mut, rect::Rect as ImageprocRect};
+
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        ballot_card::{get_scanned_ballot_card_geometry_8pt5x11, load_oval_template},
+        election::Election,
+        image_utils::WHITE,
+        synthetic_ballot::{
+            render_ballot_card, timing_mark_grid, BackMetadataValues, FrontMetadataValues,
+        },
+    };
+
+    fn bits(img: &GrayImage) -> BitImage {
+        BitImage::from_gray_image(img, u8::MAX / 2)
+    }
+
+    #[test]
+    fn test_is_target_printed_at() {
+        let oval_template = load_oval_template().unwrap();
+        let template = BinaryTemplate::new(&oval_template);
+        let center = Point::new(50.0, 50.0);
+
+        let blank = GrayImage::from_pixel(100, 100, WHITE);
+        assert!(!is_target_printed_at(&bits(&blank), &template, center, 5));
+
+        // printed slightly off of the expected location
+        let mut printed = blank.clone();
+        overlay(
+            &mut printed,
+            &oval_template,
+            53 - i64::from(oval_template.width() / 2),
+            48 - i64::from(oval_template.height() / 2),
+        );
+        assert!(is_target_printed_at(&bits(&printed), &template, center, 5));
+        assert!(!is_target_printed_at(
+            &bits(&printed),
+            &template,
+            Point::new(20.0, 20.0),
+            5
+        ));
+
+        let mut shaded = blank;
+        draw_filled_rect_mut(
+            &mut shaded,
+            ImageprocRect::at(10, 10).of_size(80, 80),
+            Luma([0]),
+        );
+        assert!(!is_target_printed_at(&bits(&shaded), &template, center, 5));
+    }
+
+    #[test]
+    fn test_find_unlisted_targets_of_every_shape() {
+        let election: Election = serde_json::from_str(
+            r#"{
+                "title": "Test",
+                "targetShapes": {
+                    "box": { "type": "rectangle", "width": 40, "height": 26, "lineWidth": 3 }
+                },
+                "gridLayouts": [{
+                    "precinctId": "precinct-1",
+                    "ballotStyleId": "card-number-1",
+                    "columns": 34,
+                    "rows": 41,
+                    "gridPositions": [
+                        { "type": "option", "side": "front", "column": 10, "row": 8, "contestId": "mayor", "optionId": "alice" },
+                        { "type": "option", "side": "front", "column": 10, "row": 10, "contestId": "mayor", "optionId": "bob", "targetShape": "box" }
+                    ]
+                }]
+            }"#,
+        )
+        .unwrap();
+        let target_templates = TargetTemplates::from_election(
+            &election,
+            &load_oval_template().unwrap(),
+            Path::new("."),
+        )
+        .unwrap();
+        let card = render_ballot_card(
+            &election,
+            &election.grid_layouts[0],
+            &target_templates,
+            &FrontMetadataValues {
+                batch_or_precinct_number: 1,
+                card_number: 1,
+            },
+            &BackMetadataValues::default(),
+            &[],
+        )
+        .unwrap();
+
+        // with none of the targets listed, each is found by its own shape
+        let printed = find_printed_targets(
+            &card.front,
+            &target_templates,
+            &timing_mark_grid(&get_scanned_ballot_card_geometry_8pt5x11()),
+            &[],
+            BallotSide::Front,
+            BinarizationMethod::Otsu,
+        );
+        assert_eq!(
+            printed,
+            vec![
+                GridLocation::new(BallotSide::Front, 10, 8),
+                GridLocation::new(BallotSide::Front, 10, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_proof_grid_layout() {
+        let option = |column, row| GridPosition::Option {
+            side: BallotSide::Front,
+            column,
+            row,
+            contest_id: ContestId::from("mayor".to_string()),
+            option_id: OptionId::from(format!("option-{column}-{row}")),
+            target_shape: None,
+        };
+        let grid_layout = GridLayout {
+            precinct_id: PrecinctId::from("precinct-1".to_string()),
+            ballot_style_id: BallotStyleId::from("card-number-1".to_string()),
+            columns: 34,
+            rows: 41,
+            grid_positions: vec![option(5, 10), option(5, 12)],
+            blank_ballot: None,
+        };
+
+        let proof = proof_grid_layout(
+            &grid_layout,
+            &[
+                GridLocation::new(BallotSide::Front, 5, 10),
+                GridLocation::new(BallotSide::Back, 5, 12),
+            ],
+        );
+        assert_eq!(
+            proof
+                .listed_but_not_printed
+                .iter()
+                .map(GridPosition::location)
+                .collect::<Vec<_>>(),
+            vec![GridLocation::new(BallotSide::Front, 5, 12)]
+        );
+        assert_eq!(
+            proof.printed_but_not_listed,
+            vec![GridLocation::new(BallotSide::Back, 5, 12)]
+        );
+    }
+
+    #[test]
+    fn test_generate_grid_layout() {
+        let grid_layout = generate_grid_layout(
+            PrecinctId::from("precinct-1".to_string()),
+            BallotStyleId::from("card-number-1".to_string()),
+            Size {
+                width: 34,
+                height: 41,
+            },
+            &[
+                GridLocation::new(BallotSide::Back, 2, 5),
+                GridLocation::new(BallotSide::Front, 12, 7),
+                GridLocation::new(BallotSide::Front, 2, 9),
+                GridLocation::new(BallotSide::Front, 2, 7),
+            ],
+        );
+        assert_eq!((grid_layout.columns, grid_layout.rows), (34, 41));
+        assert_eq!(
+            grid_layout
+                .grid_positions
+                .iter()
+                .map(|grid_position| match grid_position {
+                    GridPosition::Option {
+                        contest_id,
+                        option_id,
+                        ..
+                    } => (
+                        grid_position.location(),
+                        contest_id.to_string(),
+                        option_id.to_string()
+                    ),
+                    GridPosition::WriteIn { .. } => unreachable!(),
+                })
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    GridLocation::new(BallotSide::Front, 2, 7),
+                    "contest-1".to_string(),
+                    "option-1".to_string()
+                ),
+                (
+                    GridLocation::new(BallotSide::Front, 2, 9),
+                    "contest-1".to_string(),
+                    "option-2".to_string()
+                ),
+                (
+                    GridLocation::new(BallotSide::Front, 12, 7),
+                    "contest-2".to_string(),
+                    "option-3".to_string()
+                ),
+                (
+                    GridLocation::new(BallotSide::Back, 2, 5),
+                    "contest-3".to_string(),
+                    "option-4".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use image::GrayImage;
+use logging_timer::time;
+use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::{
+    binarization::{binarize, BinarizationMethod},
+    election::GridPosition,
+    geometry::{center_of_rect, Rect},
+    image_utils::{bleed, BLACK, WHITE},
+    target_templates::TargetTemplates,
+    template_matching::{BinaryTemplate, BitImage},
+    timing_marks::{score_oval_mark, OvalMark, OvalMarkScore, ScoredOvalMark, TimingMarkGrid},
+};
+
+/// How far, in pixels, ink on a marked ballot may be from the same ink on the
+/// blank reference ballot and still be considered printed content.
+const PRINTED_CONTENT_ALIGNMENT_TOLERANCE: u32 = 2;
+
+/// A scan or rendering of one side of an unmarked ballot card, along with its
+/// timing mark grid so that it can be aligned with a marked ballot.
+pub struct ReferenceBallotPage {
+    pub image: GrayImage,
+    pub binarized: GrayImage,
+    pub grid: TimingMarkGrid,
+    bits: BitImage,
+
+    /// `binarized` with the ink grown by `PRINTED_CONTENT_ALIGNMENT_TOLERANCE`
+    /// so that it covers the printed content of a slightly misaligned ballot.
+    printed_content: GrayImage,
+}
+
+impl ReferenceBallotPage {
+    pub fn new(image: GrayImage, grid: TimingMarkGrid, binarization: BinarizationMethod) -> Self {
+        let binarized = binarize(&image, binarization);
+        let bits = BitImage::from_gray_image(&binarized, u8::MAX / 2);
+        let printed_content = (0..PRINTED_CONTENT_ALIGNMENT_TOLERANCE)
+            .fold(binarized.clone(), |printed_content, _| {
+                bleed(&printed_content, BLACK)
+            });
+        Self {
+            image,
+            binarized,
+            grid,
+            bits,
+            printed_content,
+        }
+    }
+}
+
+/// Erases the ink in `binarized`, a binarized crop of a marked ballot at
+/// `area`, that is also printed on the blank reference ballot, leaving only
+/// the ink that was added to the ballot. Each pixel is aligned with the
+/// reference by the offset between the two timing mark grids at the grid
+/// location nearest to it.
+pub fn erase_printed_content(
+    binarized: &mut GrayImage,
+    area: &Rect,
+    grid: &TimingMarkGrid,
+    reference: &ReferenceBallotPage,
+) {
+    let grid_size = grid.geometry.grid_size;
+    let (last_column, last_row) = (grid_size.width - 1, grid_size.height - 1);
+    let (top_left, bottom_right) = match (
+        grid.point_for_location(0, 0),
+        grid.point_for_location(last_column, last_row),
+    ) {
+        (Some(top_left), Some(bottom_right)) => (top_left, bottom_right),
+        _ => return,
+    };
+    let offsets = (0..grid_size.height)
+        .flat_map(|row| (0..grid_size.width).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            match (
+                grid.point_for_location(column, row),
+                reference.grid.point_for_location(column, row),
+            ) {
+                (Some(point), Some(reference_point)) => {
+                    Some((reference_point.x - point.x, reference_point.y - point.y))
+                }
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    // the grid location nearest to a coordinate, assuming the grid is close
+    // to upright, which is good enough to pick an offset
+    let nearest = |value: f32, first: f32, last: f32, count: u32| {
+        ((value - first) / (last - first) * count as f32)
+            .round()
+            .clamp(0.0, count as f32) as u32
+    };
+
+    let (width, height) = reference.printed_content.dimensions();
+    for (x, y, pixel) in binarized.enumerate_pixels_mut() {
+        if *pixel != BLACK {
+            continue;
+        }
+
+        let (page_x, page_y) = (
+            (area.left() + x as i32) as f32,
+            (area.top() + y as i32) as f32,
+        );
+        let column = nearest(page_x, top_left.x, bottom_right.x, last_column);
+        let row = nearest(page_y, top_left.y, bottom_right.y, last_row);
+        let Some((offset_x, offset_y)) = offsets[(row * grid_size.width + column) as usize] else {
+            continue;
+        };
+
+        let (reference_x, reference_y) = ((page_x + offset_x).round(), (page_y + offset_y).round());
+        if reference_x >= 0.0
+            && reference_y >= 0.0
+            && (reference_x as u32) < width
+            && (reference_y as u32) < height
+            && reference.printed_content[(reference_x as u32, reference_y as u32)] == BLACK
+        {
+            *pixel = WHITE;
+        }
+    }
+}
+
+/// Scores each oval mark by how much darker it is than the same oval on the
+/// blank reference ballot, which discounts printed text and shading that
+/// overlap the oval. `binarized_img` is the image the ovals were scored in,
+/// binarized. The result is stored in `reference_fill_score`.
+#[time]
+pub fn score_oval_marks_against_reference(
+    scored_oval_marks: &mut [(GridPosition, OvalMark)],
+    binarized_img: &GrayImage,
+    reference: &ReferenceBallotPage,
+    target_templates: &TargetTemplates,
+) {
+    let calibration = &reference.grid.calibration;
+    let target_templates = &target_templates.binary_templates(calibration);
+
+    scored_oval_marks
+        .par_iter_mut()
+        .for_each(|(grid_position, oval_mark)| {
+            if let OvalMark::Scored(scored_oval_mark) = oval_mark {
+                scored_oval_mark.reference_fill_score = compute_reference_fill_score(
+                    scored_oval_mark,
+                    binarized_img,
+                    reference,
+                    target_templates.template_for(grid_position),
+                );
+            }
+        });
+}
+
+/// Finds the oval corresponding to `scored_oval_mark` on the reference ballot
+/// and computes the ratio of pixels in the template's fill region that are
+/// dark in the marked ballot but light in the reference.
+fn compute_reference_fill_score(
+    scored_oval_mark: &ScoredOvalMark,
+    binarized_img: &GrayImage,
+    reference: &ReferenceBallotPage,
+    oval_template: &BinaryTemplate,
+) -> Option<OvalMarkScore> {
+    let location = scored_oval_mark.location;
+    let expected_reference_center = reference
+        .grid
+        .point_for_location(location.column, location.row)?;
+    let reference_oval_mark = score_oval_mark(
+        &reference.image,
+        &reference.bits,
+        oval_template,
+        expected_reference_center,
+        &location,
+        reference.grid.calibration.maximum_search_distance,
+    );
+    let reference_center = center_of_rect(&reference_oval_mark.scored()?.matched_bounds);
+
+    // crop the reference to the same size as the marked ballot's oval in
+    // case the two were printed or scanned at slightly different scales
+    let marked = &scored_oval_mark.matched_bounds;
+    let (width, height) = (marked.width(), marked.height());
+    let left = (reference_center.x - width as f32 / 2.0).round();
+    let top = (reference_center.y - height as f32 / 2.0).round();
+    if left < 0.0
+        || top < 0.0
+        || left as u32 + width > reference.binarized.width()
+        || top as u32 + height > reference.binarized.height()
+    {
+        return None;
+    }
+
+    let (left, top) = (left as u32, top as u32);
+    let (marked_left, marked_top) = (marked.left() as u32, marked.top() as u32);
+    let new_ink = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter(|&(x, y)| {
+            oval_template.is_in_fill_region(x, y)
+                && binarized_img[(marked_left + x, marked_top + y)] == BLACK
+                && reference.binarized[(left + x, top + y)] != BLACK
+        })
+        .count();
+    Some(OvalMarkScore(
+        new_ink as f32 / oval_template.fill_region_pixel_count().max(1) as f32,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+    use imageproc::{drawing::draw_filled_rect_mut, rect::Rect as ImageprocRect};
+
+    use super::*;
+    use crate::{
+        ballot_card::{get_scanned_ballot_card_geometry_8pt5x11, BallotSide, Geometry},
+        election::GridLocation,
+        geometry::{Point, Rect, Size},
+        image_utils::WHITE,
+        synthetic_ballot::{grid_location_center, timing_mark_grid},
+    };
+
+    /// A small page with an 8x6 grid whose content area starts at `origin`,
+    /// so that pages can be made to look scanned with different offsets.
+    fn page_geometry(origin: Point<i32>) -> Geometry {
+        Geometry {
+            canvas_size: Size {
+                width: 600,
+                height: 450,
+            },
+            content_area: Rect::new(origin.x, origin.y, 480, 360),
+            grid_size: Size {
+                width: 8,
+                height: 6,
+            },
+            ..get_scanned_ballot_card_geometry_8pt5x11()
+        }
+    }
+
+    /// Draws a page with a hollow oval-sized box at grid location (2, 2) and
+    /// printed text overlapping its left side.
+    fn blank_page(geometry: &Geometry) -> GrayImage {
+        let mut img = GrayImage::from_pixel(
+            geometry.canvas_size.width,
+            geometry.canvas_size.height,
+            WHITE,
+        );
+        let center = grid_location_center(geometry, 2, 2);
+        let (left, top) = (center.x as i32 - 20, center.y as i32 - 13);
+        draw_filled_rect_mut(
+            &mut img,
+            ImageprocRect::at(left, top).of_size(40, 26),
+            BLACK,
+        );
+        draw_filled_rect_mut(
+            &mut img,
+            ImageprocRect::at(left + 2, top + 2).of_size(36, 22),
+            WHITE,
+        );
+        // printed text bleeding into the oval
+        draw_filled_rect_mut(
+            &mut img,
+            ImageprocRect::at(left + 2, top + 8).of_size(12, 10),
+            Luma([20]),
+        );
+        img
+    }
+
+    fn box_template() -> GrayImage {
+        let mut template = GrayImage::from_pixel(40, 26, BLACK);
+        draw_filled_rect_mut(
+            &mut template,
+            ImageprocRect::at(2, 2).of_size(36, 22),
+            WHITE,
+        );
+        template
+    }
+
+    /// Scores the oval at grid location (2, 2), returning it along with the
+    /// binarized image it was scored in.
+    fn score(
+        img: &GrayImage,
+        grid: &TimingMarkGrid,
+        template: &GrayImage,
+    ) -> (ScoredOvalMark, GrayImage) {
+        let binarized = binarize(img, BinarizationMethod::Otsu);
+        let bits = BitImage::from_gray_image(&binarized, u8::MAX / 2);
+        let location = GridLocation::new(BallotSide::Front, 2, 2);
+        let center = grid.point_for_location(2, 2).unwrap();
+        let scored_oval_mark = score_oval_mark(
+            img,
+            &bits,
+            &BinaryTemplate::new(template),
+            center,
+            &location,
+            7,
+        )
+        .scored()
+        .unwrap()
+        .clone();
+        (scored_oval_mark, binarized)
+    }
+
+    #[test]
+    fn test_reference_fill_score_discounts_printed_content() {
+        let template = box_template();
+        // the reference is shifted relative to the marked ballot, as if it
+        // were scanned with a different offset
+        let reference_geometry = page_geometry(Point::new(30, 30));
+        let marked_geometry = page_geometry(Point::new(36, 27));
+        let reference = ReferenceBallotPage::new(
+            blank_page(&reference_geometry),
+            timing_mark_grid(&reference_geometry),
+            BinarizationMethod::Otsu,
+        );
+        let marked_grid = timing_mark_grid(&marked_geometry);
+
+        // unmarked: the printed text counts toward the fill score, but not
+        // toward the reference fill score
+        let (unmarked, unmarked_binarized) =
+            score(&blank_page(&marked_geometry), &marked_grid, &template);
+        let unmarked_fill = compute_reference_fill_score(
+            &unmarked,
+            &unmarked_binarized,
+            &reference,
+            &BinaryTemplate::new(&template),
+        )
+        .unwrap();
+        assert!(unmarked.fill_score.0 > 0.1, "{:?}", unmarked.fill_score);
+        assert!(unmarked_fill.0 < 0.01, "{unmarked_fill:?}");
+
+        // marked: the fill on the rest of the oval counts toward both
+        let mut marked_img = blank_page(&marked_geometry);
+        let center = marked_grid.point_for_location(2, 2).unwrap();
+        draw_filled_rect_mut(
+            &mut marked_img,
+            ImageprocRect::at(center.x as i32 - 6, center.y as i32 - 10).of_size(24, 20),
+            Luma([40]),
+        );
+        let (marked, marked_binarized) = score(&marked_img, &marked_grid, &template);
+        let marked_fill = compute_reference_fill_score(
+            &marked,
+            &marked_binarized,
+            &reference,
+            &BinaryTemplate::new(&template),
+        )
+        .unwrap();
+        assert!(marked_fill.0 > 0.4, "{marked_fill:?}");
+        assert!(marked.fill_score.0 > marked_fill.0);
+    }
+}
@@ -0,0 +1,190 @@
+use image::{DynamicImage, GenericImageView, GrayImage};
+use serde::Serialize;
+
+use crate::{election::Election, geometry::Rect, mark_features::paper_luma};
+
+/// The fraction of the image width, centered on the middle, that is searched
+/// for the gutter between the pages.
+const GUTTER_SEARCH_WIDTH_RATIO: f32 = 0.1;
+
+/// The largest standard deviation of the luma in a column for it to be part
+/// of the gutter. The gutter is either scanner background or blank paper.
+const GUTTER_MAX_STD_DEV: f32 = 12.0;
+
+/// How much the mean luma of gutter columns may differ from each other.
+const GUTTER_MAX_LUMA_DIFFERENCE: f32 = 32.0;
+
+/// How much darker than the paper the gutter must be to be cut out of the
+/// pages. A lighter gutter is the paper margins of pages that touch.
+const GUTTER_MINIMUM_CONTRAST: f32 = 48.0;
+
+/// One half of a side-by-side scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImageHalf {
+    Left,
+    Right,
+}
+
+/// How a side-by-side scan was split into the pages of a ballot card. The
+/// left half is always side A and the right half side B, so only which of
+/// them is the front is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SideBySideSplit {
+    /// The columns between the pages that belong to neither, in the
+    /// coordinates of the scan. It is empty if the pages touch.
+    pub gutter: Rect,
+    pub front: ImageHalf,
+    pub back: ImageHalf,
+}
+
+/// Determines whether an image of the given size could be two of the
+/// election's ballot pages side by side, allowing `tolerance` in the aspect
+/// ratio of each page.
+pub fn is_side_by_side(size: (u32, u32), election: &Election, tolerance: f32) -> bool {
+    let (width, height) = size;
+    election
+        .scanned_ballot_card_geometry((width / 2, height), tolerance)
+        .is_some()
+}
+
+/// The mean and standard deviation of the luma in a column.
+fn column_luma_statistics(img: &GrayImage, x: u32) -> (f32, f32) {
+    let n = img.height() as f32;
+    let (sum, sum_of_squares) = (0..img.height()).fold((0.0, 0.0), |(sum, sum_of_squares), y| {
+        let luma = f32::from(img.get_pixel(x, y).0[0]);
+        (sum + luma, sum_of_squares + luma * luma)
+    });
+    let mean = sum / n;
+    let variance = (sum_of_squares / n - mean * mean).max(0.0);
+    (mean, variance.sqrt())
+}
+
+/// Finds the gutter between the pages of a side-by-side scan: the run of
+/// uniform columns nearest the middle. Returns `None` if nothing near the
+/// middle separates the pages.
+pub fn find_gutter(img: &GrayImage) -> Option<Rect> {
+    let (width, height) = img.dimensions();
+    let center = width / 2;
+    let search_radius = (width as f32 * GUTTER_SEARCH_WIDTH_RATIO / 2.0) as u32;
+    let search_start = center.saturating_sub(search_radius);
+    let search_end = (center + search_radius).min(width.saturating_sub(1));
+
+    let is_uniform = |x: u32| column_luma_statistics(img, x).1 <= GUTTER_MAX_STD_DEV;
+    let seed = (search_start..=search_end)
+        .filter(|&x| is_uniform(x))
+        .min_by_key(|&x| x.abs_diff(center))?;
+    let (seed_mean, _) = column_luma_statistics(img, seed);
+    let is_gutter = |x: u32| {
+        let (mean, std_dev) = column_luma_statistics(img, x);
+        std_dev <= GUTTER_MAX_STD_DEV && (mean - seed_mean).abs() <= GUTTER_MAX_LUMA_DIFFERENCE
+    };
+
+    let mut left = seed;
+    while left > 0 && is_gutter(left - 1) {
+        left -= 1;
+    }
+    let mut right = seed;
+    while right + 1 < width && is_gutter(right + 1) {
+        right += 1;
+    }
+
+    if seed_mean + GUTTER_MINIMUM_CONTRAST > f32::from(paper_luma(img)) {
+        // the pages touch, so split between their margins without losing any
+        // of either page
+        return Some(Rect::new((left + right).div_ceil(2) as i32, 0, 0, height));
+    }
+
+    Some(Rect::new(left as i32, 0, right - left + 1, height))
+}
+
+/// Splits a side-by-side scan into its left and right pages on either side
+/// of `gutter`.
+pub fn split_side_by_side(img: &DynamicImage, gutter: &Rect) -> (DynamicImage, DynamicImage) {
+    let (width, height) = img.dimensions();
+    let left_width = gutter.left() as u32;
+    let right_start = left_width + gutter.width();
+    (
+        img.crop_imm(0, 0, left_width, height),
+        img.crop_imm(right_start, 0, width - right_start, height),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+    use imageproc::{drawing::draw_filled_rect_mut, rect::Rect as ImageprocRect};
+
+    use super::*;
+    use crate::image_utils::{BLACK, WHITE};
+
+    const PAGE_WIDTH: u32 = 170;
+    const PAGE_HEIGHT: u32 = 220;
+
+    /// Draws a page with timing marks along its left and right edges.
+    fn draw_page(img: &mut GrayImage, left: u32) {
+        draw_filled_rect_mut(
+            img,
+            ImageprocRect::at(left as i32, 0).of_size(PAGE_WIDTH, PAGE_HEIGHT),
+            WHITE,
+        );
+        for y in (10..PAGE_HEIGHT - 10).step_by(10) {
+            for x in [left + 4, left + PAGE_WIDTH - 10] {
+                draw_filled_rect_mut(
+                    img,
+                    ImageprocRect::at(x as i32, y as i32).of_size(6, 3),
+                    BLACK,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_side_by_side() {
+        let election: Election =
+            serde_json::from_str(r#"{ "title": "Test", "gridLayouts": [] }"#).unwrap();
+        let tolerance = 0.01;
+        assert!(is_side_by_side((3392, 2200), &election, tolerance));
+        assert!(is_side_by_side((3392, 2800), &election, tolerance));
+        assert!(!is_side_by_side((1696, 2200), &election, tolerance));
+        assert!(!is_side_by_side((3000, 2200), &election, tolerance));
+    }
+
+    #[test]
+    fn test_find_dark_gutter() {
+        let mut img = GrayImage::from_pixel(PAGE_WIDTH * 2 + 8, PAGE_HEIGHT, Luma([20]));
+        draw_page(&mut img, 0);
+        draw_page(&mut img, PAGE_WIDTH + 8);
+
+        let gutter = find_gutter(&img).unwrap();
+        assert_eq!(gutter, Rect::new(PAGE_WIDTH as i32, 0, 8, PAGE_HEIGHT));
+
+        let (left, right) = split_side_by_side(&DynamicImage::ImageLuma8(img), &gutter);
+        assert_eq!(left.dimensions(), (PAGE_WIDTH, PAGE_HEIGHT));
+        assert_eq!(right.dimensions(), (PAGE_WIDTH, PAGE_HEIGHT));
+        assert_eq!(right.to_luma8().get_pixel(4, 10), &BLACK);
+    }
+
+    #[test]
+    fn test_find_gutter_between_touching_pages() {
+        let mut img = GrayImage::new(PAGE_WIDTH * 2, PAGE_HEIGHT);
+        draw_page(&mut img, 0);
+        draw_page(&mut img, PAGE_WIDTH);
+
+        let gutter = find_gutter(&img).unwrap();
+        assert_eq!(gutter, Rect::new(PAGE_WIDTH as i32, 0, 0, PAGE_HEIGHT));
+    }
+
+    #[test]
+    fn test_no_gutter() {
+        let img = GrayImage::from_fn(PAGE_WIDTH * 2, PAGE_HEIGHT, |x, y| {
+            if (x + y) % 3 == 0 {
+                BLACK
+            } else {
+                WHITE
+            }
+        });
+        assert_eq!(find_gutter(&img), None);
+    }
+}
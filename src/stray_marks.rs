@@ -0,0 +1,719 @@
+use image::{GenericImageView, GrayImage};
+use imageproc::{
+    drawing::draw_filled_rect_mut,
+    rect::Rect as ImageprocRect,
+    region_labelling::{connected_components, Connectivity},
+};
+use logging_timer::time;
+use serde::Serialize;
+
+use crate::{
+    ballot_card::{BallotSide, Geometry},
+    binarization::{binarize, BinarizationMethod},
+    debug,
+    debug::ImageDebugWriter,
+    election::{GridLayout, GridLocation, GridPosition},
+    geometry::{center_of_rect, Point, Rect, Segment},
+    image_utils::WHITE,
+    reference_ballot::{erase_printed_content, ReferenceBallotPage},
+    timing_marks::{ScoredOvalMarks, TimingMarkGrid},
+};
+
+/// A significant blob of ink inside the grid that is not explained by printed
+/// content or by any of the ovals in the grid layout, e.g. a circled candidate
+/// name or an "X" next to an option. These are reported so that adjudicators
+/// can review voter intent.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrayMark {
+    /// The bounds of the ink blob in the scanned source image.
+    pub bounds: Rect,
+
+    /// The number of dark pixels in the ink blob.
+    pub pixel_count: u32,
+
+    /// The grid location closest to the center of the ink blob. Uses
+    /// side/column/row, not x/y.
+    pub nearest_location: GridLocation,
+
+    /// The grid position from the grid layout closest to the center of the
+    /// ink blob, if there are any on this side of the ballot card.
+    pub nearest_grid_position: Option<GridPosition>,
+
+    /// The distance in pixels from the center of the ink blob to the center
+    /// of `nearest_grid_position`.
+    pub distance_to_nearest_grid_position: Option<f32>,
+}
+
+/// A connected group of dark pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InkBlob {
+    pub bounds: Rect,
+    pub pixel_count: u32,
+
+    /// The number of dark pixels next to a light pixel or the edge of the
+    /// image, i.e. on the outline of the blob.
+    pub edge_pixel_count: u32,
+}
+
+impl InkBlob {
+    /// Estimates the width of the strokes making up the blob from its area
+    /// and the length of its outline. A stroke of width `w` and length `l`
+    /// has an area of about `w * l` and an outline of about `2 * l`, however
+    /// it curves.
+    pub fn stroke_width(&self) -> f32 {
+        if self.edge_pixel_count == 0 {
+            return 0.0;
+        }
+        2.0 * self.pixel_count as f32 / self.edge_pixel_count as f32
+    }
+}
+
+/// Finds ink blobs inside the usable area of the timing mark grid that are
+/// large enough to be voter marks, ignoring the ink inside the scored ovals.
+/// If there is a blank reference ballot, the ink printed on it is ignored as
+/// well.
+#[time]
+#[allow(clippy::too_many_arguments)]
+pub fn find_stray_marks(
+    img: &GrayImage,
+    timing_mark_grid: &TimingMarkGrid,
+    grid_layout: &GridLayout,
+    side: BallotSide,
+    scored_oval_marks: &ScoredOvalMarks,
+    reference: Option<&ReferenceBallotPage>,
+    binarization: BinarizationMethod,
+    debug: &ImageDebugWriter,
+) -> Vec<StrayMark> {
+    let usable_area = timing_mark_grid.geometry.usable_area(side);
+    let search_area = match grid_area_bounds(timing_mark_grid, &usable_area)
+        .and_then(|bounds| bounds.intersect(&Rect::new(0, 0, img.width(), img.height())))
+    {
+        Some(search_area) => search_area,
+        None => return vec![],
+    };
+
+    let oval_bounds = scored_oval_marks
+        .iter()
+        .filter_map(|(_, oval_mark)| {
+            oval_mark
+                .scored()
+                .map(|scored_oval_mark| scored_oval_mark.matched_bounds)
+        })
+        .collect::<Vec<_>>();
+
+    let mut binarized = binarize(img, binarization)
+        .view(
+            search_area.left() as u32,
+            search_area.top() as u32,
+            search_area.width(),
+            search_area.height(),
+        )
+        .to_image();
+
+    if let Some(reference) = reference {
+        erase_printed_content(&mut binarized, &search_area, timing_mark_grid, reference);
+    }
+
+    // ink inside an oval is scored as a mark, but a blob crossing an oval,
+    // e.g. a circle around a candidate name, is still a stray mark outside it
+    for bounds in oval_bounds
+        .iter()
+        .filter_map(|bounds| bounds.intersect(&search_area))
+    {
+        draw_filled_rect_mut(
+            &mut binarized,
+            ImageprocRect::at(
+                bounds.left() - search_area.left(),
+                bounds.top() - search_area.top(),
+            )
+            .of_size(bounds.width(), bounds.height()),
+            WHITE,
+        );
+    }
+
+    let stray_marks = find_ink_blobs(&binarized)
+        .into_iter()
+        .map(|blob| InkBlob {
+            bounds: blob.bounds.offset(search_area.left(), search_area.top()),
+            ..blob
+        })
+        .filter(|blob| is_significant_ink_blob(&timing_mark_grid.geometry, blob))
+        .filter_map(|blob| stray_mark_from_ink_blob(&blob, timing_mark_grid, grid_layout, side))
+        .collect::<Vec<_>>();
+
+    debug.write("stray_marks", |canvas| {
+        debug::draw_stray_marks_debug_image_mut(canvas, &search_area, &stray_marks);
+    });
+
+    stray_marks
+}
+
+/// The extent of an ink blob as `(min_x, min_y, max_x, max_y, pixel_count,
+/// edge_pixel_count)`.
+type InkBlobExtent = (u32, u32, u32, u32, u32, u32);
+
+/// Finds all the connected groups of black pixels in a binarized image.
+pub fn find_ink_blobs(binarized: &GrayImage) -> Vec<InkBlob> {
+    let labels = connected_components(binarized, Connectivity::Eight, WHITE);
+    let (width, height) = labels.dimensions();
+    let is_ink = |x: u32, y: u32| labels[(x, y)].0[0] != 0;
+    let mut extents: Vec<Option<InkBlobExtent>> = vec![];
+
+    for (x, y, label) in labels.enumerate_pixels() {
+        let label = label.0[0] as usize;
+        if label == 0 {
+            continue;
+        }
+
+        if label >= extents.len() {
+            extents.resize(label + 1, None);
+        }
+
+        let is_edge = x == 0
+            || y == 0
+            || x == width - 1
+            || y == height - 1
+            || !is_ink(x - 1, y)
+            || !is_ink(x + 1, y)
+            || !is_ink(x, y - 1)
+            || !is_ink(x, y + 1);
+        let edge_count = u32::from(is_edge);
+        extents[label] = Some(match extents[label] {
+            Some((min_x, min_y, max_x, max_y, count, edge_pixel_count)) => (
+                min_x.min(x),
+                min_y.min(y),
+                max_x.max(x),
+                max_y.max(y),
+                count + 1,
+                edge_pixel_count + edge_count,
+            ),
+            None => (x, y, x, y, 1, edge_count),
+        });
+    }
+
+    extents
+        .into_iter()
+        .flatten()
+        .map(
+            |(min_x, min_y, max_x, max_y, pixel_count, edge_pixel_count)| InkBlob {
+                bounds: Rect::new(
+                    min_x as i32,
+                    min_y as i32,
+                    max_x - min_x + 1,
+                    max_y - min_y + 1,
+                ),
+                pixel_count,
+                edge_pixel_count,
+            },
+        )
+        .collect()
+}
+
+/// Determines whether an ink blob is large enough to be a voter mark rather
+/// than printed text, a printed rule or box, or a speck of dust. Printed
+/// glyphs are smaller than an oval, and printed rules and boxes may be large
+/// but are drawn with thin strokes, whereas hand-drawn circles and "X"s are
+/// at least as large as an oval and drawn with a pen or pencil.
+pub fn is_significant_ink_blob(geometry: &Geometry, blob: &InkBlob) -> bool {
+    let min_pixel_count = geometry.oval_size.width * geometry.oval_size.height / 8;
+    let min_extent = geometry.oval_size.width;
+    let min_stroke_width = geometry.oval_size.height as f32 / 8.0;
+    let extent = blob.bounds.width().max(blob.bounds.height());
+
+    blob.pixel_count >= min_pixel_count
+        && extent >= min_extent
+        && blob.stroke_width() >= min_stroke_width
+}
+
+/// The part of the grid inside the timing marks, where ovals and other printed
+/// content may be found, in timing mark columns and rows.
+fn grid_interior(timing_mark_grid: &TimingMarkGrid) -> Option<Rect> {
+    let grid_size = timing_mark_grid.geometry.grid_size;
+    if grid_size.width < 4 || grid_size.height < 4 {
+        return None;
+    }
+    Some(Rect::new(1, 1, grid_size.width - 2, grid_size.height - 2))
+}
+
+/// Computes the area of the image covered by the part of `area`, in timing
+/// mark columns and rows, that is inside the timing marks. The area extends
+/// half a grid cell beyond its outermost oval positions.
+fn grid_area_bounds(timing_mark_grid: &TimingMarkGrid, area: &Rect) -> Option<Rect> {
+    let interior = grid_interior(timing_mark_grid)?;
+    let area = area.intersect(&interior)?;
+
+    let (last_column, last_row) = (interior.right() as u32, interior.bottom() as u32);
+    let top_left = timing_mark_grid.point_for_location(1, 1)?;
+    let column_pitch = Segment::new(
+        top_left,
+        timing_mark_grid.point_for_location(last_column, 1)?,
+    )
+    .length()
+        / (last_column - 1) as f32;
+    let row_pitch = Segment::new(top_left, timing_mark_grid.point_for_location(1, last_row)?)
+        .length()
+        / (last_row - 1) as f32;
+
+    let (left, top, right, bottom) = (
+        area.left() as u32,
+        area.top() as u32,
+        area.right() as u32,
+        area.bottom() as u32,
+    );
+    let corners = [
+        timing_mark_grid.point_for_location(left, top)?,
+        timing_mark_grid.point_for_location(right, top)?,
+        timing_mark_grid.point_for_location(left, bottom)?,
+        timing_mark_grid.point_for_location(right, bottom)?,
+    ];
+
+    let min_x = corners.iter().map(|p| p.x).fold(f32::INFINITY, f32::min) - column_pitch / 2.0;
+    let max_x = corners
+        .iter()
+        .map(|p| p.x)
+        .fold(f32::NEG_INFINITY, f32::max)
+        + column_pitch / 2.0;
+    let min_y = corners.iter().map(|p| p.y).fold(f32::INFINITY, f32::min) - row_pitch / 2.0;
+    let max_y = corners
+        .iter()
+        .map(|p| p.y)
+        .fold(f32::NEG_INFINITY, f32::max)
+        + row_pitch / 2.0;
+
+    let left = min_x.floor().max(0.0);
+    let top = min_y.floor().max(0.0);
+    Some(Rect::new(
+        left as i32,
+        top as i32,
+        (max_x.ceil() - left) as u32,
+        (max_y.ceil() - top) as u32,
+    ))
+}
+
+/// Maps an ink blob to the nearest grid location and grid position.
+fn stray_mark_from_ink_blob(
+    blob: &InkBlob,
+    timing_mark_grid: &TimingMarkGrid,
+    grid_layout: &GridLayout,
+    side: BallotSide,
+) -> Option<StrayMark> {
+    let center = center_of_rect(&blob.bounds);
+    let distance_to = |point: Point<f32>| Segment::new(center, point).length();
+
+    let area =
+        grid_interior(timing_mark_grid)?.intersect(&timing_mark_grid.geometry.usable_area(side))?;
+    let (nearest_location, _) = (area.left() as u32..=area.right() as u32)
+        .flat_map(|column| (area.top() as u32..=area.bottom() as u32).map(move |row| (column, row)))
+        .filter_map(|(column, row)| {
+            timing_mark_grid
+                .point_for_location(column, row)
+                .map(|point| (GridLocation::new(side, column, row), distance_to(point)))
+        })
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))?;
+
+    let nearest_grid_position = grid_layout
+        .grid_positions
+        .iter()
+        .filter(|grid_position| grid_position.location().side == side)
+        .filter_map(|grid_position| {
+            let location = grid_position.location();
+            timing_mark_grid
+                .point_for_location(location.column, location.row)
+                .map(|point| (grid_position, distance_to(point)))
+        })
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    Some(StrayMark {
+        bounds: blob.bounds,
+        pixel_count: blob.pixel_count,
+        nearest_location,
+        nearest_grid_position: nearest_grid_position
+            .map(|(grid_position, _)| grid_position.clone()),
+        distance_to_nearest_grid_position: nearest_grid_position.map(|(_, distance)| distance),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use image::Luma;
+    use imageproc::drawing::{
+        draw_hollow_ellipse_mut, draw_hollow_rect_mut, draw_line_segment_mut,
+    };
+
+    use super::*;
+    use crate::{
+        ballot_card::{get_scanned_ballot_card_geometry_8pt5x11, load_oval_template},
+        election::Election,
+        image_utils::BLACK,
+        synthetic_ballot::{
+            grid_location_center, render_test_ballot_card, test_election, timing_mark_grid,
+        },
+        target_templates::TargetTemplates,
+        timing_marks::score_oval_marks_from_grid_layout,
+    };
+
+    #[test]
+    fn test_find_ink_blobs() {
+        let mut img = GrayImage::from_pixel(100, 100, WHITE);
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(10, 10).of_size(5, 5), Luma([0]));
+        draw_filled_rect_mut(
+            &mut img,
+            ImageprocRect::at(50, 60).of_size(20, 3),
+            Luma([0]),
+        );
+
+        let mut blobs = find_ink_blobs(&img);
+        blobs.sort_by_key(|blob| blob.bounds.left());
+        assert_eq!(
+            blobs,
+            vec![
+                InkBlob {
+                    bounds: Rect::new(10, 10, 5, 5),
+                    pixel_count: 25,
+                    edge_pixel_count: 16,
+                },
+                InkBlob {
+                    bounds: Rect::new(50, 60, 20, 3),
+                    pixel_count: 60,
+                    edge_pixel_count: 42,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_ink_blobs_empty() {
+        let img = GrayImage::from_pixel(10, 10, WHITE);
+        assert!(find_ink_blobs(&img).is_empty());
+    }
+
+    #[test]
+    fn test_ink_blob_stroke_width() {
+        // a box drawn with 2px rules
+        let mut img = GrayImage::from_pixel(320, 420, WHITE);
+        for inset in 0..2 {
+            draw_hollow_rect_mut(
+                &mut img,
+                ImageprocRect::at(10 + inset, 10 + inset)
+                    .of_size(300 - 2 * inset as u32, 400 - 2 * inset as u32),
+                BLACK,
+            );
+        }
+        let blobs = find_ink_blobs(&img);
+        assert_eq!(blobs.len(), 1);
+        assert!(
+            (blobs[0].stroke_width() - 2.0).abs() < 0.1,
+            "{}",
+            blobs[0].stroke_width()
+        );
+
+        // a solid stroke 6px wide
+        let mut img = GrayImage::from_pixel(100, 100, WHITE);
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(10, 10).of_size(6, 80), BLACK);
+        let blobs = find_ink_blobs(&img);
+        assert!(
+            (blobs[0].stroke_width() - 6.0).abs() < 0.5,
+            "{}",
+            blobs[0].stroke_width()
+        );
+    }
+
+    #[test]
+    fn test_is_significant_ink_blob() {
+        let geometry = get_scanned_ballot_card_geometry_8pt5x11();
+
+        // a hand-drawn "X" roughly the size of an oval, with 5px strokes
+        assert!(is_significant_ink_blob(
+            &geometry,
+            &InkBlob {
+                bounds: Rect::new(0, 0, 50, 50),
+                pixel_count: 700,
+                edge_pixel_count: 280,
+            }
+        ));
+
+        // a printed glyph
+        assert!(!is_significant_ink_blob(
+            &geometry,
+            &InkBlob {
+                bounds: Rect::new(0, 0, 12, 20),
+                pixel_count: 150,
+                edge_pixel_count: 100,
+            }
+        ));
+
+        // a printed rule
+        assert!(!is_significant_ink_blob(
+            &geometry,
+            &InkBlob {
+                bounds: Rect::new(0, 0, 600, 3),
+                pixel_count: 1800,
+                edge_pixel_count: 1200,
+            }
+        ));
+
+        // a printed contest box with 2px rules
+        assert!(!is_significant_ink_blob(
+            &geometry,
+            &InkBlob {
+                bounds: Rect::new(0, 0, 300, 400),
+                pixel_count: 2784,
+                edge_pixel_count: 2784,
+            }
+        ));
+
+        // a speck of dust
+        assert!(!is_significant_ink_blob(
+            &geometry,
+            &InkBlob {
+                bounds: Rect::new(0, 0, 2, 2),
+                pixel_count: 4,
+                edge_pixel_count: 4,
+            }
+        ));
+    }
+
+    /// Draws a line of printed text starting at `left`, centered on `y`, as
+    /// glyph-sized outlines 2px wide.
+    fn draw_text_mut(img: &mut GrayImage, left: i32, y: i32, glyphs: i32) {
+        for glyph in 0..glyphs {
+            for inset in 0..2 {
+                draw_hollow_rect_mut(
+                    img,
+                    ImageprocRect::at(left + glyph * 16 + inset, y - 8 + inset)
+                        .of_size(12 - 2 * inset as u32, 16 - 2 * inset as u32),
+                    BLACK,
+                );
+            }
+        }
+    }
+
+    /// Renders the front page of the test ballot with a printed contest box
+    /// around its ovals and each candidate's name printed next to their oval.
+    /// Returns the page and the center of each oval.
+    fn render_printed_page(
+        election: &Election,
+        target_templates: &TargetTemplates,
+    ) -> (GrayImage, Vec<Point<f32>>) {
+        let geometry = get_scanned_ballot_card_geometry_8pt5x11();
+        let mut img = render_test_ballot_card(election, target_templates, &[]).front;
+
+        let centers = election.grid_layouts[0]
+            .grid_positions
+            .iter()
+            .map(GridPosition::location)
+            .filter(|location| location.side == BallotSide::Front)
+            .map(|location| grid_location_center(&geometry, location.column, location.row))
+            .collect::<Vec<_>>();
+
+        // the contest box, with the contest title in it
+        let top_left = grid_location_center(&geometry, 9, 6);
+        let bottom_right = grid_location_center(&geometry, 20, 14);
+        for inset in 0..2 {
+            draw_hollow_rect_mut(
+                &mut img,
+                ImageprocRect::at(top_left.x as i32 + inset, top_left.y as i32 + inset).of_size(
+                    (bottom_right.x - top_left.x) as u32 - 2 * inset as u32,
+                    (bottom_right.y - top_left.y) as u32 - 2 * inset as u32,
+                ),
+                BLACK,
+            );
+        }
+        draw_text_mut(&mut img, top_left.x as i32 + 20, top_left.y as i32 + 30, 10);
+
+        for center in &centers {
+            draw_text_mut(&mut img, center.x as i32 + 30, center.y as i32, 12);
+        }
+
+        (img, centers)
+    }
+
+    /// Draws a pen "X" 40px across centered on `center`.
+    fn draw_x_mut(img: &mut GrayImage, center: Point<f32>) {
+        for offset in 0..5 {
+            draw_line_segment_mut(
+                img,
+                (center.x - 20.0 + offset as f32, center.y - 20.0),
+                (center.x + 20.0 + offset as f32, center.y + 20.0),
+                Luma([40]),
+            );
+            draw_line_segment_mut(
+                img,
+                (center.x + 20.0 + offset as f32, center.y - 20.0),
+                (center.x - 20.0 + offset as f32, center.y + 20.0),
+                Luma([40]),
+            );
+        }
+    }
+
+    fn option_location(election: &Election, index: usize) -> GridLocation {
+        election.grid_layouts[0].grid_positions[index].location()
+    }
+
+    fn find_test_stray_marks(
+        img: &GrayImage,
+        election: &Election,
+        target_templates: &TargetTemplates,
+        reference: Option<&ReferenceBallotPage>,
+    ) -> Vec<StrayMark> {
+        let grid_layout = &election.grid_layouts[0];
+        let grid = timing_mark_grid(&get_scanned_ballot_card_geometry_8pt5x11());
+        let scored_oval_marks = score_oval_marks_from_grid_layout(
+            img,
+            target_templates,
+            &grid,
+            grid_layout,
+            BallotSide::Front,
+            BinarizationMethod::Otsu,
+            None,
+            &ImageDebugWriter::disabled(),
+        );
+        find_stray_marks(
+            img,
+            &grid,
+            grid_layout,
+            BallotSide::Front,
+            &scored_oval_marks,
+            reference,
+            BinarizationMethod::Otsu,
+            &ImageDebugWriter::disabled(),
+        )
+    }
+
+    #[test]
+    fn test_printed_content_is_not_a_stray_mark() {
+        let election = test_election();
+        let target_templates = TargetTemplates::from_election(
+            &election,
+            &load_oval_template().unwrap(),
+            Path::new("."),
+        )
+        .unwrap();
+        let (mut img, centers) = render_printed_page(&election, &target_templates);
+
+        let stray_marks = find_test_stray_marks(&img, &election, &target_templates, None);
+        assert!(stray_marks.is_empty(), "{stray_marks:?}");
+
+        // a circle around the second candidate's name crossing their oval
+        let name_center = Point::new(centers[1].x + 125.0, centers[1].y);
+        for radius in 0..4 {
+            draw_hollow_ellipse_mut(
+                &mut img,
+                (name_center.x as i32, name_center.y as i32),
+                125 + radius,
+                20 + radius,
+                BLACK,
+            );
+        }
+        let stray_marks = find_test_stray_marks(&img, &election, &target_templates, None);
+        assert_eq!(stray_marks.len(), 1, "{stray_marks:?}");
+        assert_eq!(
+            stray_marks[0]
+                .nearest_grid_position
+                .as_ref()
+                .map(GridPosition::location),
+            Some(option_location(&election, 1))
+        );
+    }
+
+    #[test]
+    fn test_printed_content_on_reference_is_not_a_stray_mark() {
+        let election = test_election();
+        let target_templates = TargetTemplates::from_election(
+            &election,
+            &load_oval_template().unwrap(),
+            Path::new("."),
+        )
+        .unwrap();
+        let (mut img, centers) = render_printed_page(&election, &target_templates);
+
+        // a bold printed heading drawn with strokes as thick as a pen's
+        let heading = grid_location_center(&get_scanned_ballot_card_geometry_8pt5x11(), 12, 3);
+        draw_filled_rect_mut(
+            &mut img,
+            ImageprocRect::at(heading.x as i32, heading.y as i32).of_size(120, 30),
+            BLACK,
+        );
+        let reference = ReferenceBallotPage::new(
+            img.clone(),
+            timing_mark_grid(&get_scanned_ballot_card_geometry_8pt5x11()),
+            BinarizationMethod::Otsu,
+        );
+        assert_eq!(
+            find_test_stray_marks(&img, &election, &target_templates, None).len(),
+            1
+        );
+        let stray_marks =
+            find_test_stray_marks(&img, &election, &target_templates, Some(&reference));
+        assert!(stray_marks.is_empty(), "{stray_marks:?}");
+
+        // an "X" next to the third candidate's oval
+        draw_x_mut(&mut img, Point::new(centers[2].x + 260.0, centers[2].y));
+        let stray_marks =
+            find_test_stray_marks(&img, &election, &target_templates, Some(&reference));
+        assert_eq!(stray_marks.len(), 1, "{stray_marks:?}");
+        assert_eq!(
+            stray_marks[0]
+                .nearest_grid_position
+                .as_ref()
+                .map(GridPosition::location),
+            Some(option_location(&election, 2))
+        );
+    }
+
+    #[test]
+    fn test_stray_marks_outside_usable_area_are_ignored() {
+        let election = test_election();
+        let grid_layout = &election.grid_layouts[0];
+        let target_templates = TargetTemplates::from_election(
+            &election,
+            &load_oval_template().unwrap(),
+            Path::new("."),
+        )
+        .unwrap();
+        let mut img = render_test_ballot_card(&election, &target_templates, &[]).back;
+
+        let geometry = get_scanned_ballot_card_geometry_8pt5x11();
+        draw_x_mut(&mut img, grid_location_center(&geometry, 26, 10));
+        draw_x_mut(&mut img, grid_location_center(&geometry, 26, 30));
+        let find_back_stray_marks = |geometry: &Geometry| {
+            let grid = timing_mark_grid(geometry);
+            let scored_oval_marks = score_oval_marks_from_grid_layout(
+                &img,
+                &target_templates,
+                &grid,
+                grid_layout,
+                BallotSide::Back,
+                BinarizationMethod::Otsu,
+                None,
+                &ImageDebugWriter::disabled(),
+            );
+            find_stray_marks(
+                &img,
+                &grid,
+                grid_layout,
+                BallotSide::Back,
+                &scored_oval_marks,
+                None,
+                BinarizationMethod::Otsu,
+                &ImageDebugWriter::disabled(),
+            )
+        };
+        assert_eq!(find_back_stray_marks(&geometry).len(), 2);
+
+        // only the top half of the back may be printed on
+        let geometry = Geometry {
+            back_usable_area: Rect::new(0, 0, 34, 20),
+            ..geometry
+        };
+        let stray_marks = find_back_stray_marks(&geometry);
+        assert_eq!(stray_marks.len(), 1, "{stray_marks:?}");
+        assert_eq!(
+            stray_marks[0].nearest_location,
+            GridLocation::new(BallotSide::Back, 26, 10)
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! Renders ballot card images from an election definition so that
+//! interpretation can be tested end to end without real scans.
+
+use image::{GrayImage, Luma};
+use imageproc::{
+    drawing::{draw_filled_ellipse_mut, draw_filled_rect_mut},
+    rect::Rect as ImageprocRect,
+};
+
+use crate::{
+    ballot_card::{BallotSide, BorderEdge, Geometry},
+    calibration::Calibration,
+    election::{Election, GridLayout, GridLocation},
+    geometry::{Point, Rect},
+    image_utils::{BLACK, WHITE},
+    metadata::{ENDER_CODE, METADATA_BITS},
+    target_templates::TargetTemplates,
+    timing_marks::{Complete, Partial, TimingMark, TimingMarkGrid},
+};
+
+/// The luma of a typical pencil or pen mark.
+pub const DEFAULT_MARK_LUMA: Luma<u8> = Luma([30]);
+
+/// Values encoded by the metadata timing marks on the front of a ballot card.
+#[derive(Debug, Clone, Copy)]
+pub struct FrontMetadataValues {
+    pub batch_or_precinct_number: u16,
+    pub card_number: u16,
+}
+
+/// Values encoded by the metadata timing marks on the back of a ballot card.
+#[derive(Debug, Clone, Copy)]
+pub struct BackMetadataValues {
+    pub election_day: u8,
+    pub election_month: u8,
+    pub election_year: u8,
+
+    /// A capital letter, e.g. 'G' for a general election.
+    pub election_type: char,
+}
+
+impl Default for BackMetadataValues {
+    fn default() -> Self {
+        Self {
+            election_day: 8,
+            election_month: 11,
+            election_year: 22,
+            election_type: 'G',
+        }
+    }
+}
+
+/// A voter's mark filling in the target at a grid location.
+#[derive(Debug, Clone, Copy)]
+pub struct SyntheticMark {
+    pub location: GridLocation,
+
+    /// How dark the ink is. Lighter marks are fainter.
+    pub luma: Luma<u8>,
+
+    /// How much of the target the mark covers, from its center outwards. 1.0
+    /// fills it to its outline.
+    pub coverage: f32,
+}
+
+impl SyntheticMark {
+    /// A dark mark filling the whole target at `location`.
+    pub const fn new(location: GridLocation) -> Self {
+        Self {
+            location,
+            luma: DEFAULT_MARK_LUMA,
+            coverage: 1.0,
+        }
+    }
+}
+
+/// The rendered pages of a synthetic ballot card.
+#[derive(Debug, Clone)]
+pub struct SyntheticBallotCard {
+    pub front: GrayImage,
+    pub back: GrayImage,
+}
+
+/// Writes the low bits of `value` into `bits`, least significant first.
+fn write_bits(bits: &mut [bool], value: u32) {
+    for (i, bit) in bits.iter_mut().enumerate() {
+        *bit = (value >> i) & 1 == 1;
+    }
+}
+
+/// Encodes the front metadata as bits in LSB-MSB order, the inverse of
+/// `decode_front_metadata_from_bits`.
+pub fn encode_front_metadata_bits(values: &FrontMetadataValues) -> [bool; METADATA_BITS] {
+    let mut bits = [false; METADATA_BITS];
+    write_bits(&mut bits[2..15], u32::from(values.batch_or_precinct_number));
+    write_bits(&mut bits[15..28], u32::from(values.card_number));
+    // the sequence number is always 0 and the start bit always 1
+    bits[31] = true;
+    let checksum = bits[2..].iter().filter(|&&bit| bit).count() % 4;
+    write_bits(&mut bits[0..2], checksum as u32);
+    bits
+}
+
+/// Encodes the back metadata as bits in LSB-MSB order, the inverse of
+/// `decode_back_metadata_from_bits`. Panics if the election type is not a
+/// capital letter, which is all the metadata can encode.
+pub fn encode_back_metadata_bits(values: &BackMetadataValues) -> [bool; METADATA_BITS] {
+    assert!(
+        values.election_type.is_ascii_uppercase(),
+        "election type must be a capital letter A-Z, got {:?}",
+        values.election_type
+    );
+    let mut bits = [false; METADATA_BITS];
+    write_bits(&mut bits[0..5], u32::from(values.election_day));
+    write_bits(&mut bits[5..9], u32::from(values.election_month));
+    write_bits(&mut bits[9..16], u32::from(values.election_year));
+    write_bits(
+        &mut bits[16..21],
+        u32::from(values.election_type) - u32::from('A'),
+    );
+    bits[21..].copy_from_slice(&ENDER_CODE);
+    bits
+}
+
+/// Finds the geometry of the election's ballots whose grid matches the size
+/// of `grid_layout`.
+pub fn geometry_for_grid_layout(election: &Election, grid_layout: &GridLayout) -> Option<Geometry> {
+    election.geometries().into_iter().find(|geometry| {
+        geometry.grid_size.width == grid_layout.columns
+            && geometry.grid_size.height == grid_layout.rows
+    })
+}
+
+/// The center of the timing mark, or target, at a grid location. Like printed
+/// ballots, the timing marks are evenly spaced in both directions, with the
+/// grid as large as fits in the content area and centered in it.
+pub fn grid_location_center(geometry: &Geometry, column: u32, row: u32) -> Point<f32> {
+    let content_area = &geometry.content_area;
+    let timing_mark_size = &geometry.timing_mark_size;
+    let grid_size = &geometry.grid_size;
+    let pitch = f32::min(
+        (content_area.width() as f32 - timing_mark_size.width) / (grid_size.width - 1) as f32,
+        (content_area.height() as f32 - timing_mark_size.height) / (grid_size.height - 1) as f32,
+    );
+    let grid_width = pitch.mul_add((grid_size.width - 1) as f32, timing_mark_size.width);
+    let grid_height = pitch.mul_add((grid_size.height - 1) as f32, timing_mark_size.height);
+    let left = (content_area.width() as f32 - grid_width).mul_add(
+        0.5,
+        content_area.left() as f32 + timing_mark_size.width / 2.0,
+    );
+    let top = (content_area.height() as f32 - grid_height).mul_add(
+        0.5,
+        content_area.top() as f32 + timing_mark_size.height / 2.0,
+    );
+    Point::new(
+        (column as f32).mul_add(pitch, left),
+        (row as f32).mul_add(pitch, top),
+    )
+}
+
+/// The bounds of the timing mark at a grid location, as rendered.
+fn timing_mark_bounds(geometry: &Geometry, column: u32, row: u32) -> Rect {
+    let center = grid_location_center(geometry, column, row);
+    let size = &geometry.timing_mark_size;
+    Rect::new(
+        (center.x - size.width / 2.0).round() as i32,
+        (center.y - size.height / 2.0).round() as i32,
+        size.width.round() as u32,
+        size.height.round() as u32,
+    )
+}
+
+/// The timing mark grid of a page rendered with `geometry`, as if it had been
+/// found in the image, for testing the stages of interpretation after it.
+pub fn timing_mark_grid(geometry: &Geometry) -> TimingMarkGrid {
+    let (columns, rows) = (geometry.grid_size.width, geometry.grid_size.height);
+    let mark_at = |column, row| TimingMark {
+        rect: timing_mark_bounds(geometry, column, row),
+        center: grid_location_center(geometry, column, row),
+    };
+    let top_marks = (0..columns)
+        .map(|column| mark_at(column, 0))
+        .collect::<Vec<_>>();
+    let bottom_marks = (0..columns)
+        .map(|column| mark_at(column, rows - 1))
+        .collect::<Vec<_>>();
+    let left_marks = (0..rows).map(|row| mark_at(0, row)).collect::<Vec<_>>();
+    let right_marks = (0..rows)
+        .map(|row| mark_at(columns - 1, row))
+        .collect::<Vec<_>>();
+
+    let (top_left_mark, top_right_mark) = (top_marks[0], top_marks[columns as usize - 1]);
+    let (bottom_left_mark, bottom_right_mark) =
+        (bottom_marks[0], bottom_marks[columns as usize - 1]);
+    let complete = Complete {
+        geometry: *geometry,
+        top_left_corner: top_left_mark.center,
+        top_right_corner: top_right_mark.center,
+        bottom_left_corner: bottom_left_mark.center,
+        bottom_right_corner: bottom_right_mark.center,
+        top_marks,
+        bottom_marks,
+        left_marks,
+        right_marks,
+        top_left_mark,
+        top_right_mark,
+        bottom_left_mark,
+        bottom_right_mark,
+    };
+    TimingMarkGrid::new(
+        *geometry,
+        Calibration::nominal(geometry),
+        Partial::from(complete.clone()),
+        complete,
+        vec![],
+        None,
+    )
+}
+
+/// The grid locations of the timing marks along an edge, in the order the
+/// metadata is read: left to right, or top to bottom.
+fn edge_locations(geometry: &Geometry, edge: BorderEdge) -> Vec<(u32, u32)> {
+    let (columns, rows) = (geometry.grid_size.width, geometry.grid_size.height);
+    match edge {
+        BorderEdge::Top => (0..columns).map(|column| (column, 0)).collect(),
+        BorderEdge::Bottom => (0..columns).map(|column| (column, rows - 1)).collect(),
+        BorderEdge::Left => (0..rows).map(|row| (0, row)).collect(),
+        BorderEdge::Right => (0..rows).map(|row| (columns - 1, row)).collect(),
+    }
+}
+
+/// Draws the timing marks of one page, leaving out the marks on the metadata
+/// edge for `metadata_bits` that are 0.
+fn draw_timing_marks(img: &mut GrayImage, geometry: &Geometry, metadata_bits: &[bool]) {
+    let layout = &geometry.timing_mark_layout;
+
+    for (edge, has_timing_marks) in [
+        (BorderEdge::Top, layout.top),
+        (BorderEdge::Bottom, layout.bottom),
+        (BorderEdge::Left, layout.left),
+        (BorderEdge::Right, layout.right),
+    ] {
+        if !has_timing_marks {
+            continue;
+        }
+
+        let locations = edge_locations(geometry, edge);
+        let is_metadata_edge = layout.metadata_edge == Some(edge);
+        for (index, (column, row)) in locations.iter().enumerate() {
+            // bits are read from the mark next to the last corner backwards
+            let bit = (locations.len() - 1)
+                .checked_sub(index + 1)
+                .and_then(|bit| metadata_bits.get(bit));
+            if is_metadata_edge && index > 0 && bit == Some(&false) {
+                continue;
+            }
+
+            let bounds = timing_mark_bounds(geometry, *column, *row);
+            draw_filled_rect_mut(
+                img,
+                ImageprocRect::at(bounds.left(), bounds.top())
+                    .of_size(bounds.width(), bounds.height()),
+                BLACK,
+            );
+        }
+    }
+}
+
+/// Draws `template` centered on `center`, keeping whatever ink is already on
+/// the page under its white pixels.
+fn draw_template(img: &mut GrayImage, template: &GrayImage, center: Point<f32>) {
+    let left = center.x.round() as i64 - i64::from(template.width() / 2);
+    let top = center.y.round() as i64 - i64::from(template.height() / 2);
+    for (x, y, pixel) in template.enumerate_pixels() {
+        let (x, y) = (left + i64::from(x), top + i64::from(y));
+        if let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) {
+            if x < img.width() && y < img.height() {
+                let existing = img.get_pixel_mut(x, y);
+                existing.0[0] = existing.0[0].min(pixel.0[0]);
+            }
+        }
+    }
+}
+
+/// Renders one page of a ballot card.
+fn render_page(
+    geometry: &Geometry,
+    grid_layout: &GridLayout,
+    target_templates: &TargetTemplates,
+    side: BallotSide,
+    metadata_bits: &[bool],
+    marks: &[SyntheticMark],
+) -> GrayImage {
+    let mut img = GrayImage::from_pixel(
+        geometry.canvas_size.width,
+        geometry.canvas_size.height,
+        WHITE,
+    );
+    draw_timing_marks(&mut img, geometry, metadata_bits);
+
+    for grid_position in &grid_layout.grid_positions {
+        let location = grid_position.location();
+        if location.side == side {
+            draw_template(
+                &mut img,
+                target_templates.template_for(grid_position),
+                grid_location_center(geometry, location.column, location.row),
+            );
+        }
+    }
+
+    for mark in marks.iter().filter(|mark| mark.location.side == side) {
+        let template = grid_layout
+            .grid_positions
+            .iter()
+            .find(|grid_position| grid_position.location() == mark.location)
+            .map_or_else(
+                || target_templates.default_template(),
+                |grid_position| target_templates.template_for(grid_position),
+            );
+        let center = grid_location_center(geometry, mark.location.column, mark.location.row);
+        draw_filled_ellipse_mut(
+            &mut img,
+            (center.x.round() as i32, center.y.round() as i32),
+            (template.width() as f32 * mark.coverage / 2.0).round() as i32,
+            (template.height() as f32 * mark.coverage / 2.0).round() as i32,
+            mark.luma,
+        );
+    }
+
+    img
+}
+
+/// Renders both pages of a ballot card for `grid_layout` at the resolution of
+/// the matching election geometry, with timing marks encoding the given
+/// metadata, a target at each grid position, and the given marks. Returns
+/// `None` if no geometry matches the grid layout.
+pub fn render_ballot_card(
+    election: &Election,
+    grid_layout: &GridLayout,
+    target_templates: &TargetTemplates,
+    front_metadata: &FrontMetadataValues,
+    back_metadata: &BackMetadataValues,
+    marks: &[SyntheticMark],
+) -> Option<SyntheticBallotCard> {
+    let geometry = geometry_for_grid_layout(election, grid_layout)?;
+    let render = |side, metadata_bits: &[bool]| {
+        render_page(
+            &geometry,
+            grid_layout,
+            target_templates,
+            side,
+            metadata_bits,
+            marks,
+        )
+    };
+
+    Some(SyntheticBallotCard {
+        front: render(
+            BallotSide::Front,
+            &encode_front_metadata_bits(front_metadata),
+        ),
+        back: render(BallotSide::Back, &encode_back_metadata_bits(back_metadata)),
+    })
+}
+
+/// An election with a single letter-size ballot style, card 3, with three
+/// targets down a column of the front and two across a row of the back.
+pub fn test_election() -> Election {
+    serde_json::from_str(
+        r#"{
+            "title": "Test",
+            "gridLayouts": [{
+                "precinctId": "precinct-1",
+                "ballotStyleId": "card-number-3",
+                "columns": 34,
+                "rows": 41,
+                "gridPositions": [
+                    { "type": "option", "side": "front", "column": 12, "row": 8, "contestId": "mayor", "optionId": "alice" },
+                    { "type": "option", "side": "front", "column": 12, "row": 10, "contestId": "mayor", "optionId": "bob" },
+                    { "type": "write-in", "side": "front", "column": 12, "row": 12, "contestId": "mayor", "writeInIndex": 0 },
+                    { "type": "option", "side": "back", "column": 20, "row": 30, "contestId": "measure-1", "optionId": "yes" },
+                    { "type": "option", "side": "back", "column": 22, "row": 30, "contestId": "measure-1", "optionId": "no" }
+                ]
+            }]
+        }"#,
+    )
+    .unwrap()
+}
+
+/// Renders the first grid layout of `election` as card 3 of precinct 1, the
+/// card `test_election` describes, with the given marks.
+pub fn render_test_ballot_card(
+    election: &Election,
+    target_templates: &TargetTemplates,
+    marks: &[SyntheticMark],
+) -> SyntheticBallotCard {
+    render_ballot_card(
+        election,
+        &election.grid_layouts[0],
+        target_templates,
+        &FrontMetadataValues {
+            batch_or_precinct_number: 1,
+            card_number: 3,
+        },
+        &BackMetadataValues::default(),
+        marks,
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{decode_back_metadata_from_bits, decode_front_metadata_from_bits};
+
+    #[test]
+    fn test_encode_front_metadata_bits() {
+        let metadata =
+            decode_front_metadata_from_bits(&encode_front_metadata_bits(&FrontMetadataValues {
+                batch_or_precinct_number: 42,
+                card_number: 7,
+            }))
+            .unwrap();
+        assert_eq!(metadata.batch_or_precinct_number, 42);
+        assert_eq!(metadata.card_number, 7);
+        assert_eq!(metadata.sequence_number, 0);
+    }
+
+    #[test]
+    fn test_encode_back_metadata_bits() {
+        let metadata =
+            decode_back_metadata_from_bits(&encode_back_metadata_bits(&BackMetadataValues {
+                election_day: 3,
+                election_month: 11,
+                election_year: 20,
+                election_type: 'P',
+            }))
+            .unwrap();
+        assert_eq!(
+            (
+                metadata.election_day,
+                metadata.election_month,
+                metadata.election_year,
+                metadata.election_type.to_char()
+            ),
+            (3, 11, 20, 'P')
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "election type must be a capital letter")]
+    fn test_encode_back_metadata_bits_rejects_lowercase_election_type() {
+        encode_back_metadata_bits(&BackMetadataValues {
+            election_type: 'g',
+            ..BackMetadataValues::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "election type must be a capital letter")]
+    fn test_encode_back_metadata_bits_rejects_election_type_before_a() {
+        encode_back_metadata_bits(&BackMetadataValues {
+            election_type: '@',
+            ..BackMetadataValues::default()
+        });
+    }
+}
@@ -0,0 +1,420 @@
+use std::{collections::HashMap, path::Path};
+
+use image::GrayImage;
+use imageproc::{
+    drawing::{draw_filled_rect_mut, draw_polygon_mut},
+    point::Point as ImageprocPoint,
+    rect::Rect as ImageprocRect,
+};
+use serde::Serialize;
+
+use crate::{
+    ballot_card::binarize_target_scan,
+    calibration::Calibration,
+    election::{Election, GridPosition, TargetShape},
+    image_utils::{ImageOpenFailureCause, BLACK, WHITE},
+    template_matching::BinaryTemplate,
+};
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Error {
+    ImageReadFailure {
+        name: String,
+        path: String,
+        cause: ImageOpenFailureCause,
+    },
+    InvalidDimensions {
+        name: String,
+    },
+    UnknownTargetShape {
+        name: String,
+    },
+}
+
+/// A template for a target shape, along with the part of it a voter fills in
+/// to mark it, if that is only part of what's inside the outline, e.g. the
+/// gap in an arrow. The fill region is dark on a light background.
+#[derive(Debug, Clone)]
+struct Target<T> {
+    template: T,
+    fill_region: Option<T>,
+}
+
+/// The templates for each of an election's target shapes, used to find and
+/// score the target at each grid position.
+#[derive(Debug, Clone)]
+pub struct TargetTemplates<T = GrayImage> {
+    default: Target<T>,
+    by_name: HashMap<String, Target<T>>,
+}
+
+impl TargetTemplates {
+    /// Builds binarized templates for every target shape defined by
+    /// `election`, using `oval_template` for the built-in oval. Relative image
+    /// paths are resolved from `election_dir`.
+    pub fn from_election(
+        election: &Election,
+        oval_template: &GrayImage,
+        election_dir: &Path,
+    ) -> Result<Self, Error> {
+        let by_name = election
+            .target_shapes
+            .iter()
+            .map(|(name, shape)| {
+                render_target_template(name, shape, oval_template, election_dir)
+                    .map(|target| (name.clone(), target))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        let default = match &election.default_target_shape {
+            Some(name) => match by_name.get(name) {
+                Some(target) => target.clone(),
+                None => return Err(Error::UnknownTargetShape { name: name.clone() }),
+            },
+            None => Target {
+                template: oval_template.clone(),
+                fill_region: None,
+            },
+        };
+
+        if let Some(name) = election
+            .grid_layouts
+            .iter()
+            .flat_map(|grid_layout| &grid_layout.grid_positions)
+            .filter_map(GridPosition::target_shape)
+            .find(|name| !by_name.contains_key(*name))
+        {
+            return Err(Error::UnknownTargetShape {
+                name: name.to_string(),
+            });
+        }
+
+        Ok(Self { default, by_name })
+    }
+
+    /// Scales every template and its fill region by `calibration` and
+    /// prepares them for matching.
+    pub fn binary_templates(&self, calibration: &Calibration) -> TargetTemplates<BinaryTemplate> {
+        let binary_target = |target: &Target<GrayImage>| Target {
+            template: BinaryTemplate::with_fill_region(
+                &calibration.scale_oval_template(&target.template),
+                target
+                    .fill_region
+                    .as_ref()
+                    .map(|fill_region| calibration.scale_oval_template(fill_region))
+                    .as_ref(),
+            ),
+            fill_region: None,
+        };
+        TargetTemplates {
+            default: binary_target(&self.default),
+            by_name: self
+                .by_name
+                .iter()
+                .map(|(name, target)| (name.clone(), binary_target(target)))
+                .collect(),
+        }
+    }
+}
+
+impl<T> TargetTemplates<T> {
+    /// Gets the template for the target at `grid_position`.
+    pub fn template_for(&self, grid_position: &GridPosition) -> &T {
+        &grid_position
+            .target_shape()
+            .and_then(|name| self.by_name.get(name))
+            .unwrap_or(&self.default)
+            .template
+    }
+
+    /// Gets the template for grid positions that don't specify a target shape.
+    #[cfg(test)]
+    pub const fn default_template(&self) -> &T {
+        &self.default.template
+    }
+
+    /// Iterates over every template: the default one, then one for each
+    /// named target shape.
+    pub fn templates(&self) -> impl Iterator<Item = &T> {
+        std::iter::once(&self.default)
+            .chain(self.by_name.values())
+            .map(|target| &target.template)
+    }
+
+    /// Converts every template and fill region, e.g. to scale it.
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> TargetTemplates<U> {
+        let map_target = |target: &Target<T>| Target {
+            template: f(&target.template),
+            fill_region: target.fill_region.as_ref().map(&f),
+        };
+        TargetTemplates {
+            default: map_target(&self.default),
+            by_name: self
+                .by_name
+                .iter()
+                .map(|(name, target)| (name.clone(), map_target(target)))
+                .collect(),
+        }
+    }
+}
+
+/// Builds the binarized template image for a target shape and, for shapes
+/// that are marked by filling in only part of them, their fill region.
+fn render_target_template(
+    name: &str,
+    shape: &TargetShape,
+    oval_template: &GrayImage,
+    election_dir: &Path,
+) -> Result<Target<GrayImage>, Error> {
+    let invalid_dimensions = || Error::InvalidDimensions {
+        name: name.to_string(),
+    };
+
+    match shape {
+        TargetShape::Oval => Ok(Target {
+            template: oval_template.clone(),
+            fill_region: None,
+        }),
+
+        TargetShape::Image { path } => {
+            let path = election_dir.join(path);
+            match image::open(&path) {
+                Ok(img) => Ok(Target {
+                    template: binarize_target_scan(&img.to_luma8()),
+                    fill_region: None,
+                }),
+                Err(error) => Err(Error::ImageReadFailure {
+                    name: name.to_string(),
+                    path: path.display().to_string(),
+                    cause: error.into(),
+                }),
+            }
+        }
+
+        &TargetShape::Rectangle {
+            width,
+            height,
+            line_width,
+        } => {
+            if line_width == 0 || width <= line_width * 2 || height <= line_width * 2 {
+                return Err(invalid_dimensions());
+            }
+
+            let mut template = GrayImage::from_pixel(width, height, WHITE);
+            for rect in [
+                ImageprocRect::at(0, 0).of_size(width, line_width),
+                ImageprocRect::at(0, (height - line_width) as i32).of_size(width, line_width),
+                ImageprocRect::at(0, 0).of_size(line_width, height),
+                ImageprocRect::at((width - line_width) as i32, 0).of_size(line_width, height),
+            ] {
+                draw_filled_rect_mut(&mut template, rect, BLACK);
+            }
+
+            // the voter fills in the inside of the rectangle
+            let mut fill_region = GrayImage::from_pixel(width, height, WHITE);
+            draw_filled_rect_mut(
+                &mut fill_region,
+                ImageprocRect::at(line_width as i32, line_width as i32)
+                    .of_size(width - line_width * 2, height - line_width * 2),
+                BLACK,
+            );
+            Ok(Target {
+                template,
+                fill_region: Some(fill_region),
+            })
+        }
+
+        &TargetShape::Arrow {
+            width,
+            height,
+            gap_width,
+            line_width,
+        } => {
+            let head_length = height / 2;
+            let shaft_length = width.saturating_sub(head_length + line_width);
+            if line_width == 0 || line_width >= height || gap_width >= shaft_length {
+                return Err(invalid_dimensions());
+            }
+
+            // the tail is a vertical bar on the left, followed by the shaft
+            // with a gap in the middle, and then a filled triangular head
+            let shaft_top = ((height - line_width) / 2) as i32;
+            let left_shaft_length = (shaft_length - gap_width) / 2;
+            let right_shaft_start = line_width + left_shaft_length + gap_width;
+
+            let mut template = GrayImage::from_pixel(width, height, WHITE);
+            draw_filled_rect_mut(
+                &mut template,
+                ImageprocRect::at(0, 0).of_size(line_width, height),
+                BLACK,
+            );
+            if left_shaft_length > 0 {
+                draw_filled_rect_mut(
+                    &mut template,
+                    ImageprocRect::at(line_width as i32, shaft_top)
+                        .of_size(left_shaft_length, line_width),
+                    BLACK,
+                );
+            }
+            draw_filled_rect_mut(
+                &mut template,
+                ImageprocRect::at(right_shaft_start as i32, shaft_top)
+                    .of_size(width - head_length - right_shaft_start, line_width),
+                BLACK,
+            );
+            draw_polygon_mut(
+                &mut template,
+                &[
+                    ImageprocPoint::new((width - head_length) as i32, 0),
+                    ImageprocPoint::new(width as i32 - 1, (height / 2) as i32),
+                    ImageprocPoint::new((width - head_length) as i32, height as i32 - 1),
+                ],
+                BLACK,
+            );
+
+            // the voter draws a line across the gap, which counts a little
+            // above or below the shaft too
+            let mut fill_region = GrayImage::from_pixel(width, height, WHITE);
+            draw_filled_rect_mut(
+                &mut fill_region,
+                ImageprocRect::at((line_width + left_shaft_length) as i32, 0)
+                    .of_size(gap_width, height),
+                BLACK,
+            );
+            Ok(Target {
+                template,
+                fill_region: Some(fill_region),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ballot_card::{load_oval_template, BallotSide};
+    use crate::election::{ContestId, OptionId};
+
+    fn election_with_target_shapes(json: &str) -> Election {
+        serde_json::from_str(&format!(
+            r#"{{
+                "title": "Test",
+                "gridLayouts": [],
+                {json}
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    fn grid_position(target_shape: Option<&str>) -> GridPosition {
+        GridPosition::Option {
+            side: BallotSide::Front,
+            column: 1,
+            row: 1,
+            contest_id: ContestId::from("contest-1".to_string()),
+            option_id: OptionId::from("option-1".to_string()),
+            target_shape: target_shape.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_template_for_grid_position() {
+        let oval_template = load_oval_template().unwrap();
+        let election = election_with_target_shapes(
+            r#""targetShapes": {
+                "box": { "type": "rectangle", "width": 30, "height": 20 }
+            }"#,
+        );
+        let templates =
+            TargetTemplates::from_election(&election, &oval_template, Path::new(".")).unwrap();
+
+        assert_eq!(templates.template_for(&grid_position(None)), &oval_template);
+        let box_template = templates.template_for(&grid_position(Some("box")));
+        assert_eq!(box_template.dimensions(), (30, 20));
+        assert_eq!(box_template.get_pixel(0, 0), &BLACK);
+        assert_eq!(box_template.get_pixel(15, 10), &WHITE);
+        assert_eq!(box_template.get_pixel(28, 19), &BLACK);
+    }
+
+    #[test]
+    fn test_default_target_shape() {
+        let oval_template = load_oval_template().unwrap();
+        let election = election_with_target_shapes(
+            r#""targetShapes": {
+                "arrow": { "type": "arrow", "width": 80, "height": 20, "gapWidth": 30 }
+            },
+            "defaultTargetShape": "arrow""#,
+        );
+        let templates =
+            TargetTemplates::from_election(&election, &oval_template, Path::new(".")).unwrap();
+
+        let arrow_template = templates.template_for(&grid_position(None));
+        assert_eq!(arrow_template.dimensions(), (80, 20));
+        // tail, gap, shaft, and head along the middle of the arrow
+        assert_eq!(arrow_template.get_pixel(0, 10), &BLACK);
+        assert_eq!(arrow_template.get_pixel(10, 10), &BLACK);
+        assert_eq!(arrow_template.get_pixel(35, 10), &WHITE);
+        assert_eq!(arrow_template.get_pixel(60, 10), &BLACK);
+        assert_eq!(arrow_template.get_pixel(78, 10), &BLACK);
+        assert_eq!(arrow_template.get_pixel(35, 2), &WHITE);
+    }
+
+    #[test]
+    fn test_unknown_target_shape() {
+        let oval_template = load_oval_template().unwrap();
+        let election = election_with_target_shapes(r#""defaultTargetShape": "missing""#);
+
+        assert!(matches!(
+            TargetTemplates::from_election(&election, &oval_template, Path::new(".")),
+            Err(Error::UnknownTargetShape { name }) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_target_shape_dimensions() {
+        let oval_template = load_oval_template().unwrap();
+        let election = election_with_target_shapes(
+            r#""targetShapes": {
+                "arrow": { "type": "arrow", "width": 40, "height": 20, "gapWidth": 40 }
+            }"#,
+        );
+
+        assert!(matches!(
+            TargetTemplates::from_election(&election, &oval_template, Path::new(".")),
+            Err(Error::InvalidDimensions { name }) if name == "arrow"
+        ));
+    }
+
+    #[test]
+    fn test_image_target_shape() {
+        let oval_template = load_oval_template().unwrap();
+        let election_dir = tempfile::tempdir().unwrap();
+        let mut scan = GrayImage::from_pixel(20, 10, WHITE);
+        draw_filled_rect_mut(&mut scan, ImageprocRect::at(5, 3).of_size(10, 4), BLACK);
+        scan.save(election_dir.path().join("target.png")).unwrap();
+
+        let election = election_with_target_shapes(
+            r#""targetShapes": {
+                "scan": { "type": "image", "path": "target.png" },
+                "missing": { "type": "image", "path": "missing.png" }
+            }"#,
+        );
+        assert!(matches!(
+            TargetTemplates::from_election(&election, &oval_template, election_dir.path()),
+            Err(Error::ImageReadFailure { name, .. }) if name == "missing"
+        ));
+
+        let election = election_with_target_shapes(
+            r#""targetShapes": {
+                "scan": { "type": "image", "path": "target.png" }
+            }"#,
+        );
+        let templates =
+            TargetTemplates::from_election(&election, &oval_template, election_dir.path()).unwrap();
+        let template = templates.template_for(&grid_position(Some("scan")));
+        assert_eq!(template.dimensions(), (20, 10));
+        assert_eq!(template.get_pixel(10, 5), &BLACK);
+        assert_eq!(template.get_pixel(1, 1), &WHITE);
+    }
+}
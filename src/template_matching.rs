@@ -0,0 +1,327 @@
+use image::GrayImage;
+
+use crate::mark_features::find_template_interior;
+
+const WORD_BITS: u32 = u64::BITS;
+
+/// A binarized image stored with one bit per pixel, packed into 64-bit words
+/// row by row. Set bits are dark pixels, i.e. those that would be black after
+/// thresholding. Comparing a template against a window of the image is then a
+/// handful of AND + popcount operations per row instead of a per-pixel diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitImage {
+    width: u32,
+    height: u32,
+    words_per_row: usize,
+    words: Vec<u64>,
+}
+
+impl BitImage {
+    /// Binarizes `img` such that pixels at or below `threshold` are dark. This
+    /// matches the behavior of `imageproc::contrast::threshold`.
+    pub fn from_gray_image(img: &GrayImage, threshold: u8) -> Self {
+        let (width, height) = img.dimensions();
+        let mut bit_image = Self::new(width, height);
+
+        for (y, row) in img.rows().enumerate() {
+            let row_start = y * bit_image.words_per_row;
+            for (x, pixel) in row.enumerate() {
+                if pixel.0[0] <= threshold {
+                    bit_image.words[row_start + x / WORD_BITS as usize] |=
+                        1 << (x as u32 % WORD_BITS);
+                }
+            }
+        }
+
+        bit_image
+    }
+
+    /// Creates an image with every pixel dark.
+    pub fn filled(width: u32, height: u32) -> Self {
+        let mut bit_image = Self::new(width, height);
+
+        for y in 0..height as usize {
+            let row_start = y * bit_image.words_per_row;
+            for x in (0..width).step_by(WORD_BITS as usize) {
+                let bits = (width - x).min(WORD_BITS);
+                bit_image.words[row_start + (x / WORD_BITS) as usize] = if bits == WORD_BITS {
+                    u64::MAX
+                } else {
+                    (1 << bits) - 1
+                };
+            }
+        }
+
+        bit_image
+    }
+
+    fn new(width: u32, height: u32) -> Self {
+        let words_per_row = width.div_ceil(WORD_BITS) as usize;
+        Self {
+            width,
+            height,
+            words_per_row,
+            words: vec![0; words_per_row * height as usize],
+        }
+    }
+
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Determines whether the pixel at (x, y) is dark.
+    pub fn is_dark(&self, x: u32, y: u32) -> bool {
+        self.word_at(x, y) & 1 == 1
+    }
+
+    /// Counts the dark pixels in the image.
+    pub fn count_dark_pixels(&self) -> u32 {
+        self.words.iter().map(|word| word.count_ones()).sum()
+    }
+
+    /// Counts the pixels that are dark both in `template` and in the window of
+    /// this image whose top-left corner is at (x, y). The window must fit
+    /// within this image.
+    pub fn count_overlapping_dark_pixels(&self, template: &Self, x: u32, y: u32) -> u32 {
+        debug_assert!(x + template.width <= self.width && y + template.height <= self.height);
+
+        (0..template.height)
+            .map(|row| {
+                template
+                    .row(row)
+                    .iter()
+                    .zip((x..).step_by(WORD_BITS as usize))
+                    .map(|(template_word, word_x)| {
+                        (self.word_at(word_x, y + row) & template_word).count_ones()
+                    })
+                    .sum::<u32>()
+            })
+            .sum()
+    }
+
+    fn row(&self, y: u32) -> &[u64] {
+        let row_start = y as usize * self.words_per_row;
+        &self.words[row_start..row_start + self.words_per_row]
+    }
+
+    /// Gets the `WORD_BITS` pixels of row `y` starting at column `x`, with the
+    /// pixel at `x` in the lowest bit. Pixels past the end of the row are not
+    /// dark.
+    fn word_at(&self, x: u32, y: u32) -> u64 {
+        let row = self.row(y);
+        let index = (x / WORD_BITS) as usize;
+        let shift = x % WORD_BITS;
+        let low = row.get(index).copied().unwrap_or(0) >> shift;
+
+        if shift == 0 {
+            low
+        } else {
+            low | row.get(index + 1).copied().unwrap_or(0) << (WORD_BITS - shift)
+        }
+    }
+}
+
+/// A binarized oval template prepared for matching against a `BitImage`.
+pub struct BinaryTemplate {
+    /// The binarized template image, kept for building debug images.
+    pub image: GrayImage,
+
+    /// The pixels inside the template's outline, where a voter's ink is
+    /// measured. This is the same for every oval, so it is found once.
+    pub interior: Vec<(u32, u32)>,
+    bits: BitImage,
+    window: BitImage,
+    dark_pixel_count: u32,
+
+    /// The pixels counted by the fill score, if the target is marked by
+    /// filling in only part of it, e.g. the gap in an arrow.
+    fill_region: Option<BitImage>,
+    fill_region_pixel_count: u32,
+}
+
+impl BinaryTemplate {
+    /// Prepares a binarized template, i.e. one with only black and white
+    /// pixels, for matching.
+    #[cfg(test)]
+    pub fn new(template: &GrayImage) -> Self {
+        Self::with_fill_region(template, None)
+    }
+
+    /// Prepares a binarized template for matching whose fill score only
+    /// counts the dark pixels of `fill_region`, which is the same size.
+    pub fn with_fill_region(template: &GrayImage, fill_region: Option<&GrayImage>) -> Self {
+        let bits = BitImage::from_gray_image(template, u8::MAX / 2);
+        let fill_region =
+            fill_region.map(|fill_region| BitImage::from_gray_image(fill_region, u8::MAX / 2));
+        Self {
+            image: template.clone(),
+            interior: find_template_interior(template),
+            window: BitImage::filled(bits.width(), bits.height()),
+            dark_pixel_count: bits.count_dark_pixels(),
+            fill_region_pixel_count: fill_region
+                .as_ref()
+                .map_or(bits.width() * bits.height(), BitImage::count_dark_pixels),
+            fill_region,
+            bits,
+        }
+    }
+
+    pub const fn width(&self) -> u32 {
+        self.bits.width()
+    }
+
+    pub const fn height(&self) -> u32 {
+        self.bits.height()
+    }
+
+    /// Scores how well the template matches the window of `img` whose top-left
+    /// corner is at (x, y). This is the ratio of pixels that are not dark in the
+    /// template but light in `img`, so 100% is a perfect match.
+    pub fn match_score(&self, img: &BitImage, x: u32, y: u32) -> f32 {
+        let overlap = img.count_overlapping_dark_pixels(&self.bits, x, y);
+        let missing = self.dark_pixel_count - overlap;
+        1.0 - missing as f32 / self.pixel_count() as f32
+    }
+
+    /// Scores how filled in the template is within the window of `img` whose
+    /// top-left corner is at (x, y). This is the ratio of pixels that are dark
+    /// in `img` but light in the template, so 100% is perfectly filled. With
+    /// a fill region, it is the ratio of the fill region that is dark instead.
+    pub fn fill_score(&self, img: &BitImage, x: u32, y: u32) -> f32 {
+        let filled = match &self.fill_region {
+            Some(fill_region) => img.count_overlapping_dark_pixels(fill_region, x, y),
+            None => {
+                let dark = img.count_overlapping_dark_pixels(&self.window, x, y);
+                let overlap = img.count_overlapping_dark_pixels(&self.bits, x, y);
+                dark - overlap
+            }
+        };
+        filled as f32 / self.fill_region_pixel_count.max(1) as f32
+    }
+
+    /// Determines whether the pixel at (x, y) of the template counts towards
+    /// its fill score.
+    pub fn is_in_fill_region(&self, x: u32, y: u32) -> bool {
+        self.fill_region
+            .as_ref()
+            .is_none_or(|fill_region| fill_region.is_dark(x, y))
+    }
+
+    /// The number of pixels that count towards the fill score.
+    pub const fn fill_region_pixel_count(&self) -> u32 {
+        self.fill_region_pixel_count
+    }
+
+    /// Scores how much of the template is present in the window of `img` whose
+    /// top-left corner is at (x, y). This is the ratio of the template's dark
+    /// pixels that are also dark in `img`, so 100% is a complete outline.
+    pub fn outline_coverage(&self, img: &BitImage, x: u32, y: u32) -> f32 {
+        let overlap = img.count_overlapping_dark_pixels(&self.bits, x, y);
+        overlap as f32 / self.dark_pixel_count.max(1) as f32
+    }
+
+    fn pixel_count(&self) -> u32 {
+        self.width() * self.height()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, Luma};
+    use imageproc::contrast::threshold;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::image_utils::{diff, ratio, BLACK, WHITE};
+
+    fn gray_image(width: u32, height: u32, pixels: &[u8]) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            Luma([pixels[(y * width + x) as usize % pixels.len()]])
+        })
+    }
+
+    #[test]
+    fn test_bit_image_from_gray_image() {
+        let img = gray_image(70, 2, &[0, 200, 100, 255]);
+        let bits = BitImage::from_gray_image(&img, 100);
+
+        for (x, y, pixel) in img.enumerate_pixels() {
+            assert_eq!(bits.is_dark(x, y), pixel.0[0] <= 100, "({x}, {y})");
+        }
+        assert_eq!(bits.count_dark_pixels(), 70);
+    }
+
+    #[test]
+    fn test_bit_image_filled() {
+        let bits = BitImage::filled(130, 3);
+        assert_eq!(bits.count_dark_pixels(), 390);
+        assert!(bits.is_dark(129, 2));
+    }
+
+    #[test]
+    fn test_fill_score_with_fill_region() {
+        // a 20x10 box whose fill region is its left half
+        let template_image = GrayImage::from_fn(20, 10, |x, y| {
+            if x == 0 || y == 0 || x == 19 || y == 9 {
+                BLACK
+            } else {
+                WHITE
+            }
+        });
+        let fill_region = GrayImage::from_fn(20, 10, |x, _| if x < 10 { BLACK } else { WHITE });
+        let template = BinaryTemplate::with_fill_region(&template_image, Some(&fill_region));
+        assert_eq!(template.fill_region_pixel_count(), 100);
+        assert!(template.is_in_fill_region(9, 5));
+        assert!(!template.is_in_fill_region(10, 5));
+
+        // ink outside the fill region doesn't count
+        let right_half = BitImage::from_gray_image(
+            &GrayImage::from_fn(20, 10, |x, _| if x < 10 { WHITE } else { BLACK }),
+            127,
+        );
+        assert_eq!(template.fill_score(&right_half, 0, 0), 0.0);
+
+        let left_half = BitImage::from_gray_image(&fill_region, 127);
+        assert_eq!(template.fill_score(&left_half, 0, 0), 1.0);
+    }
+
+    proptest! {
+        #[test]
+        fn test_scores_match_image_diff(
+            pixels in prop::collection::vec(any::<bool>(), 1..200),
+            template_pixels in prop::collection::vec(any::<bool>(), 1..50),
+            template_width in 1u32..80,
+            template_height in 1u32..12,
+            x in 0u32..40,
+            y in 0u32..8,
+        ) {
+            let to_luma = |dark: &bool| if *dark { 0 } else { 255 };
+            let img = gray_image(
+                120,
+                20,
+                &pixels.iter().map(to_luma).collect::<Vec<_>>(),
+            );
+            let template_image = gray_image(
+                template_width,
+                template_height,
+                &template_pixels.iter().map(to_luma).collect::<Vec<_>>(),
+            );
+
+            let template = BinaryTemplate::new(&template_image);
+            let bits = BitImage::from_gray_image(&img, 127);
+            let binarized_window = threshold(
+                &img.view(x, y, template_width, template_height).to_image(),
+                127,
+            );
+
+            let expected_match_score = ratio(&diff(&binarized_window, &template_image), WHITE);
+            let expected_fill_score = ratio(&diff(&template_image, &binarized_window), BLACK);
+            prop_assert!((template.match_score(&bits, x, y) - expected_match_score).abs() < 1e-6);
+            prop_assert!((template.fill_score(&bits, x, y) - expected_fill_score).abs() < 1e-6);
+        }
+    }
+}
@@ -5,28 +5,108 @@ use std::{
 };
 
 use image::{GenericImageView, GrayImage};
-use imageproc::{
-    contours::{find_contours_with_threshold, BorderType, Contour},
-    contrast::otsu_level,
-};
+use imageproc::contours::{find_contours_with_threshold, BorderType, Contour};
 use logging_timer::time;
 use rayon::iter::ParallelIterator;
 use rayon::prelude::IntoParallelRefIterator;
 use serde::Serialize;
 
 use crate::{
-    ballot_card::{BallotSide, Geometry},
+    ballot_card::{BallotSide, BorderEdge, Geometry},
+    binarization::{binarize, BinarizationMethod},
+    calibration::{calibrate_timing_marks, Calibration},
+    color::MarkInkColor,
     debug,
     debug::ImageDebugWriter,
     election::{GridLayout, GridLocation, GridPosition},
     geometry::{
-        center_of_rect, find_best_line_through_items, intersection_of_lines, Point, Rect, Segment,
+        center_of_rect, find_best_line_through_items, intersection_of_lines, Bounded, Point, Rect,
+        Segment,
     },
-    image_utils::{diff, expand_image, ratio, BLACK, WHITE},
+    image_utils::{expand_image, WHITE},
     interpret::Error,
+    mark_classification::MarkStatus,
+    mark_features::{compute_mark_features, paper_luma_around, MarkAnomaly, MarkFeatures},
+    mark_shapes::{recognize_mark_type, MarkType, MARK_SHAPE_MARGIN_RATIO},
     metadata::{decode_metadata_from_timing_marks, BallotPageMetadata},
+    reference_ballot::{score_oval_marks_against_reference, ReferenceBallotPage},
+    target_templates::TargetTemplates,
+    template_matching::{BinaryTemplate, BitImage},
 };
 
+/// A timing mark found in, or inferred from, a scanned ballot card.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TimingMark {
+    /// The bounds of the timing mark in the scanned source image.
+    pub rect: Rect,
+
+    /// The center of the timing mark. For timing marks found in the image this
+    /// is the intensity-weighted centroid of the mark, which is more precise
+    /// than the center of `rect` and less sensitive to stray pixels along the
+    /// edges of the mark.
+    pub center: Point<f32>,
+}
+
+impl TimingMark {
+    /// Builds a timing mark whose center is the center of `rect`.
+    pub fn from_rect(rect: Rect) -> Self {
+        Self {
+            rect,
+            center: center_of_rect(&rect),
+        }
+    }
+
+    /// Builds a timing mark for `rect` with its center refined to sub-pixel
+    /// precision by weighting each pixel in and immediately around `rect` by
+    /// how much darker it is than the lightest pixel in that area.
+    pub fn from_rect_in_image(img: &GrayImage, rect: Rect) -> Self {
+        let left = (rect.left() - 1).max(0) as u32;
+        let top = (rect.top() - 1).max(0) as u32;
+        let right = ((rect.right() + 1).max(0) as u32).min(img.width().saturating_sub(1));
+        let bottom = ((rect.bottom() + 1).max(0) as u32).min(img.height().saturating_sub(1));
+
+        if left > right || top > bottom {
+            return Self::from_rect(rect);
+        }
+
+        let window = || {
+            (top..=bottom).flat_map(move |y| (left..=right).map(move |x| (x, y, img[(x, y)].0[0])))
+        };
+        let background = window().map(|(_, _, luma)| luma).max().unwrap_or(u8::MAX);
+
+        let (total_weight, weighted_x, weighted_y) = window().fold(
+            (0.0, 0.0, 0.0),
+            |(total_weight, weighted_x, weighted_y), (x, y, luma)| {
+                let weight = f32::from(background - luma);
+                (
+                    total_weight + weight,
+                    (x as f32).mul_add(weight, weighted_x),
+                    (y as f32).mul_add(weight, weighted_y),
+                )
+            },
+        );
+
+        if total_weight == 0.0 {
+            return Self::from_rect(rect);
+        }
+
+        Self {
+            rect,
+            center: Point::new(weighted_x / total_weight, weighted_y / total_weight),
+        }
+    }
+}
+
+impl Bounded for TimingMark {
+    fn bounds(&self) -> Rect {
+        self.rect
+    }
+
+    fn center(&self) -> Point<f32> {
+        self.center
+    }
+}
+
 /// Represents partial timing marks found in a ballot card.
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -36,14 +116,14 @@ pub struct Partial {
     pub top_right_corner: Point<f32>,
     pub bottom_left_corner: Point<f32>,
     pub bottom_right_corner: Point<f32>,
-    pub top_rects: Vec<Rect>,
-    pub bottom_rects: Vec<Rect>,
-    pub left_rects: Vec<Rect>,
-    pub right_rects: Vec<Rect>,
-    pub top_left_rect: Option<Rect>,
-    pub top_right_rect: Option<Rect>,
-    pub bottom_left_rect: Option<Rect>,
-    pub bottom_right_rect: Option<Rect>,
+    pub top_marks: Vec<TimingMark>,
+    pub bottom_marks: Vec<TimingMark>,
+    pub left_marks: Vec<TimingMark>,
+    pub right_marks: Vec<TimingMark>,
+    pub top_left_mark: Option<TimingMark>,
+    pub top_right_mark: Option<TimingMark>,
+    pub bottom_left_mark: Option<TimingMark>,
+    pub bottom_right_mark: Option<TimingMark>,
 }
 
 impl From<Complete> for Partial {
@@ -54,14 +134,14 @@ impl From<Complete> for Partial {
             top_right_corner: complete_timing_marks.top_right_corner,
             bottom_left_corner: complete_timing_marks.bottom_left_corner,
             bottom_right_corner: complete_timing_marks.bottom_right_corner,
-            top_rects: complete_timing_marks.top_rects,
-            bottom_rects: complete_timing_marks.bottom_rects,
-            left_rects: complete_timing_marks.left_rects,
-            right_rects: complete_timing_marks.right_rects,
-            top_left_rect: Some(complete_timing_marks.top_left_rect),
-            top_right_rect: Some(complete_timing_marks.top_right_rect),
-            bottom_left_rect: Some(complete_timing_marks.bottom_left_rect),
-            bottom_right_rect: Some(complete_timing_marks.bottom_right_rect),
+            top_marks: complete_timing_marks.top_marks,
+            bottom_marks: complete_timing_marks.bottom_marks,
+            left_marks: complete_timing_marks.left_marks,
+            right_marks: complete_timing_marks.right_marks,
+            top_left_mark: Some(complete_timing_marks.top_left_mark),
+            top_right_mark: Some(complete_timing_marks.top_right_mark),
+            bottom_left_mark: Some(complete_timing_marks.bottom_left_mark),
+            bottom_right_mark: Some(complete_timing_marks.bottom_right_mark),
         }
     }
 }
@@ -74,14 +154,14 @@ pub struct Complete {
     pub top_right_corner: Point<f32>,
     pub bottom_left_corner: Point<f32>,
     pub bottom_right_corner: Point<f32>,
-    pub top_rects: Vec<Rect>,
-    pub bottom_rects: Vec<Rect>,
-    pub left_rects: Vec<Rect>,
-    pub right_rects: Vec<Rect>,
-    pub top_left_rect: Rect,
-    pub top_right_rect: Rect,
-    pub bottom_left_rect: Rect,
-    pub bottom_right_rect: Rect,
+    pub top_marks: Vec<TimingMark>,
+    pub bottom_marks: Vec<TimingMark>,
+    pub left_marks: Vec<TimingMark>,
+    pub right_marks: Vec<TimingMark>,
+    pub top_left_mark: TimingMark,
+    pub top_right_mark: TimingMark,
+    pub bottom_left_mark: TimingMark,
+    pub bottom_right_mark: TimingMark,
 }
 
 /// Represents a grid of timing marks and provides access to the location of
@@ -89,9 +169,13 @@ pub struct Complete {
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TimingMarkGrid {
-    /// The geometry of the ballot card.
+    /// The geometry of the ballot card, calibrated to the timing marks found
+    /// in the image.
     pub geometry: Geometry,
 
+    /// Measurements of the timing marks used to calibrate `geometry`.
+    pub calibration: Calibration,
+
     /// Timing marks found by examining the image.
     pub partial_timing_marks: Partial,
 
@@ -101,20 +185,23 @@ pub struct TimingMarkGrid {
     /// Areas of the ballot card that contain shapes that may be timing marks.
     pub candidate_timing_marks: Vec<Rect>,
 
-    /// Metadata from the ballot card bottom timing marks.
-    pub metadata: BallotPageMetadata,
+    /// Metadata from the ballot card metadata edge timing marks, if the
+    /// timing mark layout has a metadata edge.
+    pub metadata: Option<BallotPageMetadata>,
 }
 
 impl TimingMarkGrid {
     pub fn new(
         geometry: Geometry,
+        calibration: Calibration,
         partial_timing_marks: Partial,
         complete_timing_marks: Complete,
         candidate_timing_marks: Vec<Rect>,
-        metadata: BallotPageMetadata,
+        metadata: Option<BallotPageMetadata>,
     ) -> Self {
         Self {
             geometry,
+            calibration,
             partial_timing_marks,
             complete_timing_marks,
             candidate_timing_marks,
@@ -122,6 +209,30 @@ impl TimingMarkGrid {
         }
     }
 
+    /// Determines whether the page looks upside down, i.e. the edge opposite
+    /// the metadata edge has fewer timing marks than the metadata edge. The
+    /// metadata is encoded by leaving out timing marks, so the metadata edge
+    /// should have the fewest. Pages without a metadata edge, or without
+    /// timing marks opposite it, can't be told apart from upside down ones.
+    pub fn is_upside_down(&self) -> bool {
+        let layout = self.geometry.timing_mark_layout;
+        let Some(metadata_edge) = layout.metadata_edge else {
+            return false;
+        };
+        let opposite_edge = metadata_edge.opposite();
+        if !layout.has_timing_marks(opposite_edge) {
+            return false;
+        }
+
+        let marks_on = |edge: BorderEdge| match edge {
+            BorderEdge::Top => self.partial_timing_marks.top_marks.len(),
+            BorderEdge::Bottom => self.partial_timing_marks.bottom_marks.len(),
+            BorderEdge::Left => self.partial_timing_marks.left_marks.len(),
+            BorderEdge::Right => self.partial_timing_marks.right_marks.len(),
+        };
+        marks_on(opposite_edge) < marks_on(metadata_edge)
+    }
+
     /// Returns the center of the grid position at the given coordinates. Timing
     /// marks are at the edges of the grid, and the inside of the grid is where
     /// the ovals are.
@@ -139,15 +250,15 @@ impl TimingMarkGrid {
             return None;
         }
 
-        let left = self.complete_timing_marks.left_rects.get(row as usize)?;
-        let right = self.complete_timing_marks.right_rects.get(row as usize)?;
-        let top = self.complete_timing_marks.top_rects.get(column as usize)?;
+        let left = self.complete_timing_marks.left_marks.get(row as usize)?;
+        let right = self.complete_timing_marks.right_marks.get(row as usize)?;
+        let top = self.complete_timing_marks.top_marks.get(column as usize)?;
         let bottom = self
             .complete_timing_marks
-            .bottom_rects
+            .bottom_marks
             .get(column as usize)?;
-        let horizontal_segment = Segment::new(center_of_rect(left), center_of_rect(right));
-        let vertical_segment = Segment::new(center_of_rect(top), center_of_rect(bottom));
+        let horizontal_segment = Segment::new(left.center, right.center);
+        let vertical_segment = Segment::new(top.center, bottom.center);
 
         intersection_of_lines(&horizontal_segment, &vertical_segment, false)
     }
@@ -160,22 +271,26 @@ pub fn find_timing_mark_grid(
     image_path: &Path,
     geometry: &Geometry,
     img: &GrayImage,
+    binarization: BinarizationMethod,
     debug: &ImageDebugWriter,
 ) -> Result<TimingMarkGrid, Error> {
-    let candidate_timing_marks = find_timing_mark_shapes(geometry, img, debug);
+    let (calibration, candidate_timing_marks) =
+        find_timing_mark_shapes(geometry, img, binarization, debug);
+    let geometry = &calibration.apply(geometry);
+    let candidates = candidate_timing_marks
+        .iter()
+        .map(|rect| TimingMark::from_rect_in_image(img, *rect))
+        .collect::<Vec<_>>();
 
-    let partial_timing_marks = match find_partial_timing_marks_from_candidate_rects(
-        geometry,
-        &candidate_timing_marks,
-        debug,
-    ) {
-        Some(partial_timing_marks) => partial_timing_marks,
-        None => {
-            return Err(Error::MissingTimingMarks {
-                rects: candidate_timing_marks,
-            })
-        }
-    };
+    let partial_timing_marks =
+        match find_partial_timing_marks_from_candidate_rects(geometry, &candidates, debug) {
+            Some(partial_timing_marks) => partial_timing_marks,
+            None => {
+                return Err(Error::MissingTimingMarks {
+                    rects: candidate_timing_marks,
+                })
+            }
+        };
 
     let complete_timing_marks = match find_complete_timing_marks_from_partial_timing_marks(
         geometry,
@@ -190,19 +305,27 @@ pub fn find_timing_mark_grid(
         }
     };
 
-    let metadata =
-        match decode_metadata_from_timing_marks(&partial_timing_marks, &complete_timing_marks) {
-            Ok(metadata) => metadata,
+    let metadata = match geometry.timing_mark_layout.metadata_edge {
+        Some(metadata_edge) => match decode_metadata_from_timing_marks(
+            &partial_timing_marks,
+            &complete_timing_marks,
+            metadata_edge,
+        ) {
+            Ok(metadata) => Some(metadata),
             Err(error) => {
                 return Err(Error::InvalidMetadata {
                     path: image_path.to_str().unwrap_or_default().to_string(),
-                    error,
+                    half: None,
+                    error: Box::new(error),
                 })
             }
-        };
+        },
+        None => None,
+    };
 
     let timing_mark_grid = TimingMarkGrid::new(
         *geometry,
+        calibration,
         partial_timing_marks,
         complete_timing_marks,
         candidate_timing_marks,
@@ -258,43 +381,52 @@ fn get_contour_bounding_rect(contour: &Contour<u32>) -> Rect {
 const BORDER_SIZE: u8 = 1;
 
 /// Looks for possible timing mark shapes in the image without trying to
-/// determine if they are actually timing marks.
+/// determine if they are actually timing marks. The expected geometry is
+/// calibrated against the rectangular shapes found so that timing marks
+/// printed at a slightly different size are not rejected.
 #[time]
 pub fn find_timing_mark_shapes(
     geometry: &Geometry,
     img: &GrayImage,
+    binarization: BinarizationMethod,
     debug: &ImageDebugWriter,
-) -> Vec<Rect> {
-    let threshold = otsu_level(img);
+) -> (Calibration, Vec<Rect>) {
+    let binarized = binarize(img, binarization);
 
     // `find_contours_with_threshold` does not consider timing marks on the edge
     // of the image to be contours, so we expand the image and add whitespace
     // around the edges to ensure no timing marks are on the edge of the image
-    let img = if let Ok(img) = expand_image(img, BORDER_SIZE.into(), WHITE) {
+    let img = if let Ok(img) = expand_image(&binarized, BORDER_SIZE.into(), WHITE) {
         img
     } else {
-        return vec![];
+        return (Calibration::nominal(geometry), vec![]);
     };
 
-    let contours = find_contours_with_threshold(&img, threshold);
-    let candidate_timing_marks = contours
+    let contours = find_contours_with_threshold(&img, u8::MAX / 2);
+    let rectangular_shapes = contours
         .iter()
         .enumerate()
         .filter_map(|(i, contour)| {
-            if contour.border_type == BorderType::Hole {
-                let contour_bounds = get_contour_bounding_rect(contour)
-                    .offset(-i32::from(BORDER_SIZE), -i32::from(BORDER_SIZE));
-                if rect_could_be_timing_mark(geometry, &contour_bounds)
-                    && is_contour_rectangular(contour)
-                    && contours.iter().all(|c| c.parent != Some(i))
-                {
-                    return Some(contour_bounds);
-                }
+            if contour.border_type == BorderType::Hole
+                && is_contour_rectangular(contour)
+                && contours.iter().all(|c| c.parent != Some(i))
+            {
+                return Some(
+                    get_contour_bounding_rect(contour)
+                        .offset(-i32::from(BORDER_SIZE), -i32::from(BORDER_SIZE)),
+                );
             }
             None
         })
         .collect::<Vec<_>>();
 
+    let calibration = calibrate_timing_marks(geometry, &rectangular_shapes);
+    let calibrated_geometry = calibration.apply(geometry);
+    let candidate_timing_marks = rectangular_shapes
+        .into_iter()
+        .filter(|rect| rect_could_be_timing_mark(&calibrated_geometry, rect))
+        .collect::<Vec<_>>();
+
     debug.write("candidate_timing_marks", |canvas| {
         debug::draw_candidate_timing_marks_debug_image_mut(
             canvas,
@@ -309,104 +441,108 @@ pub fn find_timing_mark_shapes(
         );
     });
 
-    candidate_timing_marks
+    (calibration, candidate_timing_marks)
 }
 
 #[time]
 pub fn find_partial_timing_marks_from_candidate_rects(
     geometry: &Geometry,
-    rects: &[Rect],
+    candidates: &[TimingMark],
     debug: &ImageDebugWriter,
 ) -> Option<Partial> {
     let half_height = (geometry.canvas_size.height / 2) as i32;
-    let top_half_rects = rects
+    let half_width = (geometry.canvas_size.width / 2) as i32;
+    let top_half_marks = candidates
         .iter()
-        .filter(|r| r.top() < half_height)
+        .filter(|m| m.rect.top() < half_height)
         .copied()
-        .collect::<Vec<Rect>>();
-    let bottom_half_rects = rects
+        .collect::<Vec<TimingMark>>();
+    let bottom_half_marks = candidates
         .iter()
-        .filter(|r| r.top() >= half_height)
+        .filter(|m| m.rect.top() >= half_height)
         .copied()
-        .collect::<Vec<Rect>>();
-    let left_half_rects = rects
+        .collect::<Vec<TimingMark>>();
+    let left_half_marks = candidates
         .iter()
-        .filter(|r| r.left() < half_height)
+        .filter(|m| m.rect.left() < half_width)
         .copied()
-        .collect::<Vec<Rect>>();
-    let right_half_rects = rects
+        .collect::<Vec<TimingMark>>();
+    let right_half_marks = candidates
         .iter()
-        .filter(|r| r.left() >= half_height)
+        .filter(|m| m.rect.left() >= half_width)
         .copied()
-        .collect::<Vec<Rect>>();
-    let mut top_line = find_best_line_through_items(&top_half_rects, 0.0, 5.0_f32.to_radians());
-    let mut bottom_line =
-        find_best_line_through_items(&bottom_half_rects, 0.0, 5.0_f32.to_radians());
-    let mut left_line =
-        find_best_line_through_items(&left_half_rects, PI / 2.0, 5.0_f32.to_radians());
-    let mut right_line =
-        find_best_line_through_items(&right_half_rects, PI / 2.0, 5.0_f32.to_radians());
-
-    top_line.sort_by_key(Rect::left);
-    bottom_line.sort_by_key(Rect::left);
-    left_line.sort_by_key(Rect::top);
-    right_line.sort_by_key(Rect::top);
-
-    let top_start_rect_center = center_of_rect(top_line.first()?);
-    let top_last_rect_center = center_of_rect(top_line.last()?);
-
-    let bottom_start_rect_center = center_of_rect(bottom_line.first()?);
-    let bottom_last_rect_center = center_of_rect(bottom_line.last()?);
-
-    let left_start_rect_center = center_of_rect(left_line.first()?);
-    let left_last_rect_center = center_of_rect(left_line.last()?);
-
-    let right_start_rect_center = center_of_rect(right_line.first()?);
-    let right_last_rect_center = center_of_rect(right_line.last()?);
-
-    let top_left_corner = if top_line.first() == left_line.first() {
-        top_line.first()
-    } else {
-        None
-    };
-    let top_right_corner = if top_line.last() == right_line.first() {
-        top_line.last()
-    } else {
-        None
-    };
-    let bottom_left_corner = if bottom_line.first() == left_line.last() {
-        bottom_line.first()
-    } else {
-        None
-    };
-    let bottom_right_corner = if bottom_line.last() == right_line.last() {
-        bottom_line.last()
-    } else {
-        None
+        .collect::<Vec<TimingMark>>();
+    let layout = &geometry.timing_mark_layout;
+
+    if !layout.can_form_grid() {
+        return None;
+    }
+
+    let find_line = |has_timing_marks: bool, marks: &[TimingMark], angle: f32| {
+        if has_timing_marks {
+            find_best_line_through_items(marks, angle, 5.0_f32.to_radians())
+        } else {
+            vec![]
+        }
     };
+    let mut top_line = find_line(layout.top, &top_half_marks, 0.0);
+    let mut bottom_line = find_line(layout.bottom, &bottom_half_marks, 0.0);
+    let mut left_line = find_line(layout.left, &left_half_marks, PI / 2.0);
+    let mut right_line = find_line(layout.right, &right_half_marks, PI / 2.0);
+
+    top_line.sort_by_key(|m| m.rect.left());
+    bottom_line.sort_by_key(|m| m.rect.left());
+    left_line.sort_by_key(|m| m.rect.top());
+    right_line.sort_by_key(|m| m.rect.top());
+
+    let top_segment = segment_through_timing_marks(layout.top, &top_line)?;
+    let bottom_segment = segment_through_timing_marks(layout.bottom, &bottom_line)?;
+    let left_segment = segment_through_timing_marks(layout.left, &left_line)?;
+    let right_segment = segment_through_timing_marks(layout.right, &right_line)?;
+
+    let top_left_corner =
+        find_corner_mark(top_line.first(), left_line.first(), layout.top, layout.left);
+    let top_right_corner = find_corner_mark(
+        top_line.last(),
+        right_line.first(),
+        layout.top,
+        layout.right,
+    );
+    let bottom_left_corner = find_corner_mark(
+        bottom_line.first(),
+        left_line.last(),
+        layout.bottom,
+        layout.left,
+    );
+    let bottom_right_corner = find_corner_mark(
+        bottom_line.last(),
+        right_line.last(),
+        layout.bottom,
+        layout.right,
+    );
 
-    let top_left_intersection = intersection_of_lines(
-        &Segment::new(top_start_rect_center, top_last_rect_center),
-        &Segment::new(left_start_rect_center, left_last_rect_center),
-        false,
+    let top_left_intersection = find_corner(
+        top_segment.as_ref(),
+        left_segment.as_ref(),
+        top_line.first().or_else(|| left_line.first()),
     )?;
 
-    let top_right_intersection = intersection_of_lines(
-        &Segment::new(top_start_rect_center, top_last_rect_center),
-        &Segment::new(right_start_rect_center, right_last_rect_center),
-        false,
+    let top_right_intersection = find_corner(
+        top_segment.as_ref(),
+        right_segment.as_ref(),
+        top_line.last().or_else(|| right_line.first()),
     )?;
 
-    let bottom_left_intersection = intersection_of_lines(
-        &Segment::new(bottom_start_rect_center, bottom_last_rect_center),
-        &Segment::new(left_start_rect_center, left_last_rect_center),
-        false,
+    let bottom_left_intersection = find_corner(
+        bottom_segment.as_ref(),
+        left_segment.as_ref(),
+        bottom_line.first().or_else(|| left_line.last()),
     )?;
 
-    let bottom_right_intersection = intersection_of_lines(
-        &Segment::new(bottom_start_rect_center, bottom_last_rect_center),
-        &Segment::new(right_start_rect_center, right_last_rect_center),
-        false,
+    let bottom_right_intersection = find_corner(
+        bottom_segment.as_ref(),
+        right_segment.as_ref(),
+        bottom_line.last().or_else(|| right_line.last()),
     )?;
 
     let partial_timing_marks = Partial {
@@ -415,14 +551,14 @@ pub fn find_partial_timing_marks_from_candidate_rects(
         top_right_corner: top_right_intersection,
         bottom_left_corner: bottom_left_intersection,
         bottom_right_corner: bottom_right_intersection,
-        top_left_rect: top_left_corner.copied(),
-        top_right_rect: top_right_corner.copied(),
-        bottom_left_rect: bottom_left_corner.copied(),
-        bottom_right_rect: bottom_right_corner.copied(),
-        top_rects: top_line,
-        bottom_rects: bottom_line,
-        left_rects: left_line,
-        right_rects: right_line,
+        top_left_mark: top_left_corner,
+        top_right_mark: top_right_corner,
+        bottom_left_mark: bottom_left_corner,
+        bottom_right_mark: bottom_right_corner,
+        top_marks: top_line,
+        bottom_marks: bottom_line,
+        left_marks: left_line,
+        right_marks: right_line,
     };
 
     debug.write("partial_timing_marks", |canvas| {
@@ -432,41 +568,93 @@ pub fn find_partial_timing_marks_from_candidate_rects(
     Some(partial_timing_marks)
 }
 
+/// Builds a segment from the first to the last timing mark on an edge. The
+/// outer `Option` is `None` if the edge should have timing marks but none were
+/// found, and the inner `Option` is `None` if the edge has no timing marks.
+#[allow(clippy::option_option)]
+fn segment_through_timing_marks(
+    has_timing_marks: bool,
+    line: &[TimingMark],
+) -> Option<Option<Segment>> {
+    if !has_timing_marks {
+        return Some(None);
+    }
+
+    Some(Some(Segment::new(
+        line.first()?.center,
+        line.last()?.center,
+    )))
+}
+
+/// Finds the timing mark at a corner of the grid. If both edges meeting at the
+/// corner have timing marks then the ends of both lines must be the same mark.
+/// If only one does then the end of that line is the corner.
+fn find_corner_mark(
+    horizontal_end: Option<&TimingMark>,
+    vertical_end: Option<&TimingMark>,
+    has_horizontal_timing_marks: bool,
+    has_vertical_timing_marks: bool,
+) -> Option<TimingMark> {
+    match (has_horizontal_timing_marks, has_vertical_timing_marks) {
+        (true, true) if horizontal_end == vertical_end => horizontal_end.copied(),
+        (true, false) => horizontal_end.copied(),
+        (false, true) => vertical_end.copied(),
+        _ => None,
+    }
+}
+
+/// Finds the point at a corner of the grid: the intersection of the lines
+/// along the two edges meeting at the corner if both have timing marks, or
+/// otherwise the center of the timing mark at the end of whichever does.
+fn find_corner(
+    horizontal_segment: Option<&Segment>,
+    vertical_segment: Option<&Segment>,
+    end_timing_mark: Option<&TimingMark>,
+) -> Option<Point<f32>> {
+    match (horizontal_segment, vertical_segment) {
+        (Some(horizontal_segment), Some(vertical_segment)) => {
+            intersection_of_lines(horizontal_segment, vertical_segment, false)
+        }
+        _ => end_timing_mark.map(|timing_mark| timing_mark.center),
+    }
+}
+
 #[time]
 pub fn find_complete_timing_marks_from_partial_timing_marks(
     geometry: &Geometry,
     partial_timing_marks: &Partial,
     debug: &ImageDebugWriter,
 ) -> Option<Complete> {
-    let top_line = &partial_timing_marks.top_rects;
-    let bottom_line = &partial_timing_marks.bottom_rects;
-    let left_line = &partial_timing_marks.left_rects;
-    let right_line = &partial_timing_marks.right_rects;
-    let (top_left_rect, top_right_rect, bottom_left_rect, bottom_right_rect) = match (
-        &partial_timing_marks.top_left_rect,
-        &partial_timing_marks.top_right_rect,
-        &partial_timing_marks.bottom_left_rect,
-        &partial_timing_marks.bottom_right_rect,
+    let layout = &geometry.timing_mark_layout;
+    let top_line = &partial_timing_marks.top_marks;
+    let bottom_line = &partial_timing_marks.bottom_marks;
+    let left_line = &partial_timing_marks.left_marks;
+    let right_line = &partial_timing_marks.right_marks;
+    let (top_left_mark, top_right_mark, bottom_left_mark, bottom_right_mark) = match (
+        &partial_timing_marks.top_left_mark,
+        &partial_timing_marks.top_right_mark,
+        &partial_timing_marks.bottom_left_mark,
+        &partial_timing_marks.bottom_right_mark,
     ) {
         (
-            Some(top_left_rect),
-            Some(top_right_rect),
-            Some(bottom_left_rect),
-            Some(bottom_right_rect),
+            Some(top_left_mark),
+            Some(top_right_mark),
+            Some(bottom_left_mark),
+            Some(bottom_right_mark),
         ) => (
-            top_left_rect,
-            top_right_rect,
-            bottom_left_rect,
-            bottom_right_rect,
+            top_left_mark,
+            top_right_mark,
+            bottom_left_mark,
+            bottom_right_mark,
         ),
         _ => return None,
     };
 
     let mut all_distances = vec![];
-    all_distances.append(&mut distances_between_rects(top_line));
-    all_distances.append(&mut distances_between_rects(bottom_line));
-    all_distances.append(&mut distances_between_rects(left_line));
-    all_distances.append(&mut distances_between_rects(right_line));
+    all_distances.append(&mut distances_between_timing_marks(top_line));
+    all_distances.append(&mut distances_between_timing_marks(bottom_line));
+    all_distances.append(&mut distances_between_timing_marks(left_line));
+    all_distances.append(&mut distances_between_timing_marks(right_line));
     all_distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
 
     if all_distances.is_empty() {
@@ -475,49 +663,69 @@ pub fn find_complete_timing_marks_from_partial_timing_marks(
 
     let median_distance = all_distances[all_distances.len() / 2];
 
-    let top_line = infer_missing_timing_marks_on_segment(
-        top_line,
-        &Segment::new(
-            partial_timing_marks.top_left_corner,
-            partial_timing_marks.top_right_corner,
-        ),
-        median_distance,
-        geometry.grid_size.width,
-        geometry,
+    let top_segment = Segment::new(
+        partial_timing_marks.top_left_corner,
+        partial_timing_marks.top_right_corner,
     );
+    let top_line = if layout.top {
+        infer_missing_timing_marks_on_segment(
+            top_line,
+            &top_segment,
+            median_distance,
+            geometry.grid_size.width,
+            geometry,
+        )
+    } else {
+        interpolate_timing_marks_on_segment(&top_segment, geometry.grid_size.width, geometry)
+    };
 
-    let bottom_line = infer_missing_timing_marks_on_segment(
-        bottom_line,
-        &Segment::new(
-            partial_timing_marks.bottom_left_corner,
-            partial_timing_marks.bottom_right_corner,
-        ),
-        median_distance,
-        geometry.grid_size.width,
-        geometry,
+    let bottom_segment = Segment::new(
+        partial_timing_marks.bottom_left_corner,
+        partial_timing_marks.bottom_right_corner,
     );
+    let bottom_line = if layout.bottom {
+        infer_missing_timing_marks_on_segment(
+            bottom_line,
+            &bottom_segment,
+            median_distance,
+            geometry.grid_size.width,
+            geometry,
+        )
+    } else {
+        interpolate_timing_marks_on_segment(&bottom_segment, geometry.grid_size.width, geometry)
+    };
 
-    let left_line = infer_missing_timing_marks_on_segment(
-        left_line,
-        &Segment::new(
-            partial_timing_marks.top_left_corner,
-            partial_timing_marks.bottom_left_corner,
-        ),
-        median_distance,
-        geometry.grid_size.height,
-        geometry,
+    let left_segment = Segment::new(
+        partial_timing_marks.top_left_corner,
+        partial_timing_marks.bottom_left_corner,
     );
+    let left_line = if layout.left {
+        infer_missing_timing_marks_on_segment(
+            left_line,
+            &left_segment,
+            median_distance,
+            geometry.grid_size.height,
+            geometry,
+        )
+    } else {
+        interpolate_timing_marks_on_segment(&left_segment, geometry.grid_size.height, geometry)
+    };
 
-    let right_line = infer_missing_timing_marks_on_segment(
-        right_line,
-        &Segment::new(
-            partial_timing_marks.top_right_corner,
-            partial_timing_marks.bottom_right_corner,
-        ),
-        median_distance,
-        geometry.grid_size.height,
-        geometry,
+    let right_segment = Segment::new(
+        partial_timing_marks.top_right_corner,
+        partial_timing_marks.bottom_right_corner,
     );
+    let right_line = if layout.right {
+        infer_missing_timing_marks_on_segment(
+            right_line,
+            &right_segment,
+            median_distance,
+            geometry.grid_size.height,
+            geometry,
+        )
+    } else {
+        interpolate_timing_marks_on_segment(&right_segment, geometry.grid_size.height, geometry)
+    };
 
     if top_line.len() != bottom_line.len() || left_line.len() != right_line.len() {
         return None;
@@ -525,18 +733,18 @@ pub fn find_complete_timing_marks_from_partial_timing_marks(
 
     let complete_timing_marks = Complete {
         geometry: *geometry,
-        top_rects: top_line,
-        bottom_rects: bottom_line,
-        left_rects: left_line,
-        right_rects: right_line,
+        top_marks: top_line,
+        bottom_marks: bottom_line,
+        left_marks: left_line,
+        right_marks: right_line,
         top_left_corner: partial_timing_marks.top_left_corner,
         top_right_corner: partial_timing_marks.top_right_corner,
         bottom_left_corner: partial_timing_marks.bottom_left_corner,
         bottom_right_corner: partial_timing_marks.bottom_right_corner,
-        top_left_rect: *top_left_rect,
-        top_right_rect: *top_right_rect,
-        bottom_left_rect: *bottom_left_rect,
-        bottom_right_rect: *bottom_right_rect,
+        top_left_mark: *top_left_mark,
+        top_right_mark: *top_right_mark,
+        bottom_left_mark: *bottom_left_mark,
+        bottom_right_mark: *bottom_right_mark,
     };
 
     debug.write("complete_timing_marks", |canvas| {
@@ -555,12 +763,12 @@ pub fn find_complete_timing_marks_from_partial_timing_marks(
 /// distance between them is roughly `expected_distance`. There should be
 /// exactly `expected_count` timing marks along the segment.
 fn infer_missing_timing_marks_on_segment(
-    timing_marks: &[Rect],
+    timing_marks: &[TimingMark],
     segment: &Segment,
     expected_distance: f32,
     expected_count: u32,
     geometry: &Geometry,
-) -> Vec<Rect> {
+) -> Vec<TimingMark> {
     if timing_marks.is_empty() {
         return vec![];
     }
@@ -571,44 +779,71 @@ fn infer_missing_timing_marks_on_segment(
     let maximum_error = expected_distance / 2.0;
     while inferred_timing_marks.len() < expected_count as usize {
         // find the closest existing timing mark
-        let closest_rect = timing_marks
+        let closest_mark = timing_marks
             .iter()
             .min_by(|a, b| {
-                let a_distance =
-                    Segment::new(center_of_rect(a), current_timing_mark_center).length();
-                let b_distance =
-                    Segment::new(center_of_rect(b), current_timing_mark_center).length();
+                let a_distance = Segment::new(a.center, current_timing_mark_center).length();
+                let b_distance = Segment::new(b.center, current_timing_mark_center).length();
                 a_distance
                     .partial_cmp(&b_distance)
                     .unwrap_or(std::cmp::Ordering::Equal)
             })
-            .map_or_else(
-                || unreachable!("there will always be a closest timing mark"),
-                |rect| rect,
-            );
+            .unwrap_or_else(|| unreachable!("there will always be a closest timing mark"));
 
         // if the closest timing mark is close enough, use it
-        if Segment::new(center_of_rect(closest_rect), current_timing_mark_center).length()
-            <= maximum_error
-        {
-            inferred_timing_marks.push(*closest_rect);
-            current_timing_mark_center = center_of_rect(closest_rect) + next_point_vector;
+        if Segment::new(closest_mark.center, current_timing_mark_center).length() <= maximum_error {
+            inferred_timing_marks.push(*closest_mark);
+            current_timing_mark_center = closest_mark.center + next_point_vector;
         } else {
             // otherwise, we need to fill in a point
-            inferred_timing_marks.push(Rect::new(
-                (current_timing_mark_center.x - geometry.timing_mark_size.width / 2.0).round()
-                    as i32,
-                (current_timing_mark_center.y - geometry.timing_mark_size.height / 2.0).round()
-                    as i32,
-                geometry.timing_mark_size.width.round() as u32,
-                geometry.timing_mark_size.height.round() as u32,
-            ));
+            inferred_timing_marks.push(TimingMark {
+                rect: Rect::new(
+                    (current_timing_mark_center.x - geometry.timing_mark_size.width / 2.0).round()
+                        as i32,
+                    (current_timing_mark_center.y - geometry.timing_mark_size.height / 2.0).round()
+                        as i32,
+                    geometry.timing_mark_size.width.round() as u32,
+                    geometry.timing_mark_size.height.round() as u32,
+                ),
+                center: current_timing_mark_center,
+            });
             current_timing_mark_center += next_point_vector;
         }
     }
     inferred_timing_marks
 }
 
+/// Places `count` evenly spaced timing marks from the start to the end of a
+/// segment. This is used for edges of the ballot that don't have timing marks
+/// printed on them, where the grid lines are instead assumed to be evenly
+/// spaced between the corners.
+fn interpolate_timing_marks_on_segment(
+    segment: &Segment,
+    count: u32,
+    geometry: &Geometry,
+) -> Vec<TimingMark> {
+    let steps = count.saturating_sub(1).max(1) as f32;
+    let step_vector = segment.with_length(segment.length() / steps).vector();
+
+    (0..count)
+        .map(|i| {
+            let center = Point::new(
+                (i as f32).mul_add(step_vector.x, segment.start.x),
+                (i as f32).mul_add(step_vector.y, segment.start.y),
+            );
+            TimingMark {
+                rect: Rect::new(
+                    (center.x - geometry.timing_mark_size.width / 2.0).round() as i32,
+                    (center.y - geometry.timing_mark_size.height / 2.0).round() as i32,
+                    geometry.timing_mark_size.width.round() as u32,
+                    geometry.timing_mark_size.height.round() as u32,
+                ),
+                center,
+            }
+        })
+        .collect()
+}
+
 /// Determines whether a rect could be a timing mark based on its size.
 pub fn rect_could_be_timing_mark(geometry: &Geometry, rect: &Rect) -> bool {
     let min_timing_mark_width = (geometry.timing_mark_size.width * 1.0 / 4.0).floor() as u32;
@@ -621,11 +856,12 @@ pub fn rect_could_be_timing_mark(geometry: &Geometry, rect: &Rect) -> bool {
         && rect.height() <= max_timing_mark_height
 }
 
-/// Gets all the distances between adjacent rects in a list of rects.
-pub fn distances_between_rects(rects: &[Rect]) -> Vec<f32> {
-    let mut distances = rects
+/// Gets all the distances between the centers of adjacent timing marks in a
+/// list of timing marks.
+pub fn distances_between_timing_marks(timing_marks: &[TimingMark]) -> Vec<f32> {
+    let mut distances = timing_marks
         .windows(2)
-        .map(|w| Segment::new(center_of_rect(&w[1]), center_of_rect(&w[0])).length())
+        .map(|w| Segment::new(w[1].center, w[0].center).length())
         .collect::<Vec<f32>>();
     distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
     distances
@@ -674,33 +910,83 @@ pub struct ScoredOvalMark {
     /// perfectly filled.
     pub fill_score: OvalMarkScore,
 
+    /// The score for the fill of the oval relative to the same oval on a blank
+    /// reference ballot, i.e. only counting ink that isn't on the blank
+    /// ballot. Only present if the grid layout has a blank ballot.
+    pub reference_fill_score: Option<OvalMarkScore>,
+
+    /// Whether the oval is marked, based on its fill scores. This is set once
+    /// all the ovals on the page have been scored.
+    pub status: Option<MarkStatus>,
+
+    /// Measurements of the ink inside the oval at `matched_bounds`.
+    pub features: MarkFeatures,
+
+    /// Whether the ink inside the oval looks like an erasure or a hesitation
+    /// mark, which should be reviewed to confirm voter intent.
+    pub anomaly: Option<MarkAnomaly>,
+
+    /// The shape of the ink in and around the oval, e.g. whether the voter
+    /// filled it in or drew a check or X through it.
+    pub mark_type: MarkType,
+
+    /// The color of the ink in the oval, if requested and the scan is in
+    /// color.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ink_color: Option<MarkInkColor>,
+
     /// The expected bounds of the oval mark in the scanned source image.
     pub expected_bounds: Rect,
 
     /// The bounds of the oval mark in the scanned source image that was
     /// determined to be the best match.
     pub matched_bounds: Rect,
+}
 
-    /// The cropped source image at `matched_bounds`.
-    #[serde(skip_serializing)]
+/// Images of a scored oval mark for debugging, cropped at its
+/// `matched_bounds`. These are built on request by
+/// [`ScoredOvalMark::images`] rather than kept for every oval.
+#[cfg(test)]
+pub struct ScoredOvalMarkImages {
+    /// The cropped source image.
     pub source_image: GrayImage,
 
-    /// The cropped source image at `matched_bounds` with each pixel binarized
-    /// to either 0 (black) or 255 (white).
-    #[serde(skip_serializing)]
-    pub binarized_source_image: GrayImage,
-
-    /// A binarized diff image of `binarized_source_image` with the template.
-    /// The more white pixels, the better the match.
-    #[serde(skip_serializing)]
+    /// A binarized diff image of the cropped, binarized source image with the
+    /// template. The more white pixels, the better the match.
     pub match_diff_image: GrayImage,
 
-    /// A binarized diff image of `binarized_source_image` with the fill of the
-    /// template. The more black pixels, the better the fill.
-    #[serde(skip_serializing)]
+    /// A binarized diff image of the cropped, binarized source image with the
+    /// fill of the template. The more black pixels, the better the fill.
     pub fill_diff_image: GrayImage,
 }
 
+#[cfg(test)]
+impl ScoredOvalMark {
+    /// Crops the images of this oval mark from the image it was scored in and
+    /// that image binarized.
+    pub fn images(
+        &self,
+        img: &GrayImage,
+        binarized_img: &GrayImage,
+        oval_template: &BinaryTemplate,
+    ) -> ScoredOvalMarkImages {
+        let bounds = &self.matched_bounds;
+        let (x, y) = (bounds.left() as u32, bounds.top() as u32);
+        let (width, height) = (bounds.width(), bounds.height());
+        let source_image = img.view(x, y, width, height).to_image();
+        let binarized_source_image = binarized_img.view(x, y, width, height).to_image();
+        let match_diff_image =
+            crate::image_utils::diff(&binarized_source_image, &oval_template.image);
+        let fill_diff_image =
+            crate::image_utils::diff(&oval_template.image, &binarized_source_image);
+        ScoredOvalMarkImages {
+            source_image,
+            match_diff_image,
+            fill_diff_image,
+        }
+    }
+}
+
 impl std::fmt::Debug for ScoredOvalMark {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         write!(
@@ -713,121 +999,658 @@ impl std::fmt::Debug for ScoredOvalMark {
 
 pub const DEFAULT_MAXIMUM_SEARCH_DISTANCE: u32 = 7;
 
-pub type ScoredOvalMarks = Vec<(GridPosition, Option<ScoredOvalMark>)>;
+/// The outcome of looking for an oval mark at a grid position.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum OvalMark {
+    /// The oval was found in the scanned image and scored.
+    Scored(Box<ScoredOvalMark>),
+
+    /// The grid position's location could not be mapped to a point in the
+    /// scanned image, e.g. because it lies outside the timing mark grid.
+    #[serde(rename_all = "camelCase")]
+    NotInGrid { location: GridLocation },
+
+    /// The oval would lie at least partly outside the scanned image at every
+    /// offset searched, so it could not be scored. This usually means the
+    /// timing mark grid was inferred incorrectly.
+    #[serde(rename_all = "camelCase")]
+    OutOfBounds {
+        location: GridLocation,
+        expected_bounds: Rect,
+    },
+}
+
+impl OvalMark {
+    /// Returns the scored oval mark, if the oval could be scored.
+    pub fn scored(&self) -> Option<&ScoredOvalMark> {
+        match self {
+            Self::Scored(scored_oval_mark) => Some(scored_oval_mark.as_ref()),
+            Self::NotInGrid { .. } | Self::OutOfBounds { .. } => None,
+        }
+    }
+}
 
+pub type ScoredOvalMarks = Vec<(GridPosition, OvalMark)>;
+
+/// Scores the oval marks on one side of a ballot card. If there is a blank
+/// `reference` ballot page, each oval is also scored against it.
 #[time]
+#[allow(clippy::too_many_arguments)]
 pub fn score_oval_marks_from_grid_layout(
     img: &GrayImage,
-    oval_template: &GrayImage,
+    target_templates: &TargetTemplates,
     timing_mark_grid: &TimingMarkGrid,
     grid_layout: &GridLayout,
     side: BallotSide,
+    binarization: BinarizationMethod,
+    reference: Option<&ReferenceBallotPage>,
     debug: &ImageDebugWriter,
 ) -> ScoredOvalMarks {
-    let threshold = otsu_level(img);
-
-    let scored_ovals = &grid_layout.grid_positions.par_iter().flat_map(|grid_position| {
-        let location = grid_position.location();
-
-        if location.side != side {
-            return vec![];
-        }
+    let calibration = &timing_mark_grid.calibration;
+    let scaled_target_templates = &target_templates.binary_templates(calibration);
+    let binarized_img = &binarize(img, binarization);
+    let binarized = &BitImage::from_gray_image(binarized_img, u8::MAX / 2);
+
+    let mut scored_ovals = grid_layout
+        .grid_positions
+        .par_iter()
+        .flat_map(|grid_position| {
+            let location = grid_position.location();
+
+            if location.side != side {
+                return vec![];
+            }
 
-        match timing_mark_grid.point_for_location(location.column, location.row) {
-            Some(expected_oval_center) => {
-                vec![(
-                    grid_position.clone(),
-                    score_oval_mark(
-                        img,
-                        oval_template,
-                        expected_oval_center,
-                        &location,
-                        DEFAULT_MAXIMUM_SEARCH_DISTANCE,
-                        threshold,
-                    ),
-                )]
+            match timing_mark_grid.point_for_location(location.column, location.row) {
+                Some(expected_oval_center) => {
+                    vec![(
+                        grid_position.clone(),
+                        score_oval_mark(
+                            img,
+                            binarized,
+                            scaled_target_templates.template_for(grid_position),
+                            expected_oval_center,
+                            &location,
+                            calibration.maximum_search_distance,
+                        ),
+                    )]
+                }
+                None => vec![(grid_position.clone(), OvalMark::NotInGrid { location })],
             }
-            None => vec![(grid_position.clone(), None)],
-        }
-    }).collect::<ScoredOvalMarks>();
+        })
+        .collect::<ScoredOvalMarks>();
+
+    if let Some(reference) = reference {
+        score_oval_marks_against_reference(
+            &mut scored_ovals,
+            binarized_img,
+            reference,
+            target_templates,
+        );
+    }
 
     debug.write("scored_oval_marks", |canvas| {
-        debug::draw_scored_oval_marks_debug_image_mut(canvas, scored_ovals);
+        debug::draw_scored_oval_marks_debug_image_mut(canvas, &scored_ovals);
     });
 
-    scored_ovals.to_vec()
+    scored_ovals
 }
 
-/// Scores an oval mark within a scanned ballot image.
+/// Scores an oval mark within a scanned ballot image. `binarized` must be
+/// `img` binarized. Offsets that would put the oval partly outside the image
+/// are skipped, and if there are none left the oval is reported as out of
+/// bounds.
 pub fn score_oval_mark(
     img: &GrayImage,
-    oval_template: &GrayImage,
+    binarized: &BitImage,
+    oval_template: &BinaryTemplate,
     expected_oval_center: Point<f32>,
     location: &GridLocation,
     maximum_search_distance: u32,
-    threshold: u8,
-) -> Option<ScoredOvalMark> {
-    let center_x = expected_oval_center.x.round() as u32;
-    let center_y = expected_oval_center.y.round() as u32;
-    let left = center_x - oval_template.width() / 2;
-    let top = center_y - oval_template.height() / 2;
+) -> OvalMark {
     let width = oval_template.width();
     let height = oval_template.height();
-    let expected_bounds = Rect::new(left as i32, top as i32, width, height);
+    // casting saturates, so a huge center ends up at the edge of the `i32`
+    // range and is caught by the bounds checks below
+    let left = (expected_oval_center.x.round() as i32).saturating_sub((width / 2) as i32);
+    let top = (expected_oval_center.y.round() as i32).saturating_sub((height / 2) as i32);
+    let expected_bounds = Rect::new(left, top, width, height);
+    if !expected_oval_center.x.is_finite() || !expected_oval_center.y.is_finite() {
+        return OvalMark::OutOfBounds {
+            location: *location,
+            expected_bounds,
+        };
+    }
+
+    let max_x = img.width() as i32 - width as i32;
+    let max_y = img.height() as i32 - height as i32;
     let mut best_match_score = OvalMarkScore(f32::NEG_INFINITY);
     let mut best_match_bounds: Option<Rect> = None;
-    let mut best_match_diff: Option<GrayImage> = None;
 
     for offset_x in -(maximum_search_distance as i32)..(maximum_search_distance as i32) {
-        let x = left as i32 + offset_x;
-        if x < 0 {
+        let x = left.saturating_add(offset_x);
+        if x < 0 || x > max_x {
             continue;
         }
 
         for offset_y in -(maximum_search_distance as i32)..(maximum_search_distance as i32) {
-            let y = top as i32 + offset_y;
-            if y < 0 {
+            let y = top.saturating_add(offset_y);
+            if y < 0 || y > max_y {
                 continue;
             }
 
-            let cropped = img.view(x as u32, y as u32, width, height).to_image();
-            let cropped_and_thresholded = imageproc::contrast::threshold(&cropped, threshold);
-
-            let match_diff = diff(&cropped_and_thresholded, oval_template);
-            let match_score = OvalMarkScore(ratio(&match_diff, WHITE));
+            let match_score =
+                OvalMarkScore(oval_template.match_score(binarized, x as u32, y as u32));
 
             if match_score > best_match_score {
                 best_match_score = match_score;
-                best_match_bounds = Some(Rect::new(x, y, width, oval_template.height()));
-                best_match_diff = Some(match_diff);
+                best_match_bounds = Some(Rect::new(x, y, width, height));
             }
         }
     }
 
-    let best_match_bounds = best_match_bounds?;
-    let best_match_diff = best_match_diff?;
-
-    let source_image = img
-        .view(
-            best_match_bounds.left() as u32,
-            best_match_bounds.top() as u32,
-            best_match_bounds.width(),
-            best_match_bounds.height(),
-        )
-        .to_image();
-    let binarized_source_image = imageproc::contrast::threshold(&source_image, threshold);
-    let diff_image = diff(oval_template, &binarized_source_image);
-    let fill_score = OvalMarkScore(ratio(&diff_image, BLACK));
+    let best_match_bounds = match best_match_bounds {
+        Some(best_match_bounds) => best_match_bounds,
+        None => {
+            return OvalMark::OutOfBounds {
+                location: *location,
+                expected_bounds,
+            }
+        }
+    };
+    let fill_score = OvalMarkScore(oval_template.fill_score(
+        binarized,
+        best_match_bounds.left() as u32,
+        best_match_bounds.top() as u32,
+    ));
+
+    let (x, y) = (
+        best_match_bounds.left() as u32,
+        best_match_bounds.top() as u32,
+    );
+    // checks and Xs often extend past the oval, so look at a margin around it
+    let margin = (height as f32 * MARK_SHAPE_MARGIN_RATIO).round() as u32;
+    let (margin_left, margin_top) = (x.saturating_sub(margin), y.saturating_sub(margin));
+    let margin_image = img.view(
+        margin_left,
+        margin_top,
+        (x + width + margin).min(img.width()) - margin_left,
+        (y + height + margin).min(img.height()) - margin_top,
+    );
+    let template_origin = (x - margin_left, y - margin_top);
+    let paper = paper_luma_around(
+        &*margin_image,
+        &Rect::new(
+            template_origin.0 as i32,
+            template_origin.1 as i32,
+            width,
+            height,
+        ),
+    );
+    let features = compute_mark_features(
+        &*img.view(x, y, width, height),
+        &oval_template.interior,
+        paper,
+    );
+    let mark_type = recognize_mark_type(
+        &*margin_image,
+        &oval_template.image,
+        template_origin,
+        paper,
+        &features,
+    );
 
-    Some(ScoredOvalMark {
+    OvalMark::Scored(Box::new(ScoredOvalMark {
         location: *location,
         match_score: best_match_score,
         fill_score,
+        reference_fill_score: None,
+        status: None,
+        features,
+        anomaly: features.anomaly(),
+        mark_type,
+        ink_color: None,
         expected_bounds,
         matched_bounds: best_match_bounds,
-        source_image,
-        binarized_source_image,
-        match_diff_image: best_match_diff,
-        fill_diff_image: diff_image,
-    })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+    use imageproc::{drawing::draw_filled_rect_mut, rect::Rect as ImageprocRect};
+
+    use imageproc::contrast::otsu_level;
+
+    use super::*;
+    use crate::{
+        ballot_card::{
+            get_scanned_ballot_card_geometry_8pt5x11, get_scanned_ballot_card_geometry_8pt5x14,
+            load_oval_template,
+        },
+        election::Election,
+        image_utils::{diff, ratio, BLACK},
+        metadata::BallotPageMetadata,
+        synthetic_ballot::{
+            geometry_for_grid_layout, grid_location_center, render_ballot_card, BackMetadataValues,
+            FrontMetadataValues,
+        },
+        target_templates::TargetTemplates,
+    };
+
+    #[test]
+    fn test_timing_mark_center_from_image() {
+        let mut img = GrayImage::from_pixel(100, 50, WHITE);
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(20, 10).of_size(38, 13), BLACK);
+
+        let timing_mark = TimingMark::from_rect_in_image(&img, Rect::new(20, 10, 38, 13));
+        assert!((timing_mark.center.x - 38.5).abs() < 0.01);
+        assert!((timing_mark.center.y - 16.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_timing_mark_center_ignores_stray_pixel() {
+        let mut img = GrayImage::from_pixel(100, 50, WHITE);
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(20, 10).of_size(38, 13), BLACK);
+        img.put_pixel(58, 16, Luma([0]));
+
+        // the stray pixel grows the bounding rect, shifting its center by half
+        // a pixel, but barely affects the centroid
+        let rect = Rect::new(20, 10, 39, 13);
+        let timing_mark = TimingMark::from_rect_in_image(&img, rect);
+        assert!((center_of_rect(&rect).x - 39.0).abs() < 0.01);
+        assert!((timing_mark.center.x - 38.5).abs() < 0.05);
+        assert!((timing_mark.center.y - 16.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_timing_mark_center_partially_covered_edge() {
+        let mut img = GrayImage::from_pixel(100, 50, WHITE);
+        draw_filled_rect_mut(&mut img, ImageprocRect::at(20, 10).of_size(38, 13), BLACK);
+        // a column of half-covered pixels along the right edge
+        draw_filled_rect_mut(
+            &mut img,
+            ImageprocRect::at(58, 10).of_size(1, 13),
+            Luma([128]),
+        );
+
+        let timing_mark = TimingMark::from_rect_in_image(&img, Rect::new(20, 10, 39, 13));
+        assert!(timing_mark.center.x > 38.5);
+        assert!(timing_mark.center.x < 39.0);
+    }
+
+    #[test]
+    fn test_timing_mark_center_blank_image() {
+        let img = GrayImage::from_pixel(100, 50, WHITE);
+        let rect = Rect::new(20, 10, 38, 13);
+        assert_eq!(
+            TimingMark::from_rect_in_image(&img, rect),
+            TimingMark::from_rect(rect)
+        );
+    }
+
+    /// Scores an oval mark by cropping, binarizing, and diffing the image at
+    /// every offset. This is the straightforward approach `score_oval_mark`
+    /// must agree with.
+    fn score_oval_mark_by_image_diff(
+        img: &GrayImage,
+        oval_template: &GrayImage,
+        expected_oval_center: Point<f32>,
+        maximum_search_distance: u32,
+        threshold: u8,
+    ) -> Option<(f32, f32, Rect)> {
+        let (width, height) = oval_template.dimensions();
+        let left = expected_oval_center.x.round() as i32 - (width / 2) as i32;
+        let top = expected_oval_center.y.round() as i32 - (height / 2) as i32;
+        let mut best: Option<(f32, Rect)> = None;
+
+        for offset_x in -(maximum_search_distance as i32)..(maximum_search_distance as i32) {
+            for offset_y in -(maximum_search_distance as i32)..(maximum_search_distance as i32) {
+                let (x, y) = (left + offset_x, top + offset_y);
+                if x < 0
+                    || y < 0
+                    || x as u32 + width > img.width()
+                    || y as u32 + height > img.height()
+                {
+                    continue;
+                }
+
+                let cropped = img.view(x as u32, y as u32, width, height).to_image();
+                let binarized = imageproc::contrast::threshold(&cropped, threshold);
+                let match_score = ratio(&diff(&binarized, oval_template), WHITE);
+                if best.is_none_or(|(best_score, _)| match_score > best_score) {
+                    best = Some((match_score, Rect::new(x, y, width, height)));
+                }
+            }
+        }
+
+        let (match_score, bounds) = best?;
+        let cropped = img
+            .view(
+                bounds.left() as u32,
+                bounds.top() as u32,
+                bounds.width(),
+                bounds.height(),
+            )
+            .to_image();
+        let binarized = imageproc::contrast::threshold(&cropped, threshold);
+        let fill_score = ratio(&diff(oval_template, &binarized), BLACK);
+        Some((match_score, fill_score, bounds))
+    }
+
+    /// Builds a page with a slightly offset oval at every grid location
+    /// inside the timing marks, filling in every seventh one. Returns the page
+    /// and the expected oval centers.
+    fn synthetic_ballot_page(
+        geometry: &Geometry,
+        oval_template: &GrayImage,
+    ) -> (GrayImage, Vec<Point<f32>>) {
+        let mut img = GrayImage::from_pixel(
+            geometry.canvas_size.width,
+            geometry.canvas_size.height,
+            WHITE,
+        );
+        let column_pitch = geometry.canvas_size.width as f32 / geometry.grid_size.width as f32;
+        let row_pitch = geometry.canvas_size.height as f32 / geometry.grid_size.height as f32;
+        let mut centers = vec![];
+
+        for column in 1..geometry.grid_size.width - 1 {
+            for row in 1..geometry.grid_size.height - 1 {
+                let center = Point::new(
+                    (column as f32 + 0.5) * column_pitch,
+                    (row as f32 + 0.5) * row_pitch,
+                );
+                let left = center.x as i64 - oval_template.width() as i64 / 2 + (column % 5) as i64;
+                let top = center.y as i64 - oval_template.height() as i64 / 2 + (row % 3) as i64;
+                image::imageops::overlay(&mut img, oval_template, left, top);
+
+                if (column * geometry.grid_size.height + row).is_multiple_of(7) {
+                    draw_filled_rect_mut(
+                        &mut img,
+                        ImageprocRect::at(left as i32 + 8, top as i32 + 6)
+                            .of_size(oval_template.width() - 16, oval_template.height() - 12),
+                        Luma([30]),
+                    );
+                }
+
+                centers.push(center);
+            }
+        }
+
+        (img, centers)
+    }
+
+    #[test]
+    fn test_score_oval_mark_matches_image_diff() {
+        let geometry = get_scanned_ballot_card_geometry_8pt5x11();
+        let oval_template = load_oval_template().unwrap();
+        let (img, centers) = synthetic_ballot_page(&geometry, &oval_template);
+        let threshold = otsu_level(&img);
+        let binarized_img = imageproc::contrast::threshold(&img, threshold);
+        let binarized = BitImage::from_gray_image(&binarized_img, 127);
+        let binary_template = BinaryTemplate::new(&oval_template);
+        let location = GridLocation::new(BallotSide::Front, 0, 0);
+
+        for center in centers.iter().step_by(37) {
+            let scored_oval_mark = score_oval_mark(
+                &img,
+                &binarized,
+                &binary_template,
+                *center,
+                &location,
+                DEFAULT_MAXIMUM_SEARCH_DISTANCE,
+            );
+            let scored_oval_mark = scored_oval_mark.scored().unwrap();
+            let (match_score, fill_score, bounds) = score_oval_mark_by_image_diff(
+                &img,
+                &oval_template,
+                *center,
+                DEFAULT_MAXIMUM_SEARCH_DISTANCE,
+                threshold,
+            )
+            .unwrap();
+
+            assert_eq!(scored_oval_mark.matched_bounds, bounds);
+            assert!((scored_oval_mark.match_score.0 - match_score).abs() < 1e-6);
+            assert!((scored_oval_mark.fill_score.0 - fill_score).abs() < 1e-6);
+
+            let images = scored_oval_mark.images(&img, &binarized_img, &binary_template);
+            assert_eq!(images.source_image.dimensions(), oval_template.dimensions());
+            assert!((ratio(&images.match_diff_image, WHITE) - match_score).abs() < 1e-6);
+            assert!((ratio(&images.fill_diff_image, BLACK) - fill_score).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_score_oval_mark_near_image_edge() {
+        let oval_template = load_oval_template().unwrap();
+        let img = GrayImage::from_pixel(60, 40, WHITE);
+        let binarized = BitImage::from_gray_image(&img, 127);
+        let binary_template = BinaryTemplate::new(&oval_template);
+        let location = GridLocation::new(BallotSide::Front, 0, 0);
+
+        let scored_oval_mark = score_oval_mark(
+            &img,
+            &binarized,
+            &binary_template,
+            Point::new(22.0, 15.0),
+            &location,
+            DEFAULT_MAXIMUM_SEARCH_DISTANCE,
+        );
+        let scored_oval_mark = scored_oval_mark.scored().unwrap();
+        assert_eq!(scored_oval_mark.matched_bounds.left(), 0);
+        assert_eq!(scored_oval_mark.matched_bounds.top(), 0);
+
+        for center in [
+            Point::new(500.0, 500.0),
+            Point::new(-500.0, 20.0),
+            Point::new(58.0, 38.0),
+            Point::new(f32::NAN, f32::INFINITY),
+            Point::new(f32::NAN, 20.0),
+            Point::new(20.0, f32::NEG_INFINITY),
+            Point::new(f32::MAX, 20.0),
+            Point::new(20.0, -f32::MAX),
+            Point::new(i32::MAX as f32, i32::MIN as f32),
+        ] {
+            let oval_mark = score_oval_mark(
+                &img,
+                &binarized,
+                &binary_template,
+                center,
+                &location,
+                DEFAULT_MAXIMUM_SEARCH_DISTANCE,
+            );
+            assert!(
+                matches!(oval_mark, OvalMark::OutOfBounds { .. }),
+                "{center:?}: {oval_mark:?}"
+            );
+        }
+    }
+
+    /// Checks that scoring every oval on a full legal-size page is at least an
+    /// order of magnitude faster than the image diff approach. Timings are
+    /// only meaningful in release builds, so this is ignored otherwise. Run
+    /// with:
+    ///
+    /// ```sh
+    /// cargo test --release -- --nocapture bench_score_oval_marks
+    /// ```
+    /// Renders a blank ballot card for an election with the given
+    /// `timingMarkLayout` and grid size, and finds the timing mark grid on
+    /// each side of it.
+    fn find_synthetic_timing_mark_grids(
+        timing_mark_layout: &str,
+        columns: u32,
+        rows: u32,
+    ) -> (TimingMarkGrid, TimingMarkGrid) {
+        let election: Election = serde_json::from_str(&format!(
+            r#"{{
+                "title": "Test",
+                "timingMarkLayout": {timing_mark_layout},
+                "gridSize": {{ "width": {columns}, "height": {rows} }},
+                "gridLayouts": [{{
+                    "precinctId": "precinct-1",
+                    "ballotStyleId": "card-number-3",
+                    "columns": {columns},
+                    "rows": {rows},
+                    "gridPositions": []
+                }}]
+            }}"#
+        ))
+        .unwrap();
+        let grid_layout = &election.grid_layouts[0];
+        let target_templates = TargetTemplates::from_election(
+            &election,
+            &load_oval_template().unwrap(),
+            Path::new("."),
+        )
+        .unwrap();
+        let card = render_ballot_card(
+            &election,
+            grid_layout,
+            &target_templates,
+            &FrontMetadataValues {
+                batch_or_precinct_number: 1,
+                card_number: 3,
+            },
+            &BackMetadataValues::default(),
+            &[],
+        )
+        .unwrap();
+        let geometry = geometry_for_grid_layout(&election, grid_layout).unwrap();
+        let find_grid = |img: &GrayImage| {
+            find_timing_mark_grid(
+                Path::new("synthetic.png"),
+                &geometry,
+                img,
+                BinarizationMethod::Otsu,
+                &ImageDebugWriter::disabled(),
+            )
+            .unwrap()
+        };
+        (find_grid(&card.front), find_grid(&card.back))
+    }
+
+    /// Checks that the grids found on a synthetic card decode the metadata
+    /// they were rendered with and put grid locations within a few pixels of
+    /// where they were rendered, well inside the oval search distance.
+    fn assert_synthetic_grid(front: &TimingMarkGrid, back: &TimingMarkGrid) {
+        assert!(matches!(
+            &front.metadata,
+            Some(BallotPageMetadata::Front(metadata)) if metadata.card_number == 3
+        ));
+        assert!(matches!(
+            &back.metadata,
+            Some(BallotPageMetadata::Back(metadata)) if metadata.election_type.to_char() == 'G'
+        ));
+
+        let grid_size = front.geometry.grid_size;
+        for (column, row) in [
+            (1, 1),
+            (grid_size.width - 2, 1),
+            (grid_size.width / 2, grid_size.height / 2),
+            (1, grid_size.height - 2),
+            (grid_size.width - 2, grid_size.height - 2),
+        ] {
+            let expected = grid_location_center(&front.geometry, column, row);
+            let found = front.point_for_location(column, row).unwrap();
+            assert!(
+                Segment::new(expected, found).length() < 4.0,
+                "({column}, {row}): expected {expected:?}, found {found:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_grid_with_timing_marks_on_left_and_right_only() {
+        let (front, back) = find_synthetic_timing_mark_grids(
+            r#"{ "top": false, "bottom": false, "metadataEdge": "right" }"#,
+            34,
+            41,
+        );
+        assert_synthetic_grid(&front, &back);
+    }
+
+    #[test]
+    fn test_find_grid_with_metadata_on_top_edge() {
+        let (front, back) =
+            find_synthetic_timing_mark_grids(r#"{ "metadataEdge": "top" }"#, 34, 41);
+        assert_synthetic_grid(&front, &back);
+    }
+
+    #[test]
+    fn test_find_grid_with_more_columns() {
+        let (front, back) = find_synthetic_timing_mark_grids("{}", 40, 41);
+        assert_eq!(front.geometry.grid_size.width, 40);
+        assert_eq!(front.complete_timing_marks.bottom_marks.len(), 40);
+        assert_synthetic_grid(&front, &back);
+    }
+
+    /// Compares the time to score every oval on a legal page by bitset with
+    /// the time to score them by image diff. Timings are only meaningful with
+    /// optimizations and nothing else running, so this doesn't run by
+    /// default. Run it with:
+    ///
+    /// ```sh
+    /// LOG=info cargo test --release -- --ignored bench_score_oval_marks_legal_page
+    /// ```
+    #[test]
+    #[ignore]
+    fn bench_score_oval_marks_legal_page() {
+        let _ = pretty_env_logger::try_init_custom_env("LOG");
+        let geometry = get_scanned_ballot_card_geometry_8pt5x14();
+        let oval_template = load_oval_template().unwrap();
+        let (img, centers) = synthetic_ballot_page(&geometry, &oval_template);
+        let threshold = otsu_level(&img);
+        let location = GridLocation::new(BallotSide::Front, 0, 0);
+
+        // take the fastest of a few runs to reduce noise from other tests
+        let fastest = |score_page: &dyn Fn()| {
+            (0..3)
+                .map(|_| {
+                    let start = std::time::Instant::now();
+                    score_page();
+                    start.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+
+        let image_diff_duration = fastest(&|| {
+            for center in &centers {
+                score_oval_mark_by_image_diff(
+                    &img,
+                    &oval_template,
+                    *center,
+                    DEFAULT_MAXIMUM_SEARCH_DISTANCE,
+                    threshold,
+                );
+            }
+        });
+
+        let bitset_duration = fastest(&|| {
+            let binarized_img = imageproc::contrast::threshold(&img, threshold);
+            let binarized = BitImage::from_gray_image(&binarized_img, 127);
+            let binary_template = BinaryTemplate::new(&oval_template);
+            for center in &centers {
+                score_oval_mark(
+                    &img,
+                    &binarized,
+                    &binary_template,
+                    *center,
+                    &location,
+                    DEFAULT_MAXIMUM_SEARCH_DISTANCE,
+                );
+            }
+        });
+
+        log::info!(
+            "scored {} ovals: image diff {:?}, bitset {:?} ({:.1}x faster)",
+            centers.len(),
+            image_diff_duration,
+            bitset_duration,
+            image_diff_duration.as_secs_f64() / bitset_duration.as_secs_f64()
+        );
+    }
 }
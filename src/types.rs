@@ -23,8 +23,6 @@ pub(crate) use idtype;
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn test_idtype() {
         idtype!(Foo);
@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    ballot_card::BorderEdge,
+    election::{ContestId, Election, GridLayout, GridLocation, GridPosition, OptionId},
+    geometry::{Rect, Size},
+};
+
+/// A problem found in an election definition.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationProblem {
+    /// Where the problem is, as a JSONPath into the election definition.
+    pub path: String,
+
+    #[serde(flatten)]
+    pub kind: ValidationProblemKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ValidationProblemKind {
+    /// The layout's columns and rows don't match the grid of any ballot
+    /// geometry the election may use.
+    UnsupportedLayoutDimensions { columns: u32, rows: u32 },
+
+    /// Another grid position in the same layout has the same location.
+    #[serde(rename_all = "camelCase")]
+    DuplicateGridPosition {
+        location: GridLocation,
+        other_path: String,
+    },
+
+    /// The grid position is beyond the layout's columns or rows.
+    GridPositionOutsideLayout {
+        location: GridLocation,
+        columns: u32,
+        rows: u32,
+    },
+
+    /// The grid position is on a row or column of timing marks.
+    GridPositionOnTimingMarks { location: GridLocation },
+
+    /// The grid position is outside the usable area of its side.
+    #[serde(rename_all = "camelCase")]
+    GridPositionOutsideUsableArea {
+        location: GridLocation,
+        usable_area: Rect,
+    },
+
+    /// Another grid position in the same layout is for the same option.
+    #[serde(rename_all = "camelCase")]
+    DuplicateOption {
+        contest_id: ContestId,
+        option_id: OptionId,
+        other_path: String,
+    },
+
+    /// Another grid position in the same layout is for the same write-in.
+    #[serde(rename_all = "camelCase")]
+    DuplicateWriteIn {
+        contest_id: ContestId,
+        write_in_index: u32,
+        other_path: String,
+    },
+
+    /// The target shape isn't one of the election's target shapes.
+    UnknownTargetShape { name: String },
+
+    /// The timing mark layout reads the metadata from an edge that has no
+    /// timing marks, so no page could be read.
+    MetadataEdgeWithoutTimingMarks { edge: BorderEdge },
+
+    /// A physical size in the geometry isn't a positive, finite number of
+    /// inches.
+    NonPositiveSize { size: Size<f32> },
+
+    /// The grid has fewer than two timing mark columns or rows, so there is
+    /// no grid to find.
+    #[serde(rename_all = "camelCase")]
+    GridSizeTooSmall { grid_size: Size<u32> },
+}
+
+/// Checks an election definition for mistakes that would otherwise only show
+/// up as odd interpretation results.
+pub fn validate_election(election: &Election) -> Vec<ValidationProblem> {
+    let mut problems = vec![];
+
+    if let Some(geometry) = &election.geometry {
+        for (name, size) in [
+            ("paperSize", geometry.paper_size),
+            ("timingMarkSize", geometry.timing_mark_size),
+            ("ovalSize", geometry.oval_size),
+        ] {
+            let is_positive = |inches: f32| inches.is_finite() && inches > 0.0;
+            if !is_positive(size.width) || !is_positive(size.height) {
+                problems.push(ValidationProblem {
+                    path: format!("$.geometry.{name}"),
+                    kind: ValidationProblemKind::NonPositiveSize { size },
+                });
+            }
+        }
+        validate_grid_size(geometry.grid_size, "$.geometry.gridSize", &mut problems);
+    }
+
+    if let Some(grid_size) = election.grid_size {
+        validate_grid_size(grid_size, "$.gridSize", &mut problems);
+    }
+
+    if let Some(layout) = &election.timing_mark_layout {
+        if let Some(edge) = layout.metadata_edge {
+            if !layout.has_timing_marks(edge) {
+                problems.push(ValidationProblem {
+                    path: "$.timingMarkLayout.metadataEdge".to_string(),
+                    kind: ValidationProblemKind::MetadataEdgeWithoutTimingMarks { edge },
+                });
+            }
+        }
+    }
+
+    if let Some(name) = &election.default_target_shape {
+        if !election.target_shapes.contains_key(name) {
+            problems.push(ValidationProblem {
+                path: "$.defaultTargetShape".to_string(),
+                kind: ValidationProblemKind::UnknownTargetShape { name: name.clone() },
+            });
+        }
+    }
+
+    for (index, grid_layout) in election.grid_layouts.iter().enumerate() {
+        validate_grid_layout(
+            election,
+            grid_layout,
+            &format!("$.gridLayouts[{index}]"),
+            &mut problems,
+        );
+    }
+
+    problems
+}
+
+/// Checks that a grid has at least two timing mark columns and rows.
+fn validate_grid_size(grid_size: Size<u32>, path: &str, problems: &mut Vec<ValidationProblem>) {
+    if grid_size.width < 2 || grid_size.height < 2 {
+        problems.push(ValidationProblem {
+            path: path.to_string(),
+            kind: ValidationProblemKind::GridSizeTooSmall { grid_size },
+        });
+    }
+}
+
+/// Checks a grid layout and the grid positions in it.
+fn validate_grid_layout(
+    election: &Election,
+    grid_layout: &GridLayout,
+    path: &str,
+    problems: &mut Vec<ValidationProblem>,
+) {
+    let (columns, rows) = (grid_layout.columns, grid_layout.rows);
+    let geometry = election
+        .geometries()
+        .into_iter()
+        .find(|geometry| geometry.grid_size.width == columns && geometry.grid_size.height == rows);
+    if geometry.is_none() {
+        problems.push(ValidationProblem {
+            path: path.to_string(),
+            kind: ValidationProblemKind::UnsupportedLayoutDimensions { columns, rows },
+        });
+    }
+    let timing_mark_layout = election.timing_mark_layout.unwrap_or_default();
+
+    let mut paths_by_location = HashMap::new();
+    let mut paths_by_option = HashMap::new();
+    let mut paths_by_write_in = HashMap::new();
+
+    for (index, grid_position) in grid_layout.grid_positions.iter().enumerate() {
+        let path = format!("{path}.gridPositions[{index}]");
+        let location = grid_position.location();
+        let mut problem = |kind| {
+            problems.push(ValidationProblem {
+                path: path.clone(),
+                kind,
+            });
+        };
+
+        if let Some(other_path) = paths_by_location.insert(location, path.clone()) {
+            problem(ValidationProblemKind::DuplicateGridPosition {
+                location,
+                other_path,
+            });
+        }
+
+        match grid_position {
+            GridPosition::Option {
+                contest_id,
+                option_id,
+                ..
+            } => {
+                if let Some(other_path) =
+                    paths_by_option.insert((contest_id.clone(), option_id.clone()), path.clone())
+                {
+                    problem(ValidationProblemKind::DuplicateOption {
+                        contest_id: contest_id.clone(),
+                        option_id: option_id.clone(),
+                        other_path,
+                    });
+                }
+            }
+            GridPosition::WriteIn {
+                contest_id,
+                write_in_index,
+                ..
+            } => {
+                if let Some(other_path) =
+                    paths_by_write_in.insert((contest_id.clone(), *write_in_index), path.clone())
+                {
+                    problem(ValidationProblemKind::DuplicateWriteIn {
+                        contest_id: contest_id.clone(),
+                        write_in_index: *write_in_index,
+                        other_path,
+                    });
+                }
+            }
+        }
+
+        if location.column >= columns || location.row >= rows {
+            problem(ValidationProblemKind::GridPositionOutsideLayout {
+                location,
+                columns,
+                rows,
+            });
+        } else if (timing_mark_layout.left && location.column == 0)
+            || (timing_mark_layout.right && location.column == columns - 1)
+            || (timing_mark_layout.top && location.row == 0)
+            || (timing_mark_layout.bottom && location.row == rows - 1)
+        {
+            problem(ValidationProblemKind::GridPositionOnTimingMarks { location });
+        } else if let Some(geometry) = &geometry {
+            let usable_area = geometry.usable_area(location.side);
+            if !usable_area.contains(location.column as i32, location.row as i32) {
+                problem(ValidationProblemKind::GridPositionOutsideUsableArea {
+                    location,
+                    usable_area,
+                });
+            }
+        }
+
+        if let Some(name) = grid_position.target_shape() {
+            if !election.target_shapes.contains_key(name) {
+                problem(ValidationProblemKind::UnknownTargetShape {
+                    name: name.to_string(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ballot_card::BallotSide;
+
+    fn election(grid_positions: &str) -> Election {
+        serde_json::from_str(&format!(
+            r#"{{
+                "title": "Test",
+                "gridLayouts": [
+                    {{
+                        "precinctId": "precinct-1",
+                        "ballotStyleId": "card-number-1",
+                        "columns": 34,
+                        "rows": 41,
+                        "gridPositions": [{grid_positions}]
+                    }}
+                ]
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    fn option(column: u32, row: u32, option_id: &str) -> String {
+        format!(
+            r#"{{
+                "type": "option",
+                "side": "front",
+                "column": {column},
+                "row": {row},
+                "contestId": "mayor",
+                "optionId": "{option_id}"
+            }}"#
+        )
+    }
+
+    #[test]
+    fn test_valid_election() {
+        let election = election(&[option(5, 10, "alice"), option(5, 12, "bob")].join(","));
+        assert_eq!(validate_election(&election), vec![]);
+    }
+
+    #[test]
+    fn test_duplicate_grid_positions() {
+        let election = election(&[option(5, 10, "alice"), option(5, 10, "alice")].join(","));
+        assert_eq!(
+            validate_election(&election),
+            vec![
+                ValidationProblem {
+                    path: "$.gridLayouts[0].gridPositions[1]".to_string(),
+                    kind: ValidationProblemKind::DuplicateGridPosition {
+                        location: GridLocation::new(BallotSide::Front, 5, 10),
+                        other_path: "$.gridLayouts[0].gridPositions[0]".to_string(),
+                    },
+                },
+                ValidationProblem {
+                    path: "$.gridLayouts[0].gridPositions[1]".to_string(),
+                    kind: ValidationProblemKind::DuplicateOption {
+                        contest_id: ContestId::from("mayor".to_string()),
+                        option_id: OptionId::from("alice".to_string()),
+                        other_path: "$.gridLayouts[0].gridPositions[0]".to_string(),
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grid_positions_out_of_place() {
+        let election = election(
+            &[
+                option(34, 10, "alice"),
+                option(0, 10, "bob"),
+                option(5, 40, "carol"),
+            ]
+            .join(","),
+        );
+        let problems = validate_election(&election);
+        assert_eq!(problems.len(), 3);
+        assert!(matches!(
+            problems[0].kind,
+            ValidationProblemKind::GridPositionOutsideLayout { .. }
+        ));
+        assert!(matches!(
+            problems[1].kind,
+            ValidationProblemKind::GridPositionOnTimingMarks { .. }
+        ));
+        assert!(matches!(
+            problems[2].kind,
+            ValidationProblemKind::GridPositionOnTimingMarks { .. }
+        ));
+    }
+
+    #[test]
+    fn test_grid_position_outside_usable_area() {
+        let mut election = election(&[option(5, 10, "alice"), option(5, 30, "bob")].join(","));
+        election.geometry = Some(
+            serde_json::from_str(
+                r#"{
+                    "paperSize": { "width": 8.5, "height": 11 },
+                    "timingMarkSize": { "width": 0.1875, "height": 0.0625 },
+                    "ovalSize": { "width": 0.2, "height": 0.13 },
+                    "gridSize": { "width": 34, "height": 41 },
+                    "frontUsableArea": { "left": 0, "top": 0, "width": 34, "height": 20 }
+                }"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            validate_election(&election),
+            vec![ValidationProblem {
+                path: "$.gridLayouts[0].gridPositions[1]".to_string(),
+                kind: ValidationProblemKind::GridPositionOutsideUsableArea {
+                    location: GridLocation::new(BallotSide::Front, 5, 30),
+                    usable_area: Rect::new(0, 0, 34, 20),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_invalid_geometry() {
+        let mut election = election(&option(5, 10, "alice"));
+        election.geometry = Some(
+            serde_json::from_str(
+                r#"{
+                    "paperSize": { "width": 0, "height": 0 },
+                    "timingMarkSize": { "width": 0.1875, "height": 0.0625 },
+                    "ovalSize": { "width": 0.2, "height": -0.13 },
+                    "gridSize": { "width": 34, "height": 1 }
+                }"#,
+            )
+            .unwrap(),
+        );
+        let problems = validate_election(&election);
+        assert_eq!(
+            problems[..3],
+            [
+                ValidationProblem {
+                    path: "$.geometry.paperSize".to_string(),
+                    kind: ValidationProblemKind::NonPositiveSize {
+                        size: Size {
+                            width: 0.0,
+                            height: 0.0
+                        }
+                    },
+                },
+                ValidationProblem {
+                    path: "$.geometry.ovalSize".to_string(),
+                    kind: ValidationProblemKind::NonPositiveSize {
+                        size: Size {
+                            width: 0.2,
+                            height: -0.13
+                        }
+                    },
+                },
+                ValidationProblem {
+                    path: "$.geometry.gridSize".to_string(),
+                    kind: ValidationProblemKind::GridSizeTooSmall {
+                        grid_size: Size {
+                            width: 34,
+                            height: 1
+                        }
+                    },
+                },
+            ]
+        );
+
+        election.geometry = None;
+        election.grid_size = Some(Size {
+            width: 1,
+            height: 41,
+        });
+        assert!(validate_election(&election).contains(&ValidationProblem {
+            path: "$.gridSize".to_string(),
+            kind: ValidationProblemKind::GridSizeTooSmall {
+                grid_size: Size {
+                    width: 1,
+                    height: 41
+                }
+            },
+        }));
+    }
+
+    #[test]
+    fn test_unsupported_layout_dimensions() {
+        let mut election = election(&option(5, 10, "alice"));
+        election.grid_layouts[0].rows = 50;
+        assert_eq!(
+            validate_election(&election),
+            vec![ValidationProblem {
+                path: "$.gridLayouts[0]".to_string(),
+                kind: ValidationProblemKind::UnsupportedLayoutDimensions {
+                    columns: 34,
+                    rows: 50
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unknown_target_shape() {
+        let mut election = election(&option(5, 10, "alice"));
+        election.default_target_shape = Some("square".to_string());
+        assert_eq!(
+            validate_election(&election),
+            vec![ValidationProblem {
+                path: "$.defaultTargetShape".to_string(),
+                kind: ValidationProblemKind::UnknownTargetShape {
+                    name: "square".to_string()
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_metadata_edge_without_timing_marks() {
+        let mut election = election(&option(5, 10, "alice"));
+        election.timing_mark_layout = Some(
+            serde_json::from_str(r#"{"top": false, "bottom": false, "metadataEdge": "bottom"}"#)
+                .unwrap(),
+        );
+        assert_eq!(
+            validate_election(&election),
+            vec![ValidationProblem {
+                path: "$.timingMarkLayout.metadataEdge".to_string(),
+                kind: ValidationProblemKind::MetadataEdgeWithoutTimingMarks {
+                    edge: BorderEdge::Bottom
+                },
+            }]
+        );
+
+        election.timing_mark_layout = Some(
+            serde_json::from_str(r#"{"top": false, "bottom": false, "metadataEdge": "right"}"#)
+                .unwrap(),
+        );
+        assert_eq!(validate_election(&election), vec![]);
+    }
+
+    #[test]
+    fn test_serialize_problem() {
+        let problem = ValidationProblem {
+            path: "$.gridLayouts[0]".to_string(),
+            kind: ValidationProblemKind::UnsupportedLayoutDimensions {
+                columns: 34,
+                rows: 50,
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&problem).unwrap(),
+            serde_json::json!({
+                "path": "$.gridLayouts[0]",
+                "type": "unsupportedLayoutDimensions",
+                "columns": 34,
+                "rows": 50
+            })
+        );
+    }
+}